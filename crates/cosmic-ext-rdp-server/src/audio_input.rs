@@ -0,0 +1,472 @@
+//! Microphone redirection over the `AUDIO_INPUT` dynamic channel (MS-RDPEAI).
+//!
+//! The client captures its local microphone and streams PCM to the server.
+//! Received audio is played into a `PipeWire` virtual source
+//! ("RDP Microphone") so applications inside the session can record it.
+//!
+//! # Message flow
+//!
+//! ```text
+//! server                         client
+//!   | -- Version ----------------> |
+//!   | <--------------- Version --- |
+//!   | -- Formats (offered) ------> |
+//!   | <--- Formats (supported) --- |
+//!   | -- Open (format index) ----> |
+//!   | <----------- FormatChange -- |
+//!   | <------------- OpenReply --- |
+//!   | <-- DataIncoming, Data ... - |
+//! ```
+
+use ironrdp_core::{impl_as_any, Encode, WriteCursor};
+use ironrdp_dvc::{DvcEncode, DvcMessage, DvcProcessor, DvcProcessorFactory, DvcServerProcessor};
+use ironrdp_pdu::PduResult;
+use rdp_capture::PwVirtualSource;
+
+/// Dynamic virtual channel name for audio input redirection.
+const CHANNEL_NAME: &str = "AUDIO_INPUT";
+
+/// Name of the virtual source shown in sound settings.
+const SOURCE_DESCRIPTION: &str = "RDP Microphone";
+
+/// Protocol version spoken by the server.
+const SNDIN_VERSION: u32 = 1;
+
+const MSG_SNDIN_VERSION: u8 = 0x01;
+const MSG_SNDIN_FORMATS: u8 = 0x02;
+const MSG_SNDIN_OPEN: u8 = 0x03;
+const MSG_SNDIN_OPEN_REPLY: u8 = 0x04;
+const MSG_SNDIN_DATA_INCOMING: u8 = 0x05;
+const MSG_SNDIN_DATA: u8 = 0x06;
+const MSG_SNDIN_FORMATCHANGE: u8 = 0x07;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+
+/// Capture formats offered to the client, in order of preference.
+///
+/// Only 16-bit PCM is offered so data can be fed to `PipeWire` as-is.
+const OFFERED_FORMATS: &[(u16, u32)] =
+    &[(2, 48000), (2, 44100), (1, 44100), (2, 22050), (1, 16000)];
+
+/// Packet duration requested from the client, in milliseconds.
+const PACKET_MS: u32 = 20;
+
+// ---------------------------------------------------------------------------
+// PDUs
+// ---------------------------------------------------------------------------
+
+/// An `AUDIO_FORMAT` structure (a `WAVEFORMATEX` without trailing data).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SndinFormat {
+    format_tag: u16,
+    channels: u16,
+    samples_per_sec: u32,
+    avg_bytes_per_sec: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+    extra: Vec<u8>,
+}
+
+impl SndinFormat {
+    /// 16-bit PCM at the given channel count and rate.
+    fn pcm(channels: u16, samples_per_sec: u32) -> Self {
+        let block_align = channels * 2;
+        Self {
+            format_tag: WAVE_FORMAT_PCM,
+            channels,
+            samples_per_sec,
+            avg_bytes_per_sec: samples_per_sec * u32::from(block_align),
+            block_align,
+            bits_per_sample: 16,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Whether this is 16-bit PCM, the only format we can play directly.
+    fn is_pcm16(&self) -> bool {
+        self.format_tag == WAVE_FORMAT_PCM && self.bits_per_sample == 16 && self.channels > 0
+    }
+
+    fn encoded_len(&self) -> usize {
+        18 + self.extra.len()
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.format_tag.to_le_bytes());
+        out.extend_from_slice(&self.channels.to_le_bytes());
+        out.extend_from_slice(&self.samples_per_sec.to_le_bytes());
+        out.extend_from_slice(&self.avg_bytes_per_sec.to_le_bytes());
+        out.extend_from_slice(&self.block_align.to_le_bytes());
+        out.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        out.extend_from_slice(&(self.extra.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.extra);
+    }
+
+    fn read(cur: &mut Reader<'_>) -> Option<Self> {
+        let format_tag = cur.u16()?;
+        let channels = cur.u16()?;
+        let samples_per_sec = cur.u32()?;
+        let avg_bytes_per_sec = cur.u32()?;
+        let block_align = cur.u16()?;
+        let bits_per_sample = cur.u16()?;
+        let cb_size = cur.u16()?;
+        let extra = cur.bytes(usize::from(cb_size))?.to_vec();
+        Some(Self {
+            format_tag,
+            channels,
+            samples_per_sec,
+            avg_bytes_per_sec,
+            block_align,
+            bits_per_sample,
+            extra,
+        })
+    }
+}
+
+/// Minimal little-endian reader for client PDUs.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Some(head)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Client-to-server messages.
+#[derive(Debug, PartialEq, Eq)]
+enum ClientPdu {
+    Version(u32),
+    Formats(Vec<SndinFormat>),
+    OpenReply(u32),
+    DataIncoming,
+    Data(Vec<u8>),
+    FormatChange(u32),
+}
+
+/// Decode a client PDU. Returns `None` for malformed or unknown messages.
+fn decode_client_pdu(payload: &[u8]) -> Option<ClientPdu> {
+    let (&id, body) = payload.split_first()?;
+    let mut cur = Reader { buf: body };
+    match id {
+        MSG_SNDIN_VERSION => cur.u32().map(ClientPdu::Version),
+        MSG_SNDIN_FORMATS => {
+            let count = cur.u32()?;
+            let _cb_size_formats_packet = cur.u32()?;
+            let formats = (0..count)
+                .map(|_| SndinFormat::read(&mut cur))
+                .collect::<Option<Vec<_>>>()?;
+            Some(ClientPdu::Formats(formats))
+        }
+        MSG_SNDIN_OPEN_REPLY => cur.u32().map(ClientPdu::OpenReply),
+        MSG_SNDIN_DATA_INCOMING => Some(ClientPdu::DataIncoming),
+        MSG_SNDIN_DATA => Some(ClientPdu::Data(body.to_vec())),
+        MSG_SNDIN_FORMATCHANGE => cur.u32().map(ClientPdu::FormatChange),
+        _ => None,
+    }
+}
+
+fn encode_version() -> Vec<u8> {
+    let mut out = vec![MSG_SNDIN_VERSION];
+    out.extend_from_slice(&SNDIN_VERSION.to_le_bytes());
+    out
+}
+
+fn encode_formats(formats: &[SndinFormat]) -> Vec<u8> {
+    let size = 9 + formats.iter().map(SndinFormat::encoded_len).sum::<usize>();
+    let mut out = Vec::with_capacity(size);
+    out.push(MSG_SNDIN_FORMATS);
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        out.extend_from_slice(&(formats.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(size as u32).to_le_bytes());
+    }
+    for format in formats {
+        format.write(&mut out);
+    }
+    out
+}
+
+fn encode_open(frames_per_packet: u32, initial_format: u32, format: &SndinFormat) -> Vec<u8> {
+    let mut out = vec![MSG_SNDIN_OPEN];
+    out.extend_from_slice(&frames_per_packet.to_le_bytes());
+    out.extend_from_slice(&initial_format.to_le_bytes());
+    format.write(&mut out);
+    out
+}
+
+/// Raw `AUDIO_INPUT` message ready for the DVC layer.
+struct SndinMessage {
+    data: Vec<u8>,
+}
+
+impl Encode for SndinMessage {
+    fn encode(&self, dst: &mut WriteCursor<'_>) -> ironrdp_core::EncodeResult<()> {
+        dst.write_slice(&self.data);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "SndinMessage"
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl DvcEncode for SndinMessage {}
+
+fn message(data: Vec<u8>) -> DvcMessage {
+    Box::new(SndinMessage { data })
+}
+
+// ---------------------------------------------------------------------------
+// Processor (one per RDP connection)
+// ---------------------------------------------------------------------------
+
+/// Server side of the `AUDIO_INPUT` channel.
+///
+/// Negotiates a 16-bit PCM format with the client and plays received
+/// audio into a `PipeWire` virtual source, created once the client has
+/// confirmed the capture device is open.
+#[derive(Debug, Default)]
+pub struct AudioInputProcessor {
+    /// Formats the client reported as supported.
+    client_formats: Vec<SndinFormat>,
+    /// Format currently used by the client.
+    format: Option<SndinFormat>,
+    /// Virtual source fed with received audio.
+    source: Option<PwVirtualSource>,
+}
+
+impl_as_any!(AudioInputProcessor);
+
+impl AudioInputProcessor {
+    /// Handle the client's supported formats and open the device with
+    /// the first usable one.
+    fn on_formats(&mut self, formats: Vec<SndinFormat>) -> Vec<DvcMessage> {
+        tracing::debug!(
+            count = formats.len(),
+            "AUDIO_INPUT: client formats received"
+        );
+        self.client_formats = formats;
+
+        let Some((index, format)) = self
+            .client_formats
+            .iter()
+            .enumerate()
+            .find(|(_, f)| f.is_pcm16())
+        else {
+            tracing::warn!(
+                "AUDIO_INPUT: client supports no 16-bit PCM format, microphone disabled"
+            );
+            return Vec::new();
+        };
+
+        let frames_per_packet = format.samples_per_sec * PACKET_MS / 1000;
+        tracing::info!(
+            channels = format.channels,
+            sample_rate = format.samples_per_sec,
+            frames_per_packet,
+            "AUDIO_INPUT: opening client microphone"
+        );
+        self.format = Some(format.clone());
+        #[allow(clippy::cast_possible_truncation)]
+        let index = index as u32;
+        vec![message(encode_open(frames_per_packet, index, format))]
+    }
+
+    /// Switch to a new format from the client's list.
+    fn on_format_change(&mut self, index: u32) {
+        let Some(format) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.client_formats.get(i))
+        else {
+            tracing::warn!(index, "AUDIO_INPUT: format change to unknown index");
+            return;
+        };
+        if !format.is_pcm16() {
+            tracing::warn!(index, "AUDIO_INPUT: client switched to unsupported format");
+            self.format = None;
+            self.source = None;
+            return;
+        }
+        if self.format.as_ref() != Some(format) {
+            tracing::info!(
+                channels = format.channels,
+                sample_rate = format.samples_per_sec,
+                "AUDIO_INPUT: format changed"
+            );
+            self.format = Some(format.clone());
+            // Recreate the source with the new format on next data.
+            self.source = None;
+        }
+    }
+
+    /// Create the virtual source for the active format.
+    fn ensure_source(&mut self) {
+        if self.source.is_some() {
+            return;
+        }
+        let Some(format) = &self.format else {
+            return;
+        };
+        match PwVirtualSource::start(SOURCE_DESCRIPTION, format.channels, format.samples_per_sec) {
+            Ok(source) => self.source = Some(source),
+            Err(e) => tracing::warn!("AUDIO_INPUT: failed to create virtual source: {e}"),
+        }
+    }
+}
+
+impl DvcProcessor for AudioInputProcessor {
+    #[allow(clippy::unnecessary_literal_bound)]
+    fn channel_name(&self) -> &str {
+        CHANNEL_NAME
+    }
+
+    fn start(&mut self, channel_id: u32) -> PduResult<Vec<DvcMessage>> {
+        tracing::info!(channel_id, "AUDIO_INPUT: DVC channel opened");
+        Ok(vec![message(encode_version())])
+    }
+
+    fn close(&mut self, channel_id: u32) {
+        tracing::info!(channel_id, "AUDIO_INPUT: DVC channel closed");
+        self.source = None;
+        self.format = None;
+        self.client_formats.clear();
+    }
+
+    fn process(&mut self, _channel_id: u32, payload: &[u8]) -> PduResult<Vec<DvcMessage>> {
+        let Some(pdu) = decode_client_pdu(payload) else {
+            tracing::warn!(
+                first_bytes = ?&payload[..payload.len().min(16)],
+                "AUDIO_INPUT: ignoring malformed or unknown PDU"
+            );
+            return Ok(Vec::new());
+        };
+
+        let responses = match pdu {
+            ClientPdu::Version(version) => {
+                tracing::debug!(version, "AUDIO_INPUT: client version");
+                let offered: Vec<SndinFormat> = OFFERED_FORMATS
+                    .iter()
+                    .map(|&(channels, rate)| SndinFormat::pcm(channels, rate))
+                    .collect();
+                vec![message(encode_formats(&offered))]
+            }
+            ClientPdu::Formats(formats) => self.on_formats(formats),
+            ClientPdu::FormatChange(index) => {
+                self.on_format_change(index);
+                Vec::new()
+            }
+            ClientPdu::OpenReply(result) => {
+                if result == 0 {
+                    self.ensure_source();
+                } else {
+                    tracing::warn!(
+                        result = format_args!("{result:#010x}"),
+                        "AUDIO_INPUT: client failed to open microphone"
+                    );
+                }
+                Vec::new()
+            }
+            ClientPdu::DataIncoming => Vec::new(),
+            ClientPdu::Data(pcm) => {
+                self.ensure_source();
+                if let Some(source) = &self.source {
+                    source.push(&pcm);
+                }
+                Vec::new()
+            }
+        };
+
+        Ok(responses)
+    }
+}
+
+impl DvcServerProcessor for AudioInputProcessor {}
+
+// ---------------------------------------------------------------------------
+// Factory
+// ---------------------------------------------------------------------------
+
+/// Factory that creates a fresh [`AudioInputProcessor`] per RDP connection.
+#[derive(Debug, Default)]
+pub struct AudioInputFactory;
+
+impl DvcProcessorFactory for AudioInputFactory {
+    fn build(&self) -> Box<dyn DvcProcessor> {
+        Box::new(AudioInputProcessor::default())
+    }
+
+    #[allow(clippy::unnecessary_literal_bound)]
+    fn channel_name(&self) -> &str {
+        CHANNEL_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_roundtrip() {
+        let offered = vec![SndinFormat::pcm(2, 44100), SndinFormat::pcm(1, 16000)];
+        let encoded = encode_formats(&offered);
+        assert_eq!(
+            decode_client_pdu(&encoded),
+            Some(ClientPdu::Formats(offered))
+        );
+    }
+
+    #[test]
+    fn pcm_format_fields() {
+        let f = SndinFormat::pcm(2, 48000);
+        assert_eq!(f.block_align, 4);
+        assert_eq!(f.avg_bytes_per_sec, 192_000);
+        assert!(f.is_pcm16());
+    }
+
+    #[test]
+    fn open_picks_first_pcm16_client_format() {
+        let mut aac = SndinFormat::pcm(2, 44100);
+        aac.format_tag = 0x1610;
+        let mut processor = AudioInputProcessor::default();
+        let out = processor.on_formats(vec![aac, SndinFormat::pcm(1, 16000)]);
+        assert_eq!(out.len(), 1);
+        let format = processor.format.expect("format selected");
+        assert_eq!(format.channels, 1);
+        assert_eq!(format.samples_per_sec, 16000);
+    }
+
+    #[test]
+    fn truncated_pdu_is_rejected() {
+        assert_eq!(decode_client_pdu(&[MSG_SNDIN_VERSION, 1, 0]), None);
+        assert_eq!(decode_client_pdu(&[]), None);
+    }
+
+    #[test]
+    fn data_pdu_strips_header() {
+        assert_eq!(
+            decode_client_pdu(&[MSG_SNDIN_DATA, 1, 2, 3]),
+            Some(ClientPdu::Data(vec![1, 2, 3]))
+        );
+    }
+}
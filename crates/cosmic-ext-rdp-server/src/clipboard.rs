@@ -6,7 +6,12 @@
 //! negotiate the CLIPRDR virtual channel automatically.
 //!
 //! Only plain-text clipboard (`CF_UNICODETEXT` / `CF_TEXT`) is supported.
+//!
+//! The X11/Wayland PRIMARY selection (middle-click paste) can optionally
+//! be kept in sync as well: remote copies can be mirrored into PRIMARY,
+//! and the local PRIMARY selection can be offered to the remote client.
 
+use arboard::{GetExtLinux, LinuxClipboardKind, SetExtLinux};
 use ironrdp_cliprdr::backend::{
     CliprdrBackend, CliprdrBackendFactory, ClipboardMessage,
 };
@@ -33,14 +38,57 @@ pub struct LocalClipboardBackend {
     event_tx: mpsc::UnboundedSender<ServerEvent>,
     /// Formats that the remote client currently offers.
     remote_formats: Vec<ClipboardFormat>,
+    /// Also write remote text into the local PRIMARY selection.
+    mirror_to_primary: bool,
+    /// Offer the local PRIMARY selection to the remote client.
+    offer_primary: bool,
 }
 
 impl LocalClipboardBackend {
-    fn new(event_tx: mpsc::UnboundedSender<ServerEvent>) -> Self {
+    fn new(
+        event_tx: mpsc::UnboundedSender<ServerEvent>,
+        mirror_to_primary: bool,
+        offer_primary: bool,
+    ) -> Self {
         Self {
             event_tx,
             remote_formats: Vec::new(),
+            mirror_to_primary,
+            offer_primary,
+        }
+    }
+
+    /// Read the local text that should be offered to the remote.
+    ///
+    /// When `offer_primary` is set, a non-empty PRIMARY selection wins over
+    /// the regular clipboard. Compositors without primary-selection support
+    /// fall back to the regular clipboard.
+    fn read_local_text(&self) -> Result<String, arboard::Error> {
+        let mut clipboard = arboard::Clipboard::new()?;
+        if self.offer_primary {
+            match clipboard.get().clipboard(LinuxClipboardKind::Primary).text() {
+                Ok(text) if !text.is_empty() => return Ok(text),
+                Ok(_) => {}
+                Err(e) => tracing::debug!("PRIMARY selection unavailable: {e}"),
+            }
         }
+        clipboard.get_text()
+    }
+
+    /// Write remote text to the local clipboard (and PRIMARY, if enabled).
+    fn write_local_text(&self, text: &str) -> Result<(), arboard::Error> {
+        let mut clipboard = arboard::Clipboard::new()?;
+        clipboard.set_text(text)?;
+        if self.mirror_to_primary {
+            if let Err(e) = clipboard
+                .set()
+                .clipboard(LinuxClipboardKind::Primary)
+                .text(text)
+            {
+                tracing::warn!("Failed to write to PRIMARY selection: {e}");
+            }
+        }
+        Ok(())
     }
 
     /// Send a clipboard message to the ironrdp server event loop.
@@ -87,7 +135,7 @@ impl CliprdrBackend for LocalClipboardBackend {
 
     fn on_request_format_list(&mut self) {
         // Check if the local clipboard has text content.
-        match arboard::Clipboard::new().and_then(|mut cb| cb.get_text()) {
+        match self.read_local_text() {
             Ok(text) if !text.is_empty() => {
                 tracing::debug!(len = text.len(), "Advertising local clipboard text");
                 self.send(ClipboardMessage::SendInitiateCopy(Self::text_formats()));
@@ -136,7 +184,7 @@ impl CliprdrBackend for LocalClipboardBackend {
         // Remote wants to paste our local clipboard content.
         tracing::debug!(?request, "Remote requesting local clipboard data");
 
-        let response = match arboard::Clipboard::new().and_then(|mut cb| cb.get_text()) {
+        let response = match self.read_local_text() {
             Ok(text) => {
                 if request.format == ClipboardFormatId::CF_UNICODETEXT {
                     OwnedFormatDataResponse::new_unicode_string(&text)
@@ -179,9 +227,13 @@ impl CliprdrBackend for LocalClipboardBackend {
 
         match text {
             Some(s) => {
-                match arboard::Clipboard::new().and_then(|mut cb| cb.set_text(s.clone())) {
+                match self.write_local_text(&s) {
                     Ok(()) => {
-                        tracing::debug!(len = s.len(), "Wrote remote text to local clipboard");
+                        tracing::debug!(
+                            len = s.len(),
+                            primary = self.mirror_to_primary,
+                            "Wrote remote text to local clipboard"
+                        );
                     }
                     Err(e) => {
                         tracing::warn!("Failed to write to local clipboard: {e}");
@@ -216,11 +268,17 @@ impl CliprdrBackend for LocalClipboardBackend {
 #[derive(Debug)]
 pub struct LocalClipboardFactory {
     event_tx: Option<mpsc::UnboundedSender<ServerEvent>>,
+    mirror_to_primary: bool,
+    offer_primary: bool,
 }
 
 impl LocalClipboardFactory {
-    pub fn new() -> Self {
-        Self { event_tx: None }
+    pub fn new(mirror_to_primary: bool, offer_primary: bool) -> Self {
+        Self {
+            event_tx: None,
+            mirror_to_primary,
+            offer_primary,
+        }
     }
 }
 
@@ -230,7 +288,11 @@ impl CliprdrBackendFactory for LocalClipboardFactory {
             .event_tx
             .clone()
             .expect("set_sender must be called before build_cliprdr_backend");
-        Box::new(LocalClipboardBackend::new(tx))
+        Box::new(LocalClipboardBackend::new(
+            tx,
+            self.mirror_to_primary,
+            self.offer_primary,
+        ))
     }
 }
 
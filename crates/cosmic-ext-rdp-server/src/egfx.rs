@@ -18,8 +18,18 @@
 //!
 //! - [`EgfxEventSetter`] – lightweight handle used after `RdpServer`
 //!   construction to inject the server event sender into shared state.
+//!
+//! # Resizing
+//!
+//! Clients send a monitor layout for every step while their window is
+//! dragged. Resizes therefore go through a small state machine: requests
+//! are debounced until the size settles, then `ResetGraphics` and
+//! `CreateSurface` are sent together, and frames are dropped until a
+//! keyframe at the new size has been delivered. Frames encoded for the old
+//! size are dropped rather than sent to the new surface.
 
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use ironrdp_core::{encode_vec, impl_as_any, Encode, WriteCursor};
 use ironrdp_dvc::{DvcEncode, DvcMessage, DvcProcessor, DvcProcessorFactory, DvcServerProcessor};
@@ -33,6 +43,34 @@ use tokio::sync::mpsc;
 /// Lower = better quality (18-23 is typical for RDP).
 const EGFX_QP: u8 = 22;
 
+/// How long client resize requests must stop arriving before the last one
+/// is applied.
+pub(crate) const RESIZE_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Progress of a client-requested surface resize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResizeState {
+    /// The surface matches the last request.
+    Idle,
+    /// Waiting for requests to settle before resizing to `width` x `height`.
+    Pending { width: u16, height: u16, due: Instant },
+    /// The surface was recreated; frames are dropped until a keyframe at
+    /// the new size has been sent.
+    AwaitingKeyframe,
+}
+
+/// Outcome of [`EgfxController::send_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDelivery {
+    /// The frame was queued on the DVC channel.
+    Sent,
+    /// The frame was dropped on purpose (stale size or waiting for a
+    /// keyframe after a resize); do not fall back to bitmaps.
+    Dropped,
+    /// EGFX cannot take the frame; bitmap fallback should be used.
+    Unavailable,
+}
+
 /// Shared inner state between bridge, handler, and controller.
 struct EgfxInner {
     server: GraphicsPipelineServer,
@@ -43,9 +81,10 @@ struct EgfxInner {
     width: u16,
     height: u16,
     event_tx: Option<mpsc::UnboundedSender<ServerEvent>>,
-    /// Set `true` after `resize()` so the encoder forces an IDR keyframe
+    /// Set `true` after a resize so the encoder forces an IDR keyframe
     /// on the next frame, ensuring the client can decode immediately.
     needs_keyframe: bool,
+    resize: ResizeState,
 }
 
 /// Thread-safe shared EGFX state.
@@ -229,6 +268,7 @@ impl DvcProcessor for EgfxBridge {
         inner.surface_id = None;
         inner.dvc_channel_id = None;
         inner.supports_avc420 = false;
+        inner.resize = ResizeState::Idle;
     }
 
     fn process(&mut self, channel_id: u32, payload: &[u8]) -> PduResult<Vec<DvcMessage>> {
@@ -307,6 +347,7 @@ impl EgfxController {
         inner.dvc_channel_id = None;
         inner.supports_avc420 = false;
         inner.needs_keyframe = false;
+        inner.resize = ResizeState::Idle;
         tracing::debug!("EGFX: state reset for new connection");
     }
 
@@ -326,21 +367,39 @@ impl EgfxController {
         lock_shared(&self.shared).ready
     }
 
+    /// Whether the client has opened the EGFX DVC channel.
+    ///
+    /// Clients without graphics pipeline support never open it; clients
+    /// that do may still be negotiating capabilities (see
+    /// [`is_ready`](Self::is_ready)).
+    #[must_use]
+    pub fn is_channel_open(&self) -> bool {
+        lock_shared(&self.shared).dvc_channel_id.is_some()
+    }
+
     /// Whether the negotiated capabilities include AVC420 (H.264).
     #[must_use]
     pub fn supports_avc420(&self) -> bool {
         lock_shared(&self.shared).supports_avc420
     }
 
+    /// Current surface size.
+    #[must_use]
+    pub fn size(&self) -> (u16, u16) {
+        let inner = lock_shared(&self.shared);
+        (inner.width, inner.height)
+    }
+
     /// Send an H.264 frame through the EGFX channel.
     ///
     /// Locks the shared state, calls `send_avc420_frame` on the
     /// `GraphicsPipelineServer`, drains the output PDUs, and sends
     /// them via the `ServerEvent::DvcOutput` channel.
     ///
-    /// Returns `true` if the frame was queued successfully, `false` if
-    /// the channel is not ready, backpressure is active, or the event
-    /// sender is not configured.
+    /// Frames whose size does not match the surface (encoded before a
+    /// resize) are dropped, as are non-keyframes right after a resize.
+    /// Returns [`FrameDelivery::Unavailable`] if the channel is not ready,
+    /// backpressure is active, or the event sender is not configured.
     #[allow(clippy::cast_possible_truncation)]
     pub fn send_frame(
         &self,
@@ -348,23 +407,38 @@ impl EgfxController {
         width: u16,
         height: u16,
         timestamp_ms: u32,
-    ) -> bool {
+        is_keyframe: bool,
+    ) -> FrameDelivery {
         let mut inner = lock_shared(&self.shared);
 
         let Some(ref event_tx) = inner.event_tx else {
             tracing::warn!("EGFX: cannot send frame, event sender not configured");
-            return false;
+            return FrameDelivery::Unavailable;
         };
         // Clone sender before mutating inner state (borrow checker).
         let event_tx = event_tx.clone();
 
         let Some(surface_id) = inner.surface_id else {
-            return false;
+            return FrameDelivery::Unavailable;
         };
 
+        if (width, height) != (inner.width, inner.height) {
+            tracing::debug!(
+                width, height,
+                surface_width = inner.width, surface_height = inner.height,
+                "EGFX: dropping frame encoded for a different surface size"
+            );
+            return FrameDelivery::Dropped;
+        }
+
+        if inner.resize == ResizeState::AwaitingKeyframe && !is_keyframe {
+            tracing::trace!("EGFX: waiting for a keyframe after resize, dropping frame");
+            return FrameDelivery::Dropped;
+        }
+
         if inner.server.should_backpressure() {
             tracing::trace!("EGFX: backpressure active, dropping frame");
-            return false;
+            return FrameDelivery::Unavailable;
         }
 
         let region = Avc420Region::full_frame(width, height, EGFX_QP);
@@ -379,15 +453,20 @@ impl EgfxController {
                 .server
                 .send_avc420_frame(surface_id, h264_data, &regions, timestamp_ms)
         else {
-            return false;
+            return FrameDelivery::Unavailable;
         };
 
         let drained = inner.server.drain_output();
         let messages = zgfx_wrap_messages(&drained);
         let Some(dvc_channel_id) = inner.dvc_channel_id else {
-            return false;
+            return FrameDelivery::Unavailable;
         };
 
+        if inner.resize == ResizeState::AwaitingKeyframe {
+            tracing::debug!(width, height, "EGFX: resize complete, keyframe sent");
+            inner.resize = ResizeState::Idle;
+        }
+
         drop(inner); // Release lock before sending.
 
         tracing::trace!(frame_id, dvc_channel_id, "EGFX: sending H.264 frame");
@@ -400,35 +479,88 @@ impl EgfxController {
             .is_err()
         {
             tracing::warn!("EGFX: event channel closed, cannot send frame");
-            return false;
+            return FrameDelivery::Unavailable;
         }
 
-        true
+        FrameDelivery::Sent
     }
 
-    /// Resize the EGFX surface.
+    /// Request a surface resize to `width` x `height`.
     ///
-    /// Deletes the old surface, sends `ResetGraphics`, creates a new
-    /// surface at the new dimensions, and maps it to output.
-    pub fn resize(&self, width: u16, height: u16) {
+    /// Requests are debounced: the resize is applied by
+    /// [`apply_pending_resize`](Self::apply_pending_resize) once no new
+    /// request has arrived for [`RESIZE_DEBOUNCE`]. Before the channel is
+    /// ready the size is simply recorded for surface creation.
+    pub fn request_resize(&self, width: u16, height: u16) {
+        self.request_resize_at(width, height, Instant::now());
+    }
+
+    fn request_resize_at(&self, width: u16, height: u16, now: Instant) {
         let mut inner = lock_shared(&self.shared);
 
-        let Some(ref event_tx) = inner.event_tx else {
+        if !inner.ready || inner.event_tx.is_none() {
             inner.width = width;
             inner.height = height;
+            inner.resize = ResizeState::Idle;
             return;
-        };
-        let event_tx = event_tx.clone();
+        }
 
-        if !inner.ready {
-            inner.width = width;
-            inner.height = height;
+        if (width, height) == (inner.width, inner.height) {
+            // Back to the current size before the debounce expired.
+            if matches!(inner.resize, ResizeState::Pending { .. }) {
+                tracing::debug!(width, height, "EGFX: pending resize cancelled");
+                inner.resize = ResizeState::Idle;
+            }
             return;
         }
 
+        inner.resize = ResizeState::Pending {
+            width,
+            height,
+            due: now + RESIZE_DEBOUNCE,
+        };
+    }
+
+    /// When the pending resize (if any) becomes due.
+    #[must_use]
+    pub fn resize_due(&self) -> Option<Instant> {
+        match lock_shared(&self.shared).resize {
+            ResizeState::Pending { due, .. } => Some(due),
+            _ => None,
+        }
+    }
+
+    /// Apply a pending resize whose debounce has expired.
+    ///
+    /// Deletes the old surface, then sends `ResetGraphics` and the new
+    /// surface's `CreateSurface`/`MapSurfaceToOutput` in one DVC message.
+    /// Returns the new size so the caller can rebuild its encoder; frames
+    /// are dropped until a keyframe at that size is sent.
+    pub fn apply_pending_resize(&self) -> Option<(u16, u16)> {
+        self.apply_pending_resize_at(Instant::now())
+    }
+
+    fn apply_pending_resize_at(&self, now: Instant) -> Option<(u16, u16)> {
+        let mut inner = lock_shared(&self.shared);
+
+        let ResizeState::Pending { width, height, due } = inner.resize else {
+            return None;
+        };
+        if now < due {
+            return None;
+        }
+        let event_tx = inner.event_tx.clone()?;
+
+        tracing::info!(
+            width, height,
+            old_width = inner.width, old_height = inner.height,
+            "EGFX: applying resize via ResetGraphics"
+        );
+
         inner.width = width;
         inner.height = height;
         inner.needs_keyframe = true;
+        inner.resize = ResizeState::AwaitingKeyframe;
 
         inner.server.resize(width, height);
 
@@ -442,16 +574,18 @@ impl EgfxController {
 
         let drained = inner.server.drain_output();
         let messages = zgfx_wrap_messages(&drained);
-        let Some(dvc_channel_id) = inner.dvc_channel_id else {
-            return;
-        };
+        let dvc_channel_id = inner.dvc_channel_id;
 
         drop(inner);
 
-        let _ = event_tx.send(ServerEvent::DvcOutput {
-            dvc_channel_id,
-            messages,
-        });
+        if let Some(dvc_channel_id) = dvc_channel_id {
+            let _ = event_tx.send(ServerEvent::DvcOutput {
+                dvc_channel_id,
+                messages,
+            });
+        }
+
+        Some((width, height))
     }
 }
 
@@ -528,6 +662,7 @@ pub fn create_egfx(
         height,
         event_tx: None,
         needs_keyframe: false,
+        resize: ResizeState::Idle,
     }));
 
     let factory = EgfxBridgeFactory {
@@ -577,4 +712,107 @@ mod tests {
         let bridge2 = factory.build();
         assert_eq!(bridge2.channel_name(), "Microsoft::Windows::RDS::Graphics");
     }
+
+    /// A controller whose channel is ready, with a fake event sender.
+    fn ready_controller() -> (EgfxController, mpsc::UnboundedReceiver<ServerEvent>) {
+        let (_factory, controller, setter) = create_egfx(1920, 1080);
+        let (tx, rx) = mpsc::unbounded_channel();
+        setter.set_event_sender(tx);
+        {
+            let mut inner = lock_shared(&controller.shared);
+            inner.ready = true;
+            inner.supports_avc420 = true;
+            inner.surface_id = Some(1);
+            inner.dvc_channel_id = Some(7);
+        }
+        (controller, rx)
+    }
+
+    fn dvc_outputs(rx: &mut mpsc::UnboundedReceiver<ServerEvent>) -> usize {
+        let mut count = 0;
+        while let Ok(event) = rx.try_recv() {
+            if matches!(event, ServerEvent::DvcOutput { dvc_channel_id: 7, .. }) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn resize_bursts_are_debounced() {
+        let (controller, mut rx) = ready_controller();
+        let start = Instant::now();
+
+        // A window drag: many requests in quick succession.
+        for i in 0..10u16 {
+            let at = start + Duration::from_millis(20 * u64::from(i));
+            controller.request_resize_at(1600 + 10 * i, 900, at);
+            assert_eq!(controller.apply_pending_resize_at(at), None);
+        }
+        let last = start + Duration::from_millis(20 * 9);
+        assert_eq!(controller.resize_due(), Some(last + RESIZE_DEBOUNCE));
+        assert_eq!(dvc_outputs(&mut rx), 0);
+        assert_eq!(controller.size(), (1920, 1080));
+
+        // Only the final size is applied, once, after the debounce.
+        let due = last + RESIZE_DEBOUNCE;
+        assert_eq!(controller.apply_pending_resize_at(due), Some((1690, 900)));
+        assert_eq!(controller.apply_pending_resize_at(due), None);
+        assert_eq!(controller.resize_due(), None);
+        assert_eq!(controller.size(), (1690, 900));
+        assert_eq!(dvc_outputs(&mut rx), 1);
+        assert!(controller.take_needs_keyframe());
+    }
+
+    #[test]
+    fn returning_to_current_size_cancels_resize() {
+        let (controller, mut rx) = ready_controller();
+        let now = Instant::now();
+        controller.request_resize_at(1280, 720, now);
+        controller.request_resize_at(1920, 1080, now);
+        assert_eq!(controller.resize_due(), None);
+        assert_eq!(controller.apply_pending_resize_at(now + RESIZE_DEBOUNCE), None);
+        assert_eq!(dvc_outputs(&mut rx), 0);
+    }
+
+    #[test]
+    fn stale_and_non_key_frames_are_dropped_after_resize() {
+        let (controller, _rx) = ready_controller();
+        let now = Instant::now();
+        controller.request_resize_at(1280, 720, now);
+
+        // Until the resize applies, frames at the new size do not fit.
+        assert_eq!(
+            controller.send_frame(&[], 1280, 720, 0, true),
+            FrameDelivery::Dropped
+        );
+
+        assert_eq!(
+            controller.apply_pending_resize_at(now + RESIZE_DEBOUNCE),
+            Some((1280, 720))
+        );
+        // Frames encoded before the resize no longer fit either.
+        assert_eq!(
+            controller.send_frame(&[], 1920, 1080, 0, true),
+            FrameDelivery::Dropped
+        );
+        // At the new size, only a keyframe may go first.
+        assert_eq!(
+            controller.send_frame(&[], 1280, 720, 0, false),
+            FrameDelivery::Dropped
+        );
+    }
+
+    #[test]
+    fn resize_before_ready_only_records_size() {
+        let (_factory, controller, setter) = create_egfx(1920, 1080);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        setter.set_event_sender(tx);
+
+        controller.request_resize(1024, 768);
+        assert_eq!(controller.size(), (1024, 768));
+        assert_eq!(controller.resize_due(), None);
+        assert_eq!(controller.apply_pending_resize(), None);
+        assert_eq!(dvc_outputs(&mut rx), 0);
+    }
 }
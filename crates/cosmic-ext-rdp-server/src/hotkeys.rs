@@ -0,0 +1,98 @@
+//! Server-side keyboard shortcuts.
+//!
+//! A few chords are handled by the server instead of being forwarded to
+//! the compositor:
+//!
+//! - `Ctrl+Alt+PageDown`: next monitor
+//! - `Ctrl+Alt+PageUp`: previous monitor
+//!
+//! In viewport mode these move the viewport; otherwise they switch the
+//! served monitor when that is possible (see [`crate::monitor_select`]).
+//!
+//! The chord's key press and release are both swallowed; the modifiers
+//! themselves are still forwarded, as the compositor has already seen them
+//! go down.
+
+/// Scancode (set 1) of Ctrl; the right key is extended.
+const SC_CTRL: u8 = 0x1D;
+/// Scancode of Alt; the right key (`AltGr`) is extended.
+const SC_ALT: u8 = 0x38;
+/// Scancode of `PageUp`.
+const SC_PAGE_UP: u8 = 0x49;
+/// Scancode of `PageDown`.
+const SC_PAGE_DOWN: u8 = 0x51;
+
+/// An action triggered by a server shortcut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    NextMonitor,
+    PreviousMonitor,
+}
+
+/// Tracks modifier state and recognises shortcuts.
+#[derive(Debug, Default)]
+pub struct Hotkeys {
+    ctrl: [bool; 2],
+    alt: [bool; 2],
+    /// Key whose press was swallowed, so its release is swallowed too.
+    swallowed: Option<u8>,
+}
+
+impl Hotkeys {
+    /// Feed a key event.
+    ///
+    /// Returns `Some` if the event completes a shortcut (on press) or
+    /// belongs to one (on release); such events must not be forwarded.
+    /// The release reports the same hotkey but should not trigger it again.
+    pub fn key(&mut self, code: u8, extended: bool, pressed: bool) -> Option<Hotkey> {
+        let side = usize::from(extended);
+        match code {
+            SC_CTRL => self.ctrl[side] = pressed,
+            SC_ALT => self.alt[side] = pressed,
+            _ => {}
+        }
+
+        let hotkey = match code {
+            SC_PAGE_DOWN => Hotkey::NextMonitor,
+            SC_PAGE_UP => Hotkey::PreviousMonitor,
+            _ => return None,
+        };
+
+        if !pressed {
+            return (self.swallowed.take() == Some(code)).then_some(hotkey);
+        }
+        let chord = self.ctrl.contains(&true) && self.alt.contains(&true);
+        if chord {
+            self.swallowed = Some(code);
+        }
+        chord.then_some(hotkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_is_recognised_and_swallowed() {
+        let mut keys = Hotkeys::default();
+        assert_eq!(keys.key(SC_CTRL, false, true), None);
+        assert_eq!(keys.key(SC_ALT, true, true), None);
+        assert_eq!(keys.key(SC_PAGE_DOWN, true, true), Some(Hotkey::NextMonitor));
+        // Releasing the modifiers first still swallows the key release.
+        assert_eq!(keys.key(SC_ALT, true, false), None);
+        assert_eq!(keys.key(SC_PAGE_DOWN, true, false), Some(Hotkey::NextMonitor));
+        assert_eq!(keys.key(SC_CTRL, false, false), None);
+    }
+
+    #[test]
+    fn keys_without_chord_are_forwarded() {
+        let mut keys = Hotkeys::default();
+        assert_eq!(keys.key(SC_PAGE_UP, true, true), None);
+        assert_eq!(keys.key(SC_PAGE_UP, true, false), None);
+        keys.key(SC_CTRL, true, true);
+        assert_eq!(keys.key(SC_PAGE_UP, true, true), None);
+        keys.key(SC_ALT, false, true);
+        assert_eq!(keys.key(SC_PAGE_UP, true, true), Some(Hotkey::PreviousMonitor));
+    }
+}
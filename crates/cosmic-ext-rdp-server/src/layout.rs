@@ -0,0 +1,495 @@
+//! Client monitor layouts and their mapping onto the capture.
+//!
+//! The Display Control channel (MS-RDPEDISP) describes every monitor of
+//! the client: size, position relative to the primary, and orientation.
+//! The RDP desktop is the bounding box of those monitors. Each client
+//! monitor shows one captured monitor, letterboxed into it, so a dual-head
+//! client gets one captured output per screen instead of a single image
+//! stretched across both.
+//!
+//! Captured monitors are paired with client monitors as follows:
+//!
+//! - a single client monitor shows the whole capture;
+//! - a single captured monitor is shown on the client's primary monitor;
+//! - otherwise both sides are paired in reading order (left to right, then
+//!   top to bottom), and monitors without a partner stay black or hidden.
+//!
+//! In viewport mode the desktop is instead a client-sized [`Viewport`]
+//! onto the capture, cropped rather than scaled, that pans to follow the
+//! pointer.
+
+use ironrdp_displaycontrol::pdu::{DisplayControlMonitorLayout, MonitorOrientation};
+use rdp_capture::MonitorInfo;
+use rdp_encode::{compose_bgra, Letterbox, Region};
+
+/// Rotation of a client monitor, clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Whether the monitor is in portrait orientation, i.e. its mode's
+    /// width and height are swapped on screen.
+    #[must_use]
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Self::Rotate90 | Self::Rotate270)
+    }
+}
+
+/// One monitor of the client, in desktop coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientMonitor {
+    pub x: i32,
+    pub y: i32,
+    /// Width as shown, i.e. after rotation.
+    pub width: u32,
+    /// Height as shown, i.e. after rotation.
+    pub height: u32,
+    pub primary: bool,
+    pub rotation: Rotation,
+}
+
+impl ClientMonitor {
+    fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.width)
+    }
+
+    fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.height)
+    }
+}
+
+/// The client's monitors, shifted so the desktop starts at the origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientLayout {
+    monitors: Vec<ClientMonitor>,
+    width: u16,
+    height: u16,
+}
+
+impl ClientLayout {
+    /// A single primary monitor covering the desktop.
+    #[must_use]
+    pub fn single(width: u16, height: u16) -> Self {
+        Self {
+            monitors: vec![ClientMonitor {
+                x: 0,
+                y: 0,
+                width: u32::from(width),
+                height: u32::from(height),
+                primary: true,
+                rotation: Rotation::Normal,
+            }],
+            width,
+            height,
+        }
+    }
+
+    /// Build a layout from the monitors of a layout PDU.
+    ///
+    /// Returns `None` if the PDU has no usable monitor or the desktop
+    /// would not fit in 16 bits.
+    #[must_use]
+    pub fn from_pdu(layout: &DisplayControlMonitorLayout) -> Option<Self> {
+        let monitors = layout.monitors().iter().map(|entry| {
+            let (width, height) = entry.dimensions();
+            let (x, y) = entry.position().unwrap_or((0, 0));
+            let rotation = match entry.orientation() {
+                Some(MonitorOrientation::Portrait) => Rotation::Rotate90,
+                Some(MonitorOrientation::LandscapeFlipped) => Rotation::Rotate180,
+                Some(MonitorOrientation::PortraitFlipped) => Rotation::Rotate270,
+                _ => Rotation::Normal,
+            };
+            ClientMonitor {
+                x,
+                y,
+                width,
+                height,
+                primary: entry.is_primary(),
+                rotation,
+            }
+        });
+        Self::new(monitors)
+    }
+
+    /// Build a layout from monitors in client coordinates.
+    ///
+    /// Empty monitors are dropped. If none is marked primary, the first
+    /// one is. Returns `None` if no monitor is left or the desktop would
+    /// not fit in 16 bits.
+    #[must_use]
+    pub fn new(monitors: impl IntoIterator<Item = ClientMonitor>) -> Option<Self> {
+        let mut monitors: Vec<ClientMonitor> = monitors
+            .into_iter()
+            .filter(|m| m.width > 0 && m.height > 0)
+            .collect();
+
+        let left = monitors.iter().map(|m| m.x).min()?;
+        let top = monitors.iter().map(|m| m.y).min()?;
+        let right = monitors.iter().map(ClientMonitor::right).max()?;
+        let bottom = monitors.iter().map(ClientMonitor::bottom).max()?;
+        let width = u16::try_from(right - i64::from(left)).ok()?;
+        let height = u16::try_from(bottom - i64::from(top)).ok()?;
+
+        for monitor in &mut monitors {
+            monitor.x -= left;
+            monitor.y -= top;
+        }
+        // Keep exactly one primary, first in the list.
+        let primary = monitors.iter().position(|m| m.primary).unwrap_or(0);
+        for (i, monitor) in monitors.iter_mut().enumerate() {
+            monitor.primary = i == primary;
+        }
+        monitors.swap(0, primary);
+
+        Some(Self {
+            monitors,
+            width,
+            height,
+        })
+    }
+
+    /// Desktop size (bounding box of all monitors).
+    #[must_use]
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// The primary monitor.
+    #[must_use]
+    pub fn primary(&self) -> &ClientMonitor {
+        &self.monitors[0]
+    }
+
+    /// All monitors, primary first.
+    #[must_use]
+    pub fn monitors(&self) -> &[ClientMonitor] {
+        &self.monitors
+    }
+
+    /// Map a `capture_width` x `capture_height` capture made of `captured`
+    /// monitors onto this layout.
+    #[must_use]
+    pub fn map(&self, captured: &[MonitorInfo], capture_width: u32, capture_height: u32) -> DesktopMap {
+        let whole = (0, 0, capture_width, capture_height);
+        // Both layouts start at (0, 0) once normalised (`ClientLayout::new`
+        // and rdp-capture's monitor layout), so positions are not negative.
+        let position = |v: i32| u32::try_from(v).unwrap_or(0);
+        let dest = |m: &ClientMonitor| (position(m.x), position(m.y), m.width, m.height);
+
+        let pairs: Vec<_> = if self.monitors.len() == 1 || captured.len() <= 1 {
+            vec![(whole, dest(self.primary()))]
+        } else {
+            let mut clients: Vec<&ClientMonitor> = self.monitors.iter().collect();
+            clients.sort_by_key(|m| (m.x, m.y));
+            let mut sources: Vec<&MonitorInfo> = captured.iter().collect();
+            sources.sort_by_key(|m| (m.x, m.y));
+            sources
+                .into_iter()
+                .zip(clients)
+                .map(|(src, client)| {
+                    let src = (
+                        position(src.x),
+                        position(src.y),
+                        u32::from(src.width),
+                        u32::from(src.height),
+                    );
+                    (src, dest(client))
+                })
+                .collect()
+        };
+
+        DesktopMap {
+            width: u32::from(self.width),
+            height: u32::from(self.height),
+            regions: pairs
+                .into_iter()
+                .map(|((sx, sy, sw, sh), (dx, dy, dw, dh))| {
+                    Region::new(sx, sy, dx, dy, Letterbox::fit(sw, sh, dw, dh))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Distance the pointer is kept from the edges of a [`Viewport`].
+const VIEWPORT_MARGIN: u32 = 64;
+
+/// A desktop-sized window onto a larger capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Viewport {
+    /// Left edge in the capture.
+    pub x: u32,
+    /// Top edge in the capture.
+    pub y: u32,
+    /// Width in the capture (the desktop width, unless the capture is
+    /// narrower).
+    pub width: u32,
+    /// Height in the capture.
+    pub height: u32,
+}
+
+impl Viewport {
+    /// Fit the viewport to a new desktop or capture size, keeping its
+    /// position where possible.
+    pub fn resize(&mut self, desktop: (u32, u32), capture: (u32, u32)) {
+        self.width = desktop.0.min(capture.0);
+        self.height = desktop.1.min(capture.1);
+        self.x = self.x.min(capture.0 - self.width);
+        self.y = self.y.min(capture.1 - self.height);
+    }
+
+    /// Pan so the capture point (`x`, `y`) is at least
+    /// [`VIEWPORT_MARGIN`] away from the edges, within the capture.
+    ///
+    /// Returns whether the viewport moved.
+    pub fn follow(&mut self, x: u32, y: u32, capture: (u32, u32)) -> bool {
+        let old = *self;
+        self.x = follow_axis(self.x, self.width, x, capture.0);
+        self.y = follow_axis(self.y, self.height, y, capture.1);
+        *self != old
+    }
+
+    /// Centre the viewport on a capture rectangle, within the capture.
+    pub fn centre_on(&mut self, monitor: &MonitorInfo, capture: (u32, u32)) {
+        let centre = |start: i32, len: u16, view: u32, total: u32| {
+            let middle = u32::try_from(start).unwrap_or(0) + u32::from(len) / 2;
+            middle.saturating_sub(view / 2).min(total - view)
+        };
+        self.x = centre(monitor.x, monitor.width, self.width, capture.0);
+        self.y = centre(monitor.y, monitor.height, self.height, capture.1);
+    }
+
+    /// Map the viewport onto a `desktop_width` x `desktop_height` desktop.
+    #[must_use]
+    pub fn map(&self, desktop_width: u32, desktop_height: u32) -> DesktopMap {
+        let letterbox = Letterbox::fit(self.width, self.height, desktop_width, desktop_height);
+        DesktopMap {
+            width: desktop_width,
+            height: desktop_height,
+            regions: vec![Region::new(self.x, self.y, 0, 0, letterbox)],
+        }
+    }
+}
+
+/// New start of a viewport span of `len` so `point` stays clear of its
+/// edges, within `[0, total)`.
+fn follow_axis(start: u32, len: u32, point: u32, total: u32) -> u32 {
+    let margin = VIEWPORT_MARGIN.min(len / 4);
+    let start = if point < start + margin {
+        point.saturating_sub(margin)
+    } else if point + margin >= start + len {
+        (point + margin + 1).saturating_sub(len)
+    } else {
+        start
+    };
+    start.min(total.saturating_sub(len))
+}
+
+/// Placement of the capture on the client's desktop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopMap {
+    /// Desktop width.
+    pub width: u32,
+    /// Desktop height.
+    pub height: u32,
+    /// Captured regions and where they are shown. Never empty.
+    pub regions: Vec<Region>,
+}
+
+impl DesktopMap {
+    /// The whole capture letterboxed onto the whole desktop.
+    #[must_use]
+    pub fn single(letterbox: Letterbox) -> Self {
+        Self {
+            width: letterbox.dst_width,
+            height: letterbox.dst_height,
+            regions: vec![Region::new(0, 0, 0, 0, letterbox)],
+        }
+    }
+
+    /// The mapping as a single letterbox, if the whole capture covers the
+    /// whole desktop (the case the encoder can scale by itself).
+    #[must_use]
+    pub fn as_letterbox(&self, capture_width: u32, capture_height: u32) -> Option<Letterbox> {
+        match self.regions[..] {
+            [region]
+                if (region.src_x, region.src_y, region.dst_x, region.dst_y) == (0, 0, 0, 0)
+                    && (region.letterbox.src_width, region.letterbox.src_height)
+                        == (capture_width, capture_height)
+                    && (region.letterbox.dst_width, region.letterbox.dst_height)
+                        == (self.width, self.height) =>
+            {
+                Some(region.letterbox)
+            }
+            _ => None,
+        }
+    }
+
+    /// Region shown at a desktop point, or the first one (primary
+    /// monitor) if the point is on a black area.
+    fn region_at_dest(&self, x: u32, y: u32) -> &Region {
+        self.regions
+            .iter()
+            .find(|r| r.contains_dest(x, y))
+            .unwrap_or(&self.regions[0])
+    }
+
+    /// Region containing a capture point, or the first one if that part
+    /// of the capture is not shown.
+    #[must_use]
+    pub fn region_at_source(&self, x: u32, y: u32) -> &Region {
+        self.regions
+            .iter()
+            .find(|r| r.contains_source(x, y))
+            .unwrap_or(&self.regions[0])
+    }
+
+    /// Map a desktop point to the capture.
+    #[must_use]
+    pub fn to_source(&self, x: u32, y: u32) -> (u32, u32) {
+        self.region_at_dest(x, y).to_source(x, y)
+    }
+
+    /// Map a capture point to the desktop. Points on captured monitors
+    /// that are not shown are clamped onto the primary monitor.
+    #[must_use]
+    pub fn to_dest(&self, x: u32, y: u32) -> (u32, u32) {
+        self.region_at_source(x, y).to_dest(x, y)
+    }
+
+    /// Draw a BGRA capture onto the desktop.
+    #[must_use]
+    pub fn compose(&self, src: &[u8], src_stride: usize) -> Vec<u8> {
+        compose_bgra(src, src_stride, &self.regions, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32, primary: bool) -> ClientMonitor {
+        ClientMonitor {
+            x,
+            y,
+            width,
+            height,
+            primary,
+            rotation: Rotation::Normal,
+        }
+    }
+
+    fn captured(x: i32, width: u16, height: u16) -> MonitorInfo {
+        MonitorInfo {
+            node_id: 0,
+            width,
+            height,
+            x,
+            y: 0,
+        }
+    }
+
+    #[test]
+    fn layout_is_normalized_with_primary_first() {
+        // Secondary monitor to the left of the primary.
+        let layout = ClientLayout::new([
+            monitor(-1280, 56, 1280, 1024, false),
+            monitor(0, 0, 1920, 1080, true),
+        ])
+        .unwrap();
+        assert_eq!(layout.size(), (3200, 1080));
+        assert_eq!((layout.primary().x, layout.primary().y), (1280, 0));
+        assert_eq!((layout.monitors()[1].x, layout.monitors()[1].y), (0, 56));
+    }
+
+    #[test]
+    fn oversized_or_empty_layouts_are_rejected() {
+        assert!(ClientLayout::new([]).is_none());
+        assert!(ClientLayout::new([monitor(0, 0, 0, 1080, true)]).is_none());
+        assert!(ClientLayout::new([
+            monitor(0, 0, 40000, 1080, true),
+            monitor(40000, 0, 40000, 1080, false),
+        ])
+        .is_none());
+    }
+
+    #[test]
+    fn single_capture_goes_to_primary_monitor() {
+        let layout = ClientLayout::new([
+            monitor(0, 0, 1280, 1024, false),
+            monitor(1280, 0, 1920, 1080, true),
+        ])
+        .unwrap();
+        let map = layout.map(&[captured(0, 1920, 1080)], 1920, 1080);
+        assert_eq!(map.regions.len(), 1);
+        assert_eq!((map.regions[0].dst_x, map.regions[0].dst_y), (1280, 0));
+        assert!(map.as_letterbox(1920, 1080).is_none());
+        // The secondary monitor is black; pointer positions there clamp to
+        // the primary's capture.
+        assert_eq!(map.to_source(100, 100), (0, 100));
+    }
+
+    #[test]
+    fn monitors_are_paired_in_reading_order() {
+        let layout = ClientLayout::new([
+            monitor(0, 0, 1920, 1080, true),
+            monitor(1920, 0, 1920, 1080, false),
+        ])
+        .unwrap();
+        let capture = [captured(2560, 1920, 1080), captured(0, 2560, 1440)];
+        let map = layout.map(&capture, 4480, 1440);
+        assert_eq!(map.regions.len(), 2);
+        // The 2560x1440 monitor on the left fills the left client monitor.
+        assert_eq!(map.to_source(960, 540), (1280, 720));
+        assert_eq!(map.to_source(1920, 0), (2560, 0));
+        assert_eq!(map.to_dest(2560, 0), (1920, 0));
+    }
+
+    #[test]
+    fn viewport_follows_pointer_within_capture() {
+        let capture = (5120, 1440);
+        let mut viewport = Viewport::default();
+        viewport.resize((1280, 800), capture);
+        assert_eq!((viewport.width, viewport.height), (1280, 800));
+
+        assert!(!viewport.follow(640, 400, capture));
+        assert!(viewport.follow(1300, 400, capture));
+        assert_eq!(viewport.x, 1300 + 64 + 1 - 1280);
+        assert!(viewport.follow(10, 1439, capture));
+        assert_eq!((viewport.x, viewport.y), (0, 640));
+
+        // The capture point under the viewport's corner shows at the
+        // desktop's corner, unscaled.
+        let map = viewport.map(1280, 800);
+        assert_eq!(map.to_source(0, 0), (0, 640));
+        assert!(map.as_letterbox(5120, 1440).is_none());
+    }
+
+    #[test]
+    fn viewport_centres_on_monitor() {
+        let capture = (4480, 1440);
+        let mut viewport = Viewport::default();
+        viewport.resize((1280, 800), capture);
+        viewport.centre_on(&captured(2560, 1920, 1080), capture);
+        assert_eq!((viewport.x, viewport.y), (2880, 140));
+        // Near the right edge the viewport stops at the capture.
+        viewport.resize((2000, 800), capture);
+        viewport.centre_on(&captured(2560, 1920, 1080), capture);
+        assert_eq!(viewport.x, 2480);
+    }
+
+    #[test]
+    fn single_client_monitor_shows_whole_capture() {
+        let layout = ClientLayout::single(1920, 1080);
+        let capture = [captured(0, 1920, 1080), captured(1920, 1920, 1080)];
+        let map = layout.map(&capture, 3840, 1080);
+        let letterbox = map.as_letterbox(3840, 1080).unwrap();
+        assert_eq!((letterbox.y, letterbox.height), (270, 540));
+    }
+}
@@ -1,17 +1,32 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use rdp_encode::{EncoderConfig, GstEncoder};
 
+mod audio_input;
 mod clipboard;
 mod config;
 mod dbus;
 mod egfx;
+mod hotkeys;
+mod layout;
+mod monitor_select;
+mod output;
+mod pacing;
+mod pointer_shape;
 mod server;
 mod sound;
 mod tls;
 
+/// Dynamic virtual channel processors registered on the RDP server.
+type DvcFactories = Vec<Box<dyn ironrdp_dvc::DvcProcessorFactory>>;
+
+/// Size of sources without one of their own: a virtual monitor until the
+/// client asks for its own size, and the synthetic test pattern.
+const DEFAULT_SOURCE_WIDTH: u16 = 1920;
+const DEFAULT_SOURCE_HEIGHT: u16 = 1080;
+
 /// RDP server for the COSMIC™ desktop environment.
 ///
 /// Allows remote access to COSMIC desktops using standard RDP clients
@@ -62,6 +77,15 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
     let mut cfg = load_and_merge_config(&cli)?;
 
+    // Unmute outputs a previous run left muted in redirect audio mode.
+    if let Some(marker) = mute_marker_path() {
+        rdp_capture::LocalOutputMute::recover(&marker);
+    }
+    // Put back an output mode a previous run left changed.
+    if let Some(marker) = output_marker_path() {
+        output::OutputResizer::recover(&marker).await;
+    }
+
     // Start D-Bus server for IPC with the settings UI.
     let dbus_state = rdp_dbus::server::RdpServerState::new(cfg.bind.to_string());
     let (_dbus_conn, mut dbus_cmd_rx) =
@@ -84,8 +108,15 @@ async fn main() -> Result<()> {
 
         let make_cliprdr = || -> Option<Box<dyn ironrdp_server::CliprdrServerFactory>> {
             if cfg.clipboard.enable {
-                tracing::info!("Clipboard sharing enabled");
-                Some(Box::new(clipboard::LocalClipboardFactory::new()))
+                tracing::info!(
+                    mirror_to_primary = cfg.clipboard.mirror_to_primary,
+                    offer_primary = cfg.clipboard.offer_primary,
+                    "Clipboard sharing enabled"
+                );
+                Some(Box::new(clipboard::LocalClipboardFactory::new(
+                    cfg.clipboard.mirror_to_primary,
+                    cfg.clipboard.offer_primary,
+                )))
             } else {
                 None
             }
@@ -96,17 +127,41 @@ async fn main() -> Result<()> {
                 tracing::info!(
                     channels = cfg.audio.channels,
                     sample_rate = cfg.audio.sample_rate,
+                    codec = %cfg.audio.codec,
+                    source = %cfg.audio.source,
+                    virtual_sink = cfg.audio.virtual_sink,
+                    mode = %cfg.audio.mode,
                     "Audio forwarding enabled (RDPSND)"
                 );
                 Some(Box::new(sound::PipeWireAudioFactory::new(
                     cfg.audio.channels,
                     cfg.audio.sample_rate,
+                    &cfg.audio.codec,
+                    cfg.audio.bitrate,
+                    sound::AudioRouting::new(
+                        &cfg.audio.source,
+                        cfg.audio.virtual_sink,
+                        &cfg.audio.mode,
+                        mute_marker_path(),
+                    ),
                 )))
             } else {
                 None
             }
         };
 
+        let make_dvcs = |egfx_factory: Option<egfx::EgfxBridgeFactory>| -> DvcFactories {
+            let mut factories = DvcFactories::new();
+            if let Some(factory) = egfx_factory {
+                factories.push(Box::new(factory));
+            }
+            if cfg.audio.microphone {
+                tracing::info!("Microphone redirection enabled (AUDIO_INPUT)");
+                factories.push(Box::new(audio_input::AudioInputFactory));
+            }
+            factories
+        };
+
         tracing::info!(bind = %cfg.bind, "Starting cosmic-ext-rdp-server");
         dbus_state.set_status(rdp_dbus::types::ServerStatus::Running).await;
 
@@ -116,7 +171,7 @@ async fn main() -> Result<()> {
                 egfx::create_egfx(1920, 1080);
             let rdp_server = server::build_server(
                 cfg.bind, &tls_ctx, auth.as_ref(), make_cliprdr(), make_sound(),
-                Some(Box::new(egfx_factory)),
+                make_dvcs(Some(egfx_factory)),
             );
             egfx_event_setter.set_event_sender(rdp_server.event_sender().clone());
             // Spawn background H.264 encoding task that sends a color test
@@ -124,10 +179,26 @@ async fn main() -> Result<()> {
             // AVC420. This allows testing the full encode→decode color
             // pipeline without needing live screen capture.
             tokio::spawn(static_egfx_task(egfx_controller, 1920, 1080));
-            run_with_shutdown(rdp_server, &mut dbus_cmd_rx).await
+            run_with_shutdown(rdp_server, &mut dbus_cmd_rx, None, None).await
+        } else if let Some(source) = headless_source(&cfg)? {
+            // Without a compositor there is nothing to inject input into.
+            let desktop = source.desktop();
+            let (width, height) = (desktop.width, desktop.height);
+            let mut live_display = server::LiveDisplay::new(source);
+            live_display.set_frame_rate(cfg.capture.fps);
+            let (egfx_factory, egfx_controller, egfx_event_setter) =
+                egfx::create_egfx(width, height);
+            live_display.set_egfx(egfx_controller);
+            let rdp_server = server::build_view_only_server(
+                cfg.bind, &tls_ctx, auth.as_ref(), live_display, make_cliprdr(), make_sound(),
+                make_dvcs(Some(egfx_factory)),
+            );
+            egfx_event_setter.set_event_sender(rdp_server.event_sender().clone());
+            run_with_shutdown(rdp_server, &mut dbus_cmd_rx, None, None).await
         } else {
             run_live_or_fallback(
-                &cfg, &tls_ctx, auth.as_ref(), &make_cliprdr, &make_sound, &mut dbus_cmd_rx,
+                &cfg, &tls_ctx, auth.as_ref(), &make_cliprdr, &make_sound, &make_dvcs,
+                &mut dbus_cmd_rx,
             )
             .await
         };
@@ -212,6 +283,33 @@ fn setup_auth(cfg: &config::ServerConfig) -> Result<Option<server::AuthCredentia
     }))
 }
 
+/// Frame source for `source = "synthetic"` or `"replay:<path>"`, which run
+/// without a compositor; `None` for the screen capture sources.
+fn headless_source(
+    cfg: &config::ServerConfig,
+) -> Result<Option<Box<dyn rdp_capture::FrameSource>>> {
+    let source = cfg.capture.source.as_str();
+    if source == "synthetic" {
+        tracing::info!("Serving a synthetic test pattern instead of the screen");
+        let synthetic = rdp_capture::SyntheticSource::new(
+            DEFAULT_SOURCE_WIDTH,
+            DEFAULT_SOURCE_HEIGHT,
+            cfg.capture.fps,
+        );
+        return Ok(Some(Box::new(synthetic)));
+    }
+    let Some(path) = source.strip_prefix("replay:") else {
+        return Ok(None);
+    };
+    let replay = rdp_capture::ReplaySource::open(
+        Path::new(path),
+        cfg.capture.fps,
+        cfg.capture.channel_capacity,
+    )
+    .with_context(|| format!("cannot replay {path}"))?;
+    Ok(Some(Box::new(replay)))
+}
+
 /// Reason the server shut down.
 enum ShutdownReason {
     /// Unix signal received.
@@ -229,13 +327,42 @@ async fn run_live_or_fallback(
     auth: Option<&server::AuthCredentials>,
     make_cliprdr: &dyn Fn() -> Option<Box<dyn ironrdp_server::CliprdrServerFactory>>,
     make_sound: &dyn Fn() -> Option<Box<dyn ironrdp_server::SoundServerFactory>>,
+    make_dvcs: &dyn Fn(Option<egfx::EgfxBridgeFactory>) -> DvcFactories,
     dbus_cmd_rx: &mut tokio::sync::mpsc::Receiver<rdp_dbus::server::DaemonCommand>,
 ) -> Result<ShutdownReason> {
     let restore_token = load_restore_token();
+    let source = match cfg.capture.source.as_str() {
+        "monitor" => rdp_capture::CaptureSource::Monitor,
+        // Created at the default size, then resized to the client's.
+        "virtual" => rdp_capture::CaptureSource::Virtual {
+            width: DEFAULT_SOURCE_WIDTH,
+            height: DEFAULT_SOURCE_HEIGHT,
+        },
+        other => {
+            tracing::warn!(source = other, "Unknown capture source, using monitor");
+            rdp_capture::CaptureSource::Monitor
+        }
+    };
+    let virtual_monitor = matches!(source, rdp_capture::CaptureSource::Virtual { .. });
+    let monitor_mode = if virtual_monitor {
+        if cfg.capture.multi_monitor || !cfg.capture.monitor.is_empty() {
+            tracing::warn!("Monitor selection is ignored with a virtual monitor");
+        }
+        rdp_capture::MonitorMode::Single
+    } else if cfg.capture.multi_monitor {
+        rdp_capture::MonitorMode::Merged
+    } else if cfg.capture.monitor.is_empty() {
+        rdp_capture::MonitorMode::Single
+    } else {
+        rdp_capture::MonitorMode::Switchable
+    };
     match rdp_capture::start_capture(
         restore_token.as_deref(),
         cfg.capture.channel_capacity,
         cfg.capture.swap_colors,
+        cfg.capture.fps,
+        monitor_mode,
+        source,
     )
     .await
     {
@@ -245,6 +372,16 @@ async fn run_live_or_fallback(
             if let Some(ref token) = desktop_info.restore_token {
                 save_restore_token(token);
             }
+            // Reconnecting after a capture failure starts a new portal
+            // session, which comes with a new token.
+            let mut restore_tokens = capture_handle.restore_token();
+            tokio::spawn(async move {
+                while restore_tokens.changed().await.is_ok() {
+                    if let Some(token) = restore_tokens.borrow_and_update().clone() {
+                        save_restore_token(&token);
+                    }
+                }
+            });
 
             tracing::info!(
                 width = desktop_info.width,
@@ -253,38 +390,129 @@ async fn run_live_or_fallback(
                 "Live screen capture active"
             );
 
-            let mut live_display = server::LiveDisplay::new(event_rx, &desktop_info);
+            let source = rdp_capture::PipeWireSource::new(event_rx, desktop_info.clone());
+            let mut live_display = server::LiveDisplay::new(Box::new(source));
+            live_display.set_frame_rate(cfg.capture.fps);
+            let virtual_output = capture_handle.virtual_output();
+            let mut origins = capture_handle.origin();
+            let capture_end = capture_handle.ended();
 
             // Create EGFX components for H.264 delivery via DVC.
             let (egfx_factory, egfx_controller, egfx_event_setter) =
                 egfx::create_egfx(desktop_info.width, desktop_info.height);
             live_display.set_egfx(egfx_controller);
 
-            let input_handler = match rdp_input::EiInput::new().await {
-                Ok(ei_input) => {
-                    tracing::info!("Input injection active (libei)");
-                    server::LiveInputHandler::new(ei_input)
+            // Needed to map input and cursors on scaled (HiDPI) outputs. A
+            // virtual monitor could be mistaken for a real one of its size.
+            let primary = &desktop_info.monitors[0];
+            if virtual_output.is_none() {
+                match output::captured_output_scale(primary.width, primary.height).await {
+                    Ok(scale) => live_display.set_output_scale(scale),
+                    Err(e) => tracing::debug!("Output scale unknown, assuming 1.0: {e:#}"),
                 }
+            }
+
+            match cfg.capture.display_mode.as_str() {
+                "fit" => {}
+                "viewport" => {
+                    tracing::info!("Viewport mode: the client sees a window that follows the pointer");
+                    live_display.enable_viewport();
+                }
+                other => tracing::warn!(mode = other, "Unknown display mode, using fit"),
+            }
+
+            // With a monitor configured the selector owns the capture, so
+            // it can restart it on another monitor.
+            let (mut monitor_selector, _capture) =
+                if monitor_mode == rdp_capture::MonitorMode::Switchable {
+                    let sources = desktop_info.sources.clone();
+                    let scale = live_display.desktop_scale();
+                    let mut selector =
+                        monitor_select::MonitorSelector::new(capture_handle, sources, scale).await;
+                    let request =
+                        monitor_select::MonitorRequest::Select(cfg.capture.monitor.clone());
+                    if let Err(e) = selector.handle(&request) {
+                        tracing::warn!("Serving the first selected monitor: {e:#}");
+                    }
+                    (Some(selector), None)
+                } else {
+                    (None, Some(capture_handle))
+                };
+
+            // Resizing one of several merged monitors would move the
+            // others within the capture, so only a single one is resized;
+            // a switchable capture may move away from the resized output.
+            // A virtual monitor always follows the client, and pointer
+            // input is bound to wherever the compositor placed it, which
+            // may change when the capture reconnects.
+            if let Some(virtual_output) = virtual_output {
+                let resizer = output::OutputResizer::virtual_output(virtual_output);
+                live_display.set_output_resizer(resizer);
+                live_display.set_input_origin(desktop_info.origin);
+                let scale = live_display.desktop_scale();
+                tokio::spawn(async move {
+                    while origins.changed().await.is_ok() {
+                        scale.set_input_origin(*origins.borrow_and_update());
+                    }
+                });
+            } else if cfg.capture.resize_output && desktop_info.monitors.len() > 1 {
+                tracing::warn!("Output resizing is not supported with multi-monitor capture");
+            } else if cfg.capture.resize_output && monitor_selector.is_some() {
+                tracing::warn!("Output resizing is not supported with monitor selection");
+            } else if cfg.capture.resize_output {
+                let (width, height) = (desktop_info.width, desktop_info.height);
+                match output::OutputResizer::start(width, height, output_marker_path()).await {
+                    Ok(resizer) => live_display.set_output_resizer(resizer),
+                    Err(e) => tracing::warn!("Output resizing unavailable: {e:#}"),
+                }
+            }
+
+            let ei_input = match rdp_input::EiInput::new().await {
+                Ok(ei_input) => Some(ei_input),
                 Err(e) => {
                     tracing::warn!("Failed to initialize input injection: {e}");
-                    tracing::warn!("Input events will be logged but not injected");
-                    let rdp_server = server::build_view_only_server(
-                        cfg.bind, tls_ctx, auth, live_display, make_cliprdr(), make_sound(),
-                    );
-                    let _capture = capture_handle;
-                    return run_with_shutdown(rdp_server, dbus_cmd_rx).await;
+                    None
                 }
             };
+            let Some(ei_input) = ei_input else {
+                tracing::warn!("Input events will be logged but not injected");
+                let rdp_server = server::build_view_only_server(
+                    cfg.bind, tls_ctx, auth, live_display, make_cliprdr(), make_sound(),
+                    make_dvcs(Some(egfx_factory)),
+                );
+                egfx_event_setter.set_event_sender(rdp_server.event_sender().clone());
+                return run_with_shutdown(
+                    rdp_server,
+                    dbus_cmd_rx,
+                    monitor_selector.as_mut(),
+                    Some(capture_end),
+                )
+                .await;
+            };
+            tracing::info!("Input injection active (libei)");
+            let mut input_handler =
+                server::LiveInputHandler::new(ei_input, live_display.desktop_scale());
+            if let Some(selector) = &monitor_selector {
+                input_handler.set_monitor_requests(selector.requests());
+            }
 
             let rdp_server = server::build_live_server(
                 cfg.bind, tls_ctx, auth, live_display, input_handler,
-                make_cliprdr(), make_sound(), Some(Box::new(egfx_factory)),
+                make_cliprdr(), make_sound(), make_dvcs(Some(egfx_factory)),
             );
             // Set the event sender so the EGFX controller can push
             // H.264 frames proactively via ServerEvent::DvcOutput.
             egfx_event_setter.set_event_sender(rdp_server.event_sender().clone());
-            let _capture = capture_handle;
-            run_with_shutdown(rdp_server, dbus_cmd_rx).await
+            run_with_shutdown(
+                rdp_server,
+                dbus_cmd_rx,
+                monitor_selector.as_mut(),
+                Some(capture_end),
+            )
+            .await
+        }
+        Err(rdp_capture::CaptureError::Portal(rdp_capture::PortalError::Cancelled)) => {
+            bail!("screen sharing was cancelled in the portal dialog")
         }
         Err(e) => {
             tracing::warn!("Failed to start screen capture: {e:#}");
@@ -293,50 +521,122 @@ async fn run_live_or_fallback(
                 egfx::create_egfx(1920, 1080);
             let rdp_server =
                 server::build_server(cfg.bind, tls_ctx, auth, make_cliprdr(), make_sound(),
-                    Some(Box::new(egfx_factory)));
+                    make_dvcs(Some(egfx_factory)));
             egfx_event_setter.set_event_sender(rdp_server.event_sender().clone());
-            run_with_shutdown(rdp_server, dbus_cmd_rx).await
+            run_with_shutdown(rdp_server, dbus_cmd_rx, None, None).await
         }
     }
 }
 
 /// Run the RDP server with graceful shutdown on `SIGINT` / `SIGTERM` or
 /// D-Bus commands.
+///
+/// Monitor selection requests (from D-Bus or the input handler) are
+/// carried out by `monitor_selector` while the server keeps running.
+///
+/// Fails if the screen capture behind `capture_end` ends for good, e.g.
+/// because the user stopped sharing.
 async fn run_with_shutdown(
     mut server: ironrdp_server::RdpServer,
     dbus_cmd_rx: &mut tokio::sync::mpsc::Receiver<rdp_dbus::server::DaemonCommand>,
+    mut monitor_selector: Option<&mut monitor_select::MonitorSelector>,
+    mut capture_end: Option<tokio::sync::watch::Receiver<Option<rdp_capture::CaptureEnd>>>,
 ) -> Result<ShutdownReason> {
     let mut sigterm =
         tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
             .context("failed to register SIGTERM handler")?;
+    let run = server.run();
+    tokio::pin!(run);
 
-    tokio::select! {
-        result = server.run() => {
-            result.context("RDP server error")?;
-            Ok(ShutdownReason::Stop)
-        }
-        result = tokio::signal::ctrl_c() => {
-            result.context("failed to listen for SIGINT")?;
-            tracing::info!("Received SIGINT, shutting down");
-            Ok(ShutdownReason::Signal)
+    loop {
+        let (request, reply) = tokio::select! {
+            result = &mut run => {
+                result.context("RDP server error")?;
+                return Ok(ShutdownReason::Stop);
+            }
+            result = tokio::signal::ctrl_c() => {
+                result.context("failed to listen for SIGINT")?;
+                tracing::info!("Received SIGINT, shutting down");
+                return Ok(ShutdownReason::Signal);
+            }
+            _ = sigterm.recv() => {
+                tracing::info!("Received SIGTERM, shutting down");
+                return Ok(ShutdownReason::Signal);
+            }
+            cmd = dbus_cmd_rx.recv() => {
+                match cmd {
+                    Some(rdp_dbus::server::DaemonCommand::Reload) => {
+                        tracing::info!("D-Bus: reload requested");
+                        return Ok(ShutdownReason::Reload);
+                    }
+                    Some(rdp_dbus::server::DaemonCommand::SelectMonitor { connector, reply }) => {
+                        tracing::info!(%connector, "D-Bus: monitor selection requested");
+                        (monitor_select::MonitorRequest::Select(connector), Some(reply))
+                    }
+                    Some(rdp_dbus::server::DaemonCommand::Stop) | None => {
+                        tracing::info!("D-Bus: stop requested");
+                        return Ok(ShutdownReason::Stop);
+                    }
+                }
+            }
+            end = capture_ended(capture_end.as_mut()) => {
+                return Err(anyhow::Error::new(end).context("screen capture ended"));
+            }
+            Some(request) = next_monitor_request(monitor_selector.as_deref_mut()) => (request, None),
+        };
+
+        let result = switch_monitor(monitor_selector.as_deref_mut(), &request);
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
         }
-        _ = sigterm.recv() => {
-            tracing::info!("Received SIGTERM, shutting down");
-            Ok(ShutdownReason::Signal)
+    }
+}
+
+/// Carry out a monitor request, logging why it failed.
+fn switch_monitor(
+    selector: Option<&mut monitor_select::MonitorSelector>,
+    request: &monitor_select::MonitorRequest,
+) -> Result<(), rdp_dbus::server::SelectMonitorError> {
+    use rdp_dbus::server::SelectMonitorError;
+
+    let Some(selector) = selector else {
+        tracing::warn!("Switching monitors requires `monitor` in the capture config");
+        return Err(SelectMonitorError::Unavailable);
+    };
+    selector.handle(request).map_err(|e| {
+        tracing::warn!("Cannot switch monitors: {e:#}");
+        match request {
+            monitor_select::MonitorRequest::Select(connector) if !selector.knows(connector) => {
+                SelectMonitorError::UnknownConnector(format!("{e:#}"))
+            }
+            _ => SelectMonitorError::Failed(format!("{e:#}")),
         }
-        cmd = dbus_cmd_rx.recv() => {
-            match cmd {
-                Some(rdp_dbus::server::DaemonCommand::Reload) => {
-                    tracing::info!("D-Bus: reload requested");
-                    Ok(ShutdownReason::Reload)
-                }
-                Some(rdp_dbus::server::DaemonCommand::Stop) | None => {
-                    tracing::info!("D-Bus: stop requested");
-                    Ok(ShutdownReason::Stop)
-                }
+    })
+}
+
+/// Next request for `selector`; never completes without one.
+async fn next_monitor_request(
+    selector: Option<&mut monitor_select::MonitorSelector>,
+) -> Option<monitor_select::MonitorRequest> {
+    match selector {
+        Some(selector) => selector.next_request().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Why the capture behind `capture_end` ended for good; never completes
+/// without one, or while it keeps running.
+async fn capture_ended(
+    capture_end: Option<&mut tokio::sync::watch::Receiver<Option<rdp_capture::CaptureEnd>>>,
+) -> rdp_capture::CaptureEnd {
+    if let Some(capture_end) = capture_end {
+        if let Ok(end) = capture_end.wait_for(Option::is_some).await {
+            if let Some(end) = *end {
+                return end;
             }
         }
     }
+    std::future::pending().await
 }
 
 /// Returns `true` if the address is a loopback address (`127.0.0.1`, `::1`).
@@ -354,6 +654,31 @@ fn restore_token_path() -> Option<PathBuf> {
         .map(|dir| PathBuf::from(dir).join("cosmic-ext-rdp-server").join("restore_token"))
 }
 
+/// Directory for state that must survive a logout or reboot.
+///
+/// `$XDG_STATE_HOME/cosmic-ext-rdp-server` (`~/.local/state` by default).
+/// Recovery markers live here rather than in `$XDG_RUNTIME_DIR`, because
+/// the settings they undo persist across a reboot while the runtime
+/// directory does not.
+fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir().map(|dir| dir.join("cosmic-ext-rdp-server"))
+}
+
+/// Path to the list of outputs muted in redirect audio mode.
+///
+/// It only exists while outputs are muted, so finding it at startup means
+/// the last run did not clean up.
+fn mute_marker_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("muted_outputs"))
+}
+
+/// Path to the original setting of an output resized for the client.
+///
+/// Like the mute marker, it only exists while the output is changed.
+fn output_marker_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("resized_output"))
+}
+
 /// Load a previously saved `ScreenCast` portal restore token.
 fn load_restore_token() -> Option<String> {
     let path = restore_token_path()?;
@@ -411,7 +736,7 @@ async fn static_egfx_task(
     let mut sent_count: u32 = 0;
 
     loop {
-        match encoder.encode_frame(&frame_data) {
+        match encoder.encode_frame(&frame_data, usize::from(width) * 4) {
             Ok(Some(h264_frame)) => {
                 if controller.send_frame(&h264_frame.data, width, height, timestamp_ms) {
                     sent_count += 1;
@@ -0,0 +1,158 @@
+//! Choosing which monitor is served.
+//!
+//! With a `monitor` configured, every monitor selected in the portal dialog
+//! is offered and one of them is served at a time. The served monitor can
+//! be changed at runtime over D-Bus (by connector name) or with the
+//! monitor shortcuts (see [`crate::hotkeys`]); the capture stream is then
+//! restarted on the new monitor and the session carries on.
+
+use anyhow::{bail, Context, Result};
+use rdp_capture::{CaptureHandle, MonitorInfo};
+use tokio::sync::mpsc;
+
+use crate::output::{self, OutputState};
+use crate::server::DesktopScale;
+
+/// A request to change the served monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorRequest {
+    /// Serve the monitor on this connector (e.g. "DP-1").
+    Select(String),
+    /// Serve the next (or previous) monitor, left to right.
+    Cycle { forward: bool },
+}
+
+/// Switches the capture between the monitors selected in the portal.
+pub struct MonitorSelector {
+    capture: CaptureHandle,
+    sources: Vec<MonitorInfo>,
+    /// Compositor output behind each source, where known.
+    outputs: Vec<Option<OutputState>>,
+    scale: DesktopScale,
+    tx: mpsc::UnboundedSender<MonitorRequest>,
+    rx: mpsc::UnboundedReceiver<MonitorRequest>,
+}
+
+impl MonitorSelector {
+    /// Take over a capture started with
+    /// [`MonitorMode::Switchable`](rdp_capture::MonitorMode::Switchable).
+    ///
+    /// `sources` are the monitors the session offers
+    /// ([`DesktopInfo::sources`](rdp_capture::DesktopInfo::sources)).
+    pub async fn new(
+        capture: CaptureHandle,
+        sources: Vec<MonitorInfo>,
+        scale: DesktopScale,
+    ) -> Self {
+        let outputs = match output::identify_sources(&sources).await {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                tracing::warn!("Cannot name the captured monitors: {e:#}");
+                vec![None; sources.len()]
+            }
+        };
+        for (source, output) in sources.iter().zip(&outputs) {
+            tracing::info!(
+                node_id = source.node_id,
+                width = source.width,
+                height = source.height,
+                output = output.as_ref().map_or("unknown", |o| o.name.as_str()),
+                "Monitor available"
+            );
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            capture,
+            sources,
+            outputs,
+            scale,
+            tx,
+            rx,
+        }
+    }
+
+    /// Sender for requests, picked up by [`next_request`](Self::next_request).
+    #[must_use]
+    pub fn requests(&self) -> mpsc::UnboundedSender<MonitorRequest> {
+        self.tx.clone()
+    }
+
+    /// Wait for the next request.
+    pub async fn next_request(&mut self) -> Option<MonitorRequest> {
+        self.rx.recv().await
+    }
+
+    /// Carry out a request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the monitor is unknown or the capture cannot be
+    /// switched; the current monitor is then still served, unless the new
+    /// stream failed to start.
+    pub fn handle(&mut self, request: &MonitorRequest) -> Result<()> {
+        let index = match request {
+            MonitorRequest::Select(connector) => self.find(connector)?,
+            MonitorRequest::Cycle { forward } => self.cycle(*forward),
+        };
+        self.serve(index)
+    }
+
+    /// Whether one of the captured monitors is on `connector`.
+    #[must_use]
+    pub fn knows(&self, connector: &str) -> bool {
+        self.position(connector).is_some()
+    }
+
+    /// Index of the source showing `connector`.
+    fn find(&self, connector: &str) -> Result<usize> {
+        if let Some(index) = self.position(connector) {
+            return Ok(index);
+        }
+        let known: Vec<&str> = self
+            .outputs
+            .iter()
+            .flatten()
+            .map(|o| o.name.as_str())
+            .collect();
+        bail!(
+            "monitor {connector} is not among the captured ones ({})",
+            known.join(", ")
+        )
+    }
+
+    /// Index of the source on `connector`, if any.
+    fn position(&self, connector: &str) -> Option<usize> {
+        self.outputs
+            .iter()
+            .position(|o| o.as_ref().is_some_and(|o| o.name == connector))
+    }
+
+    /// Index of the source after (or before) the served one, left to right.
+    fn cycle(&self, forward: bool) -> usize {
+        let mut order: Vec<usize> = (0..self.sources.len()).collect();
+        order.sort_by_key(|&i| (self.sources[i].x, self.sources[i].y));
+        let current = self.capture.current_source().unwrap_or(0);
+        let at = order.iter().position(|&i| i == current).unwrap_or(0);
+        let count = order.len();
+        let next = if forward { at + 1 } else { at + count - 1 };
+        order[next % count]
+    }
+
+    /// Serve source `index` and update the scaling to match; the capture
+    /// is left alone if it already serves it.
+    fn serve(&mut self, index: usize) -> Result<()> {
+        let monitor = self
+            .capture
+            .switch_to(index)
+            .context("failed to switch the captured monitor")?;
+        let output = self.outputs[index].as_ref();
+        self.scale
+            .serve_monitor(monitor, output.map(|o| o.scale_percent));
+        tracing::info!(
+            output = output.map_or("unknown", |o| o.name.as_str()),
+            "Serving monitor"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,893 @@
+//! Resizing the real compositor output to match the RDP client.
+//!
+//! Without this, the captured monitor keeps its physical resolution and a
+//! small client receives a downscaled copy of a large desktop. When
+//! enabled, the captured output is switched to the advertised mode that
+//! best fits the client's requested size, and its original mode is put
+//! back when the client disconnects or the server exits. The client's
+//! desktop scale factor is applied as the output scale alongside, so
+//! clients on high-density displays get legibly sized UI, and a rotated
+//! (portrait) client monitor rotates the output to match.
+//!
+//! Modes are changed through `cosmic-randr`, which drives COSMIC's
+//! output-management protocol. Commands run on a dedicated thread, in
+//! order, so a restore never overtakes a pending resize. Queries made from
+//! async code run on the blocking pool, so a slow or hung compositor does
+//! not stall the runtime.
+//!
+//! A virtual monitor (see [`rdp_capture::CaptureSource::Virtual`]) has no
+//! modes; it is resized by asking its stream for the new size instead.
+//!
+//! The compositor keeps whatever mode was set last, so a crash while the
+//! output is resized would leave it at the client's size. The original
+//! setting is therefore written to a marker file before the first change,
+//! and [`OutputResizer::recover`] puts it back on the next start.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+
+use anyhow::{bail, Context, Result};
+
+use rdp_capture::{MonitorInfo, VirtualOutput};
+
+use crate::layout::Rotation;
+
+/// Command-line tool used to query and change output modes.
+const RANDR: &str = "cosmic-randr";
+
+/// A display mode advertised by an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in mHz.
+    pub refresh_mhz: u32,
+}
+
+/// State of one compositor output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputState {
+    /// Connector name (e.g. "eDP-1").
+    pub name: String,
+    pub enabled: bool,
+    pub current: Option<Mode>,
+    pub modes: Vec<Mode>,
+    /// Output scale in percent (100 = 1.0).
+    pub scale_percent: u32,
+    pub transform: Transform,
+    /// Position in the compositor's (logical) layout.
+    pub position: (i32, i32),
+}
+
+/// Output transform, as named by `cosmic-randr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl Transform {
+    const NAMES: [(Self, &'static str); 8] = [
+        (Self::Normal, "normal"),
+        (Self::Rotate90, "rotate90"),
+        (Self::Rotate180, "rotate180"),
+        (Self::Rotate270, "rotate270"),
+        (Self::Flipped, "flipped"),
+        (Self::Flipped90, "flipped90"),
+        (Self::Flipped180, "flipped180"),
+        (Self::Flipped270, "flipped270"),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(t, _)| *t == self)
+            .map_or("normal", |(_, name)| name)
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(_, n)| *n == name).map(|(t, _)| *t)
+    }
+
+    /// Whether the mode's width and height are swapped on screen.
+    fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Flipped90 | Self::Flipped270
+        )
+    }
+
+    /// On-screen size of `mode` under this transform.
+    fn oriented(self, mode: Mode) -> (u32, u32) {
+        if self.swaps_axes() {
+            (mode.height, mode.width)
+        } else {
+            (mode.width, mode.height)
+        }
+    }
+}
+
+impl From<Rotation> for Transform {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Normal => Self::Normal,
+            Rotation::Rotate90 => Self::Rotate90,
+            Rotation::Rotate180 => Self::Rotate180,
+            Rotation::Rotate270 => Self::Rotate270,
+        }
+    }
+}
+
+/// Request for the resize thread.
+enum Request {
+    Resize {
+        width: u32,
+        height: u32,
+        scale_percent: Option<u32>,
+        rotation: Rotation,
+    },
+    Restore,
+}
+
+/// Mode, scale and transform applied to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Setting {
+    mode: Mode,
+    scale_percent: u32,
+    transform: Transform,
+}
+
+/// Scale (in percent) of the output being captured.
+///
+/// The output is identified as in [`OutputResizer::start`].
+///
+/// # Errors
+///
+/// Returns an error if `cosmic-randr` is unavailable or the captured
+/// output cannot be identified unambiguously.
+pub async fn captured_output_scale(capture_width: u16, capture_height: u16) -> Result<u32> {
+    let outputs = off_runtime(list_outputs).await?;
+    let output = pick_output(&outputs, u32::from(capture_width), u32::from(capture_height))?;
+    Ok(output.scale_percent)
+}
+
+/// The compositor output behind each of the portal's `sources`, if it
+/// can be identified (see [`match_sources`]).
+///
+/// # Errors
+///
+/// Returns an error if `cosmic-randr` is unavailable.
+pub async fn identify_sources(sources: &[MonitorInfo]) -> Result<Vec<Option<OutputState>>> {
+    let outputs = off_runtime(list_outputs).await?;
+    Ok(match_sources(&outputs, sources)
+        .into_iter()
+        .map(Option::<&OutputState>::cloned)
+        .collect())
+}
+
+/// Handle that changes the captured output's mode.
+///
+/// Dropping this restores the original mode and joins the thread.
+#[derive(Debug)]
+pub struct OutputResizer {
+    output: String,
+    scale_percent: u32,
+    backend: Backend,
+}
+
+/// How the output is resized.
+#[derive(Debug)]
+enum Backend {
+    /// Modes are set with `cosmic-randr` on the resize thread.
+    Randr {
+        tx: Option<mpsc::Sender<Request>>,
+        thread: Option<std::thread::JoinHandle<()>>,
+    },
+    /// A virtual monitor takes the size its stream asks for.
+    Virtual {
+        output: VirtualOutput,
+        original: (u16, u16),
+    },
+}
+
+impl OutputResizer {
+    /// Find the output being captured and start the resize thread.
+    ///
+    /// With a single enabled output that one is used; otherwise the output
+    /// whose current mode matches the capture size (in physical or logical
+    /// pixels) is picked.
+    ///
+    /// If `marker` is set, the original setting is persisted there while
+    /// the output is changed, for [`recover`](Self::recover).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cosmic-randr` is unavailable or the captured
+    /// output cannot be identified unambiguously.
+    pub async fn start(
+        capture_width: u16,
+        capture_height: u16,
+        marker: Option<PathBuf>,
+    ) -> Result<Self> {
+        let outputs = off_runtime(list_outputs).await?;
+        let output = pick_output(&outputs, u32::from(capture_width), u32::from(capture_height))?
+            .clone();
+        let Some(mode) = output.current else {
+            bail!("output {} has no current mode", output.name);
+        };
+        let original = Setting {
+            mode,
+            scale_percent: output.scale_percent,
+            transform: output.transform,
+        };
+
+        tracing::info!(
+            output = %output.name,
+            width = mode.width,
+            height = mode.height,
+            refresh_mhz = mode.refresh_mhz,
+            scale_percent = output.scale_percent,
+            transform = output.transform.name(),
+            "Output resizing enabled"
+        );
+
+        let name = output.name.clone();
+        let scale_percent = output.scale_percent;
+        let (tx, rx) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("output-resize".into())
+            .spawn(move || run_resize_loop(&output, original, marker.as_deref(), &rx))
+            .context("failed to spawn output resize thread")?;
+
+        Ok(Self {
+            output: name,
+            scale_percent,
+            backend: Backend::Randr {
+                tx: Some(tx),
+                thread: Some(thread),
+            },
+        })
+    }
+
+    /// Resize a virtual monitor to follow the client. Its scale is left to
+    /// the compositor.
+    #[must_use]
+    pub fn virtual_output(output: VirtualOutput) -> Self {
+        let original = output.size();
+        tracing::info!(
+            width = original.0,
+            height = original.1,
+            "Virtual monitor follows the client's size"
+        );
+        Self {
+            output: "virtual".into(),
+            scale_percent: 100,
+            backend: Backend::Virtual { output, original },
+        }
+    }
+
+    /// Connector name of the output being resized.
+    #[must_use]
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Output scale (in percent) once a resize asking for `requested`
+    /// has been applied.
+    #[must_use]
+    pub fn applied_scale(&self, requested: Option<u32>) -> u32 {
+        match self.backend {
+            Backend::Randr { .. } => requested.unwrap_or(self.scale_percent),
+            Backend::Virtual { .. } => self.scale_percent,
+        }
+    }
+
+    /// Switch the output to the mode that best fits `width` x `height`
+    /// once rotated by `rotation`, and to `scale_percent` if given
+    /// (otherwise the original scale).
+    ///
+    /// A virtual monitor is resized to exactly `width` x `height`.
+    #[allow(clippy::cast_possible_truncation)] // clamped to u16
+    pub fn resize(&self, width: u32, height: u32, scale_percent: Option<u32>, rotation: Rotation) {
+        if let Backend::Virtual { output, .. } = &self.backend {
+            let clamp = |v: u32| v.clamp(1, u32::from(u16::MAX)) as u16;
+            output.resize(clamp(width), clamp(height));
+            return;
+        }
+        self.send(Request::Resize {
+            width,
+            height,
+            scale_percent,
+            rotation,
+        });
+    }
+
+    /// Put the original mode back (e.g. when the client disconnects).
+    pub fn restore(&self) {
+        if let Backend::Virtual { output, original } = &self.backend {
+            output.resize(original.0, original.1);
+            return;
+        }
+        self.send(Request::Restore);
+    }
+
+    fn send(&self, request: Request) {
+        if let Backend::Randr { tx: Some(tx), .. } = &self.backend {
+            let _ = tx.send(request);
+        }
+    }
+
+    /// Restore the output setting left changed by a previous run that did
+    /// not shut down cleanly, then remove the marker.
+    ///
+    /// Does nothing if `marker` does not exist.
+    pub async fn recover(marker: &Path) {
+        let Ok(contents) = std::fs::read_to_string(marker) else {
+            return;
+        };
+        match parse_marker(&contents) {
+            Some((name, setting)) => {
+                tracing::warn!(
+                    output = %name,
+                    width = setting.mode.width,
+                    height = setting.mode.height,
+                    scale_percent = setting.scale_percent,
+                    "Previous session left the output resized, restoring"
+                );
+                let output = name.clone();
+                if let Err(e) = off_runtime(move || set_mode(&output, setting)).await {
+                    tracing::warn!(output = %name, "Failed to restore output mode: {e:#}");
+                }
+            }
+            None => tracing::warn!(path = %marker.display(), "Ignoring malformed output marker"),
+        }
+        if let Err(e) = std::fs::remove_file(marker) {
+            tracing::warn!(path = %marker.display(), "Failed to remove output marker: {e}");
+        }
+    }
+}
+
+impl Drop for OutputResizer {
+    fn drop(&mut self) {
+        self.restore();
+        if let Backend::Randr { tx, thread } = &mut self.backend {
+            // Closing the channel ends the thread once the restore has run.
+            // Waiting for that must not hold up an async runtime; one
+            // finishes its blocking tasks before shutting down.
+            *tx = None;
+            if let Some(handle) = thread.take() {
+                match tokio::runtime::Handle::try_current() {
+                    Ok(runtime) => drop(runtime.spawn_blocking(move || handle.join())),
+                    Err(_) => {
+                        let _ = handle.join();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Apply requests in order until the handle is dropped.
+///
+/// `marker`, if set, holds the original setting whenever the output is
+/// changed from it.
+fn run_resize_loop(
+    output: &OutputState,
+    original: Setting,
+    marker: Option<&Path>,
+    rx: &mpsc::Receiver<Request>,
+) {
+    let mut applied = original;
+
+    while let Ok(request) = rx.recv() {
+        let target = match request {
+            Request::Resize {
+                width,
+                height,
+                scale_percent,
+                rotation,
+            } => {
+                // Modes are listed unrotated; a portrait monitor needs a
+                // mode fitting the swapped size.
+                let transform = Transform::from(rotation);
+                let (width, height) = if transform.swaps_axes() {
+                    (height, width)
+                } else {
+                    (width, height)
+                };
+                let refresh_mhz = original.mode.refresh_mhz;
+                let Some(mode) = choose_mode(&output.modes, width, height, refresh_mhz) else {
+                    tracing::warn!(output = %output.name, "Output advertises no modes");
+                    continue;
+                };
+                Setting {
+                    mode,
+                    scale_percent: scale_percent.unwrap_or(original.scale_percent),
+                    transform,
+                }
+            }
+            Request::Restore => original,
+        };
+
+        if target == applied {
+            continue;
+        }
+        if let (true, Some(marker)) = (applied == original, marker) {
+            write_marker(marker, &output.name, original);
+        }
+        match set_mode(&output.name, target) {
+            Ok(()) => {
+                tracing::info!(
+                    output = %output.name,
+                    width = target.mode.width,
+                    height = target.mode.height,
+                    refresh_mhz = target.mode.refresh_mhz,
+                    scale_percent = target.scale_percent,
+                    transform = target.transform.name(),
+                    restored = target == original,
+                    "Output mode changed"
+                );
+                applied = target;
+            }
+            Err(e) => tracing::warn!(output = %output.name, "Failed to change output mode: {e:#}"),
+        }
+        if let (true, Some(marker)) = (applied == original, marker) {
+            let _ = std::fs::remove_file(marker);
+        }
+    }
+}
+
+/// Record `original` as the setting to restore on `name`.
+fn write_marker(marker: &Path, name: &str, original: Setting) {
+    if let Some(dir) = marker.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(e) = std::fs::write(marker, marker_line(name, original)) {
+        tracing::warn!(path = %marker.display(), "Failed to write output marker: {e}");
+    }
+}
+
+/// Marker contents: the output name, mode, scale and transform.
+fn marker_line(name: &str, setting: Setting) -> String {
+    let Setting {
+        mode,
+        scale_percent,
+        transform,
+    } = setting;
+    format!(
+        "{name} {} {} {} {scale_percent} {}\n",
+        mode.width,
+        mode.height,
+        mode.refresh_mhz,
+        transform.name()
+    )
+}
+
+/// Parse what [`marker_line`] wrote.
+fn parse_marker(contents: &str) -> Option<(String, Setting)> {
+    let mut tokens = contents.split_whitespace();
+    let name = tokens.next()?.to_string();
+    let mut number = || tokens.next()?.parse::<u32>().ok();
+    let mode = Mode {
+        width: number()?,
+        height: number()?,
+        refresh_mhz: number()?,
+    };
+    let scale_percent = number()?;
+    let transform = Transform::parse(tokens.next()?)?;
+    Some((
+        name,
+        Setting {
+            mode,
+            scale_percent,
+            transform,
+        },
+    ))
+}
+
+/// Run blocking `cosmic-randr` work on the blocking pool.
+async fn off_runtime<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .with_context(|| format!("{RANDR} task failed"))?
+}
+
+/// Query all outputs and their modes.
+fn list_outputs() -> Result<Vec<OutputState>> {
+    let output = Command::new(RANDR)
+        .args(["list", "--kdl"])
+        .output()
+        .with_context(|| format!("failed to run {RANDR}"))?;
+    if !output.status.success() {
+        bail!(
+            "{RANDR} list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_outputs(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Apply `setting` to the output named `name`.
+fn set_mode(name: &str, setting: Setting) -> Result<()> {
+    let Setting {
+        mode,
+        scale_percent,
+        transform,
+    } = setting;
+    let refresh = format!("{:.3}", f64::from(mode.refresh_mhz) / 1000.0);
+    let scale = format!("{:.2}", f64::from(scale_percent) / 100.0);
+    let output = Command::new(RANDR)
+        .args(["mode", "--refresh", &refresh, "--scale", &scale])
+        .args(["--transform", transform.name(), name])
+        .arg(mode.width.to_string())
+        .arg(mode.height.to_string())
+        .output()
+        .with_context(|| format!("failed to run {RANDR}"))?;
+    if !output.status.success() {
+        bail!(
+            "{RANDR} mode failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Parse `cosmic-randr list --kdl` output.
+///
+/// Only the parts needed here are read: output names, the enabled flag,
+/// the position, the scale, the transform and the mode list. Both `#true` (KDL v2) and `true` booleans
+/// are accepted.
+fn parse_outputs(kdl: &str) -> Vec<OutputState> {
+    let mut outputs: Vec<OutputState> = Vec::new();
+
+    for line in kdl.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("output") => {
+                let Some(name) = tokens.next().map(|t| t.trim_matches('"')) else {
+                    continue;
+                };
+                let enabled = tokens.any(|t| is_true_flag(t, "enabled"));
+                outputs.push(OutputState {
+                    name: name.to_string(),
+                    enabled,
+                    current: None,
+                    modes: Vec::new(),
+                    scale_percent: 100,
+                    transform: Transform::Normal,
+                    position: (0, 0),
+                });
+            }
+            Some("transform") => {
+                let transform = tokens.next().and_then(|t| Transform::parse(t.trim_matches('"')));
+                if let (Some(output), Some(transform)) = (outputs.last_mut(), transform) {
+                    output.transform = transform;
+                }
+            }
+            Some("position") => {
+                let numbers: Vec<i32> = tokens.take(2).filter_map(|t| t.parse().ok()).collect();
+                if let (Some(output), &[x, y]) = (outputs.last_mut(), &numbers[..]) {
+                    output.position = (x, y);
+                }
+            }
+            Some("scale") => {
+                let scale = tokens.next().and_then(|t| t.parse::<f64>().ok());
+                if let (Some(output), Some(scale)) = (outputs.last_mut(), scale) {
+                    output.scale_percent = scale_to_percent(scale);
+                }
+            }
+            Some("mode") => {
+                let Some(output) = outputs.last_mut() else {
+                    continue;
+                };
+                let numbers: Vec<u32> = tokens
+                    .clone()
+                    .take(3)
+                    .filter_map(|t| t.parse().ok())
+                    .collect();
+                let [width, height, refresh_mhz] = numbers[..] else {
+                    continue;
+                };
+                let mode = Mode {
+                    width,
+                    height,
+                    refresh_mhz,
+                };
+                if tokens.any(|t| is_true_flag(t, "current")) {
+                    output.current = Some(mode);
+                }
+                output.modes.push(mode);
+            }
+            _ => {}
+        }
+    }
+
+    outputs
+}
+
+/// Convert a fractional scale (e.g. 1.25) to percent.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped
+fn scale_to_percent(scale: f64) -> u32 {
+    (scale * 100.0).round().clamp(100.0, 500.0) as u32
+}
+
+/// Whether `token` is `key=true` or `key=#true`.
+fn is_true_flag(token: &str, key: &str) -> bool {
+    token
+        .strip_prefix(key)
+        .and_then(|rest| rest.strip_prefix('='))
+        .is_some_and(|value| value.trim_start_matches('#') == "true")
+}
+
+/// Identify the captured output among `outputs`.
+fn pick_output(outputs: &[OutputState], width: u32, height: u32) -> Result<&OutputState> {
+    let enabled: Vec<&OutputState> = outputs.iter().filter(|o| o.enabled).collect();
+    match enabled[..] {
+        [] => bail!("no enabled outputs found"),
+        [only] => Ok(only),
+        _ => {
+            let mut matching = enabled.iter().filter(|o| o.shows(width, height));
+            match (matching.next(), matching.next()) {
+                (Some(output), None) => Ok(output),
+                _ => bail!(
+                    "cannot tell which of {} enabled outputs is captured ({width}x{height})",
+                    enabled.len()
+                ),
+            }
+        }
+    }
+}
+
+/// Pair each portal source with the enabled output it shows.
+///
+/// Sources are matched by position, both layouts being shifted to start at
+/// the origin; this only lines up when every output was selected in the
+/// portal, so a source whose size matches exactly one output is accepted
+/// as well.
+fn match_sources<'a>(
+    outputs: &'a [OutputState],
+    sources: &[MonitorInfo],
+) -> Vec<Option<&'a OutputState>> {
+    let enabled: Vec<&OutputState> = outputs.iter().filter(|o| o.enabled).collect();
+    let min_x = enabled.iter().map(|o| o.position.0).min().unwrap_or(0);
+    let min_y = enabled.iter().map(|o| o.position.1).min().unwrap_or(0);
+
+    sources
+        .iter()
+        .map(|source| {
+            let (width, height) = (u32::from(source.width), u32::from(source.height));
+            let placed = enabled.iter().find(|o| {
+                (o.position.0 - min_x, o.position.1 - min_y) == (source.x, source.y)
+                    && o.shows(width, height)
+            });
+            placed.or_else(|| {
+                let mut sized = enabled.iter().filter(|o| o.shows(width, height));
+                match (sized.next(), sized.next()) {
+                    (Some(output), None) => Some(output),
+                    _ => None,
+                }
+            })
+            .copied()
+        })
+        .collect()
+}
+
+impl OutputState {
+    /// Whether a capture of `width` x `height` could come from this output.
+    ///
+    /// The portal may report the size in physical or logical (scaled)
+    /// pixels, so either is accepted. Rotated outputs are captured rotated.
+    fn shows(&self, width: u32, height: u32) -> bool {
+        self.current.is_some_and(|m| {
+            let (w, h) = self.transform.oriented(m);
+            let logical = (w * 100 / self.scale_percent, h * 100 / self.scale_percent);
+            (w, h) == (width, height) || logical == (width, height)
+        })
+    }
+}
+
+/// Pick the advertised mode that best fits `width` x `height`.
+///
+/// Prefers the largest mode that fits inside the requested size, then the
+/// refresh rate closest to `refresh_mhz`. If nothing fits, the smallest
+/// mode is used so the client still gets the least downscaling.
+fn choose_mode(modes: &[Mode], width: u32, height: u32, refresh_mhz: u32) -> Option<Mode> {
+    let area = |m: &Mode| u64::from(m.width) * u64::from(m.height);
+    let refresh_distance = |m: &Mode| m.refresh_mhz.abs_diff(refresh_mhz);
+
+    let fitting = modes
+        .iter()
+        .filter(|m| m.width <= width && m.height <= height)
+        .max_by(|a, b| {
+            area(a)
+                .cmp(&area(b))
+                .then(refresh_distance(b).cmp(&refresh_distance(a)))
+        });
+
+    fitting
+        .or_else(|| {
+            modes.iter().min_by(|a, b| {
+                area(a)
+                    .cmp(&area(b))
+                    .then(refresh_distance(a).cmp(&refresh_distance(b)))
+            })
+        })
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: &str = r#"
+output "eDP-1" enabled=#true {
+    description make="BOE" model="0x0BCA"
+    physical 309 174
+    position 0 0
+    scale 2.0
+    transform "normal"
+    modes {
+        mode 3840 2160 60000 current=#true preferred=#true
+        mode 2560 1440 60000
+        mode 1920 1080 60000
+        mode 1920 1080 48000
+        mode 1280 720 60000
+    }
+}
+output "HDMI-A-1" enabled=false {
+    modes {
+        mode 1920 1080 60000 preferred=true
+    }
+}
+"#;
+
+    fn mode(width: u32, height: u32, refresh_mhz: u32) -> Mode {
+        Mode {
+            width,
+            height,
+            refresh_mhz,
+        }
+    }
+
+    #[test]
+    fn parses_outputs_and_modes() {
+        let outputs = parse_outputs(LIST);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].name, "eDP-1");
+        assert!(outputs[0].enabled);
+        assert_eq!(outputs[0].current, Some(mode(3840, 2160, 60000)));
+        assert_eq!(outputs[0].modes.len(), 5);
+        assert_eq!(outputs[0].scale_percent, 200);
+        assert_eq!(outputs[1].name, "HDMI-A-1");
+        assert!(!outputs[1].enabled);
+        assert_eq!(outputs[1].current, None);
+        assert_eq!(outputs[1].scale_percent, 100);
+    }
+
+    #[test]
+    fn parses_fractional_scale() {
+        let outputs = parse_outputs("output \"DP-1\" enabled=#true {\n    scale 1.25\n}\n");
+        assert_eq!(outputs[0].scale_percent, 125);
+        assert_eq!(scale_to_percent(0.5), 100);
+    }
+
+    #[test]
+    fn parses_transform() {
+        let outputs = parse_outputs("output \"DP-1\" enabled=#true {\n    transform \"rotate90\"\n}\n");
+        assert_eq!(outputs[0].transform, Transform::Rotate90);
+        assert_eq!(parse_outputs(LIST)[0].transform, Transform::Normal);
+        assert_eq!(Transform::parse("flipped270"), Some(Transform::Flipped270));
+        assert_eq!(Transform::Flipped90.name(), "flipped90");
+    }
+
+    #[test]
+    fn rotated_output_matches_swapped_capture() {
+        let mut outputs = parse_outputs(LIST);
+        outputs[1].enabled = true;
+        outputs[1].current = Some(mode(1920, 1080, 60000));
+        outputs[1].transform = Transform::Rotate270;
+        assert_eq!(pick_output(&outputs, 1080, 1920).unwrap().name, "HDMI-A-1");
+        assert_eq!(pick_output(&outputs, 1920, 1080).unwrap().name, "eDP-1");
+    }
+
+    #[test]
+    fn picks_single_enabled_output() {
+        let outputs = parse_outputs(LIST);
+        let picked = pick_output(&outputs, 1, 1).unwrap();
+        assert_eq!(picked.name, "eDP-1");
+    }
+
+    #[test]
+    fn ambiguous_outputs_are_rejected() {
+        let mut outputs = parse_outputs(LIST);
+        outputs[1].enabled = true;
+        outputs[1].current = Some(mode(3840, 2160, 60000));
+        assert!(pick_output(&outputs, 3840, 2160).is_err());
+        outputs[1].current = Some(mode(2560, 1440, 60000));
+        assert_eq!(pick_output(&outputs, 2560, 1440).unwrap().name, "HDMI-A-1");
+        // eDP-1 is 3840x2160 at scale 2, i.e. 1920x1080 logical.
+        assert_eq!(pick_output(&outputs, 1920, 1080).unwrap().name, "eDP-1");
+    }
+
+    #[test]
+    fn sources_match_outputs_by_position() {
+        let list = "output \"DP-1\" enabled=#true {\n    position 1920 0\n    modes {\n        mode 1920 1080 60000 current=#true\n    }\n}\n";
+        let mut outputs = parse_outputs(LIST);
+        outputs.extend(parse_outputs(list));
+        assert_eq!(outputs[2].position, (1920, 0));
+
+        let source = |x: i32, width: u16, height: u16| MonitorInfo {
+            node_id: 0,
+            width,
+            height,
+            x,
+            y: 0,
+        };
+        // Both 1920x1080 (eDP-1 in logical pixels); told apart by position.
+        let sources = [source(1920, 1920, 1080), source(0, 1920, 1080)];
+        let names: Vec<_> = match_sources(&outputs, &sources)
+            .into_iter()
+            .map(|o| o.map(|o| o.name.as_str()))
+            .collect();
+        assert_eq!(names, [Some("DP-1"), Some("eDP-1")]);
+
+        // A lone source matches by size, unless that is ambiguous.
+        let names: Vec<_> = match_sources(&outputs, &[source(0, 3840, 2160), source(0, 1920, 1080)])
+            .into_iter()
+            .map(|o| o.map(|o| o.name.as_str()))
+            .collect();
+        assert_eq!(names, [Some("eDP-1"), Some("eDP-1")]);
+        assert_eq!(match_sources(&outputs, &[source(500, 1920, 1080)]), [None]);
+    }
+
+    #[test]
+    fn marker_round_trips() {
+        let setting = Setting {
+            mode: mode(2560, 1440, 59951),
+            scale_percent: 125,
+            transform: Transform::Rotate90,
+        };
+        let line = marker_line("DP-1", setting);
+        assert_eq!(line, "DP-1 2560 1440 59951 125 rotate90\n");
+        assert_eq!(parse_marker(&line), Some(("DP-1".to_string(), setting)));
+        assert_eq!(parse_marker("DP-1 2560 1440"), None);
+        assert_eq!(parse_marker(""), None);
+    }
+
+    #[test]
+    fn chooses_largest_fitting_mode() {
+        let modes = &parse_outputs(LIST)[0].modes;
+        assert_eq!(
+            choose_mode(modes, 1366, 768, 60000),
+            Some(mode(1280, 720, 60000))
+        );
+        assert_eq!(
+            choose_mode(modes, 1920, 1200, 60000),
+            Some(mode(1920, 1080, 60000))
+        );
+        assert_eq!(
+            choose_mode(modes, 1920, 1080, 48000),
+            Some(mode(1920, 1080, 48000))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_smallest_mode() {
+        let modes = &parse_outputs(LIST)[0].modes;
+        assert_eq!(
+            choose_mode(modes, 800, 600, 60000),
+            Some(mode(1280, 720, 60000))
+        );
+        assert_eq!(choose_mode(&[], 800, 600, 60000), None);
+    }
+}
@@ -0,0 +1,141 @@
+//! Frame pacing.
+//!
+//! Captured frames are delivered at most at the configured frame rate:
+//! a frame arriving too early is held back and replaced by any newer one,
+//! then sent when its slot comes up, so the client always ends up with
+//! the latest picture. Frames the compositor marks as unchanged (empty
+//! damage) are not sent at all; instead the last frame is repeated every
+//! [`KEEPALIVE_INTERVAL`] while the desktop is idle.
+
+use std::time::{Duration, Instant};
+
+/// How often the last frame is repeated while nothing changes.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What is due once [`FramePacer::deadline`] has passed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Due<T> {
+    /// A held-back frame may now be sent.
+    Frame(T),
+    /// Nothing was sent for a while; repeat the last frame.
+    Keepalive,
+}
+
+/// Limits the frame rate and schedules keepalive repaints.
+#[derive(Debug)]
+pub struct FramePacer<T> {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    held: Option<T>,
+}
+
+impl<T> FramePacer<T> {
+    /// Pace frames to at most `fps` per second (at least one).
+    #[must_use]
+    pub fn new(fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1),
+            last_sent: None,
+            held: None,
+        }
+    }
+
+    /// Offer a captured frame, `changed` unless its damage is empty.
+    ///
+    /// Returns the frame if it may be sent now; otherwise it is held back
+    /// (if changed) until [`poll`](Self::poll) releases it.
+    pub fn offer(&mut self, frame: T, changed: bool, now: Instant) -> Option<T> {
+        if !changed {
+            return None;
+        }
+        if self.next_slot().is_some_and(|slot| now < slot) {
+            self.held = Some(frame);
+            return None;
+        }
+        self.held = None;
+        Some(frame)
+    }
+
+    /// Drop a held-back frame, if any.
+    pub fn discard(&mut self) {
+        self.held = None;
+    }
+
+    /// Record that a frame was sent, whatever its origin.
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = Some(now);
+    }
+
+    /// When [`poll`](Self::poll) next has something to return.
+    #[must_use]
+    pub fn deadline(&self) -> Option<Instant> {
+        let last_sent = self.last_sent?;
+        Some(if self.held.is_some() {
+            last_sent + self.interval
+        } else {
+            last_sent + KEEPALIVE_INTERVAL
+        })
+    }
+
+    /// Release a held-back frame or ask for a keepalive, if one is due.
+    pub fn poll(&mut self, now: Instant) -> Option<Due<T>> {
+        if now < self.deadline()? {
+            return None;
+        }
+        Some(self.held.take().map_or(Due::Keepalive, Due::Frame))
+    }
+
+    fn next_slot(&self) -> Option<Instant> {
+        self.last_sent.map(|last_sent| last_sent + self.interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn early_frames_are_held_and_replaced() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(10);
+        assert_eq!(pacer.offer(1, true, start), Some(1));
+        pacer.sent(start);
+
+        let early = start + FRAME / 2;
+        assert_eq!(pacer.offer(2, true, early), None);
+        assert_eq!(pacer.offer(3, true, early), None);
+        assert_eq!(pacer.deadline(), Some(start + FRAME));
+        assert_eq!(pacer.poll(early), None);
+        assert_eq!(pacer.poll(start + FRAME), Some(Due::Frame(3)));
+        pacer.sent(start + FRAME);
+
+        // A frame in its own slot goes straight out and drops nothing.
+        assert_eq!(pacer.offer(4, true, start + 2 * FRAME), Some(4));
+    }
+
+    #[test]
+    fn discarded_frames_are_not_released() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(10);
+        pacer.sent(start);
+        assert_eq!(pacer.offer(1, true, start + FRAME / 2), None);
+        pacer.discard();
+        assert_eq!(pacer.deadline(), Some(start + KEEPALIVE_INTERVAL));
+        assert_eq!(pacer.poll(start + FRAME), None);
+    }
+
+    #[test]
+    fn unchanged_frames_wait_for_keepalive() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(30);
+        assert_eq!(pacer.deadline(), None);
+        pacer.sent(start);
+
+        assert_eq!(pacer.offer(1, false, start + FRAME), None);
+        assert_eq!(pacer.deadline(), Some(start + KEEPALIVE_INTERVAL));
+        assert_eq!(pacer.poll(start + FRAME), None);
+        assert_eq!(pacer.poll(start + KEEPALIVE_INTERVAL), Some(Due::Keepalive));
+    }
+}
@@ -0,0 +1,89 @@
+//! Pointer shape tracking.
+//!
+//! Cursor metadata from the compositor repeats the current shape with
+//! every position change. The client keeps showing the last shape it was
+//! sent, so a shape is only sent again when it differs, by content, from
+//! the one showing.
+//!
+//! Shapes the client has shown before are not cached on its side:
+//! `DisplayUpdate` in the IronRDP fork has no cached or large pointer
+//! update carrying a cache index yet, so a pointer cache waits on that.
+
+use std::hash::{Hash, Hasher};
+
+use rdp_capture::CursorBitmap;
+
+/// Remembers the pointer shape the client is showing.
+#[derive(Debug, Default)]
+pub struct ShownPointer {
+    /// Content hash of the shape, if the pointer is visible.
+    current: Option<u64>,
+}
+
+impl ShownPointer {
+    /// Make `bitmap` the current shape. Returns whether it differs from
+    /// the one showing, i.e. whether it must be sent.
+    pub fn show(&mut self, bitmap: &CursorBitmap) -> bool {
+        let key = shape_key(bitmap);
+        self.current.replace(key) != Some(key)
+    }
+
+    /// The pointer was hidden, or the client reset its pointer (e.g. on
+    /// reactivation): the next shape must be sent even if it is the one
+    /// last shown.
+    pub fn forget(&mut self) {
+        self.current = None;
+    }
+}
+
+/// Content hash of a shape, including its size and hotspot.
+fn shape_key(bitmap: &CursorBitmap) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (bitmap.width, bitmap.height, bitmap.hot_x, bitmap.hot_y).hash(&mut hasher);
+    bitmap.data.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(fill: u8) -> CursorBitmap {
+        CursorBitmap {
+            width: 2,
+            height: 2,
+            hot_x: 0,
+            hot_y: 0,
+            data: vec![fill; 16],
+        }
+    }
+
+    #[test]
+    fn only_changed_shapes_are_sent() {
+        let mut shown = ShownPointer::default();
+        let (arrow, ibeam) = (shape(1), shape(2));
+
+        assert!(shown.show(&arrow));
+        assert!(!shown.show(&arrow));
+        assert!(shown.show(&ibeam));
+        assert!(shown.show(&arrow));
+    }
+
+    #[test]
+    fn hotspot_is_part_of_the_shape() {
+        let mut shown = ShownPointer::default();
+        let mut moved = shape(1);
+        moved.hot_x = 1;
+
+        assert!(shown.show(&shape(1)));
+        assert!(shown.show(&moved));
+    }
+
+    #[test]
+    fn forgotten_shape_is_sent_again() {
+        let mut shown = ShownPointer::default();
+        shown.show(&shape(1));
+        shown.forget();
+        assert!(shown.show(&shape(1)));
+    }
+}
@@ -1,5 +1,6 @@
 use std::num::{NonZeroU16, NonZeroUsize};
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
 use bytes::Bytes;
@@ -11,12 +12,21 @@ use ironrdp_server::{
     PixelFormat, RGBAPointer, RdpServer, RdpServerDisplay, RdpServerDisplayUpdates,
     RdpServerInputHandler, SoundServerFactory,
 };
-use rdp_capture::{CaptureEvent, CapturedFrame, CursorInfo, DesktopInfo};
-use rdp_encode::{EncoderConfig, GstEncoder};
+use rdp_capture::{
+    CaptureEvent, CapturedFrame, CursorBitmap, CursorInfo, DesktopInfo, FrameSource, MediaClock,
+    MonitorInfo, PixelFormat as FrameFormat,
+};
+use rdp_encode::{nv12_to_bgra, scale_bgra, EncoderConfig, GstEncoder, Letterbox};
 use rdp_input::{EiInput, MouseButton};
 use tokio::sync::mpsc;
 
-use crate::egfx::EgfxController;
+use crate::egfx::{EgfxController, FrameDelivery, RESIZE_DEBOUNCE};
+use crate::hotkeys::{Hotkey, Hotkeys};
+use crate::layout::{ClientLayout, DesktopMap, Viewport};
+use crate::monitor_select::MonitorRequest;
+use crate::output::OutputResizer;
+use crate::pacing::{Due, FramePacer};
+use crate::pointer_shape::ShownPointer;
 use crate::tls::TlsContext;
 
 const DEFAULT_WIDTH: u16 = 1920;
@@ -43,15 +53,47 @@ impl RdpServerInputHandler for StaticInputHandler {
 /// Input handler that injects keyboard and mouse events into the compositor.
 ///
 /// Wraps an [`EiInput`] backend and maps all RDP events to
-/// the appropriate reis/libei calls.
+/// the appropriate reis/libei calls. Absolute pointer positions are mapped
+/// from the client's desktop back onto the compositor through `scale`.
+/// Server shortcuts (see [`crate::hotkeys`]) are handled here and not
+/// forwarded.
 pub struct LiveInputHandler {
     input: EiInput,
+    scale: DesktopScale,
+    hotkeys: Hotkeys,
+    /// Where monitor shortcuts go outside viewport mode, when the served
+    /// monitor can be switched.
+    monitor_requests: Option<mpsc::UnboundedSender<MonitorRequest>>,
 }
 
 impl LiveInputHandler {
     /// Create a new live input handler.
-    pub fn new(input: EiInput) -> Self {
-        Self { input }
+    pub fn new(input: EiInput, scale: DesktopScale) -> Self {
+        Self {
+            input,
+            scale,
+            hotkeys: Hotkeys::default(),
+            monitor_requests: None,
+        }
+    }
+
+    /// Let the monitor shortcuts switch the served monitor (see
+    /// [`crate::monitor_select`]).
+    pub fn set_monitor_requests(&mut self, tx: mpsc::UnboundedSender<MonitorRequest>) {
+        self.monitor_requests = Some(tx);
+    }
+
+    fn run_hotkey(&mut self, hotkey: Hotkey) {
+        let forward = hotkey == Hotkey::NextMonitor;
+        if let Some((x, y)) = self.scale.jump_to_monitor(forward) {
+            tracing::info!(?hotkey, x, y, "Viewport moved to another monitor");
+            // Move the pointer along so the viewport does not pan back.
+            self.input.mouse_move(x, y);
+        } else if let Some(tx) = &self.monitor_requests {
+            let _ = tx.send(MonitorRequest::Cycle { forward });
+        } else {
+            tracing::debug!(?hotkey, "No other monitor to move the viewport to");
+        }
     }
 }
 
@@ -59,10 +101,16 @@ impl RdpServerInputHandler for LiveInputHandler {
     fn keyboard(&mut self, event: KeyboardEvent) {
         match event {
             KeyboardEvent::Pressed { code, extended } => {
-                self.input.key_press(code, extended);
+                if let Some(hotkey) = self.hotkeys.key(code, extended, true) {
+                    self.run_hotkey(hotkey);
+                } else {
+                    self.input.key_press(code, extended);
+                }
             }
             KeyboardEvent::Released { code, extended } => {
-                self.input.key_release(code, extended);
+                if self.hotkeys.key(code, extended, false).is_none() {
+                    self.input.key_release(code, extended);
+                }
             }
             // Unicode key events: handle common control characters by mapping
             // them to their corresponding scancode equivalents. Some RDP clients
@@ -106,7 +154,9 @@ impl RdpServerInputHandler for LiveInputHandler {
     fn mouse(&mut self, event: MouseEvent) {
         match event {
             MouseEvent::Move { x, y } => {
-                self.input.mouse_move(x, y);
+                if let Some((x, y)) = self.scale.to_compositor(x, y) {
+                    self.input.mouse_move(x, y);
+                }
             }
             MouseEvent::RelMove { x, y } => {
                 self.input.mouse_rel_move(x, y);
@@ -262,47 +312,377 @@ fn create_blue_bitmap(width: u16, height: u16) -> BitmapUpdate {
 
 /// Shared channel state between [`LiveDisplay`] and [`LiveDisplayUpdates`].
 ///
-/// When a client connects, the receivers are taken from here. When the client
-/// disconnects, [`LiveDisplayUpdates::drop`] puts them back so the next
-/// connection can reuse them without restarting capture.
+/// When a client connects, the frame source and receiver are taken from
+/// here. When the client disconnects, [`LiveDisplayUpdates::drop`] puts them
+/// back so the next connection can reuse them without restarting capture.
 struct DisplayChannels {
-    event_rx: Option<mpsc::Receiver<CaptureEvent>>,
+    source: Option<Box<dyn FrameSource>>,
+    /// Desktop sizes requested by bitmap-only clients.
+    resize_rx: Option<mpsc::UnboundedReceiver<(u16, u16)>>,
 }
 
-/// Display that streams live screen capture frames via `PipeWire` and
-/// supports dynamic resize requests from the RDP client.
+/// Largest cursor bitmap sent after scaling (the large-pointer limit).
+const MAX_CURSOR_SIZE: u32 = rdp_capture::spa_meta::MAX_CURSOR_SIZE;
+
+/// Mapping between captured frames and the client's desktop.
+///
+/// The capture size follows the compositor while the desktop follows the
+/// client's monitor layout, so captured monitors are letterboxed onto the
+/// client's monitors (see [`crate::layout`]). The display records the
+/// client layout and scale factor, the update stream the capture size, and
+/// the input handler maps pointer positions back onto the compositor.
+#[derive(Clone)]
+pub struct DesktopScale {
+    state: Arc<std::sync::Mutex<ScaleState>>,
+}
+
+/// Snapshot of [`DesktopScale`].
+#[derive(Debug, Clone)]
+struct ScaleState {
+    /// The client's monitors.
+    layout: ClientLayout,
+    /// Layout requested by the client, applied with the resize to its size.
+    requested: Option<ClientLayout>,
+    /// Captured monitors and their place in the capture.
+    captured: Vec<MonitorInfo>,
+    /// Size of the latest captured frame.
+    capture_size: (u32, u32),
+    /// Mapping of the capture onto `layout`.
+    map: DesktopMap,
+    /// Window onto the capture, in viewport mode.
+    viewport: Option<Viewport>,
+    /// The viewport moved without the pointer (e.g. by a shortcut).
+    viewport_moved: bool,
+    /// Client desktop scale factor in percent (`DesktopScaleFactor`), if
+    /// the client sent one.
+    client_scale: Option<u32>,
+    /// Compositor scale of the captured output in percent.
+    output_scale: u32,
+    /// Compositor position of the capture's top-left corner, added to
+    /// pointer positions; `None` if unknown, which disables absolute
+    /// pointer input.
+    input_origin: Option<(i32, i32)>,
+}
+
+impl ScaleState {
+    /// Factor to apply to a cursor bitmap at capture position (`x`, `y`)
+    /// so it matches the scaled frame and the client's scale factor.
+    ///
+    /// When the output scale already follows the client the compositor
+    /// draws cursors at the right size, leaving only the letterbox factor.
+    fn cursor_factor(&self, x: u32, y: u32) -> f64 {
+        let letterbox = self.map.region_at_source(x, y).letterbox;
+        let letterbox = f64::from(letterbox.width) / f64::from(letterbox.src_width.max(1));
+        letterbox
+            * self
+                .client_scale
+                .map_or(1.0, |client| f64::from(client) / f64::from(self.output_scale))
+    }
+
+    /// Compositor (logical) position of capture position (`x`, `y`), if
+    /// the capture's place in the compositor is known.
+    ///
+    /// Captured frames are in physical pixels, so positions on a scaled
+    /// output are divided by its scale.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped to u16
+    fn to_compositor(&self, x: u32, y: u32) -> Option<(u16, u16)> {
+        let (origin_x, origin_y) = self.input_origin?;
+        let logical = |v: u32, origin: i32| {
+            let v = i64::from(v * 100 / self.output_scale) + i64::from(origin);
+            v.clamp(0, i64::from(u16::MAX)) as u16
+        };
+        Some((logical(x, origin_x), logical(y, origin_y)))
+    }
+
+    /// Recompute the mapping after the layout, capture or viewport changed.
+    fn remap(&mut self) {
+        if let Some(viewport) = &mut self.viewport {
+            let (width, height) = self.layout.size();
+            let desktop = (u32::from(width), u32::from(height));
+            viewport.resize(desktop, self.capture_size);
+            self.map = viewport.map(desktop.0, desktop.1);
+        } else {
+            let (width, height) = self.capture_size;
+            self.map = self.layout.map(&self.captured, width, height);
+        }
+    }
+}
+
+impl DesktopScale {
+    fn new(info: &DesktopInfo) -> Self {
+        let layout = ClientLayout::single(info.width, info.height);
+        let capture_size = (u32::from(info.width), u32::from(info.height));
+        let map = layout.map(&info.monitors, capture_size.0, capture_size.1);
+        Self {
+            state: Arc::new(std::sync::Mutex::new(ScaleState {
+                layout,
+                requested: None,
+                captured: info.monitors.clone(),
+                capture_size,
+                map,
+                viewport: None,
+                viewport_moved: false,
+                client_scale: None,
+                output_scale: 100,
+                input_origin: Some((0, 0)),
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ScaleState> {
+        self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Current mapping.
+    fn current(&self) -> ScaleState {
+        self.lock().clone()
+    }
+
+    /// Record the layout the client asked for; it takes effect when the
+    /// desktop is resized to its size (see [`set_client_size`](Self::set_client_size)).
+    fn request_layout(&self, layout: ClientLayout) {
+        self.lock().requested = Some(layout);
+    }
+
+    /// Switch to a new client layout of the same desktop size.
+    fn set_client_layout(&self, layout: ClientLayout) {
+        let mut state = self.lock();
+        state.layout = layout;
+        state.remap();
+    }
+
+    /// Record a new client desktop size, using the requested layout if it
+    /// has that size and a single monitor otherwise.
+    fn set_client_size(&self, width: u16, height: u16) {
+        let mut state = self.lock();
+        state.layout = state
+            .requested
+            .take()
+            .filter(|layout| layout.size() == (width, height))
+            .unwrap_or_else(|| ClientLayout::single(width, height));
+        state.remap();
+    }
+
+    /// Show a desktop-sized window onto the capture, following the
+    /// pointer, instead of scaling the whole capture onto the desktop.
+    fn enable_viewport(&self) {
+        let mut state = self.lock();
+        state.viewport = Some(Viewport::default());
+        state.remap();
+    }
+
+    /// Pan the viewport to follow the pointer at capture position
+    /// (`x`, `y`).
+    ///
+    /// Returns whether the visible part of the capture changed since the
+    /// last call, i.e. whether the desktop needs repainting.
+    fn follow_pointer(&self, x: u32, y: u32) -> bool {
+        let mut state = self.lock();
+        let capture = state.capture_size;
+        let Some(viewport) = state.viewport.as_mut() else {
+            return false;
+        };
+        let panned = viewport.follow(x, y, capture);
+        if panned {
+            state.remap();
+        }
+        std::mem::take(&mut state.viewport_moved) || panned
+    }
+
+    /// Centre the viewport on the next (or previous) captured monitor in
+    /// reading order.
+    ///
+    /// Returns the compositor position of that monitor's centre, for the
+    /// pointer, or `None` outside viewport mode, with a single monitor or
+    /// while the capture's position is unknown.
+    fn jump_to_monitor(&self, forward: bool) -> Option<(u16, u16)> {
+        let mut state = self.lock();
+        let mut viewport = state.viewport?;
+        let mut monitors = state.captured.clone();
+        if monitors.len() < 2 {
+            return None;
+        }
+        monitors.sort_by_key(|m| (m.x, m.y));
+
+        let (x, y) = (viewport.x + viewport.width / 2, viewport.y + viewport.height / 2);
+        let current = monitors
+            .iter()
+            .position(|m| {
+                let left = u32::try_from(m.x).unwrap_or(0);
+                let top = u32::try_from(m.y).unwrap_or(0);
+                (left..left + u32::from(m.width)).contains(&x)
+                    && (top..top + u32::from(m.height)).contains(&y)
+            })
+            .unwrap_or(0);
+        let count = monitors.len();
+        let next = if forward { current + 1 } else { current + count - 1 };
+        let target = &monitors[next % count];
+
+        viewport.centre_on(target, state.capture_size);
+        state.viewport = Some(viewport);
+        state.viewport_moved = true;
+        state.remap();
+
+        let centre = |start: i32, len: u16| u32::try_from(start).unwrap_or(0) + u32::from(len) / 2;
+        state.to_compositor(
+            centre(target.x, target.width),
+            centre(target.y, target.height),
+        )
+    }
+
+    /// Record that `monitor` is now the only one captured, with its
+    /// compositor scale (percent) if known.
+    pub fn serve_monitor(&self, monitor: MonitorInfo, output_scale: Option<u32>) {
+        let mut state = self.lock();
+        state.captured = vec![monitor];
+        if let Some(percent) = output_scale {
+            state.output_scale = percent.max(1);
+        }
+        state.remap();
+    }
+
+    /// Record the client's desktop scale factor (percent).
+    fn set_client_scale(&self, percent: Option<u32>) {
+        self.lock().client_scale = percent;
+    }
+
+    /// Record the compositor scale of the captured output (percent).
+    fn set_output_scale(&self, percent: u32) {
+        self.lock().output_scale = percent.max(1);
+    }
+
+    /// Record the size of the latest captured frame and return the mapping
+    /// for it.
+    fn fit_capture(&self, width: u32, height: u32) -> DesktopMap {
+        let mut state = self.lock();
+        if state.capture_size != (width, height) {
+            state.capture_size = (width, height);
+            state.remap();
+            tracing::info!(
+                capture_width = width,
+                capture_height = height,
+                desktop_width = state.map.width,
+                desktop_height = state.map.height,
+                "Capture size changed, updating desktop scaling"
+            );
+        }
+        state.map.clone()
+    }
+
+    /// Record where the capture sits in the compositor's layout, so
+    /// pointer positions land on it. Absolute pointer input is disabled
+    /// while the position is unknown.
+    pub fn set_input_origin(&self, origin: Option<(i32, i32)>) {
+        let mut state = self.lock();
+        if origin.is_none() && state.input_origin.is_some() {
+            tracing::warn!("Position of the capture is unknown, disabling absolute pointer input");
+        } else if origin.is_some() && state.input_origin.is_none() {
+            tracing::info!("Position of the capture is known, enabling absolute pointer input");
+        }
+        state.input_origin = origin;
+    }
+
+    /// Map a client desktop position to compositor (logical) coordinates,
+    /// if the capture's place in the compositor is known.
+    pub fn to_compositor(&self, x: u16, y: u16) -> Option<(u16, u16)> {
+        let state = self.lock();
+        let (x, y) = state.map.to_source(u32::from(x), u32::from(y));
+        state.to_compositor(x, y)
+    }
+}
+
+/// Display that streams frames from a [`FrameSource`], normally the live
+/// `PipeWire` screen capture, and supports dynamic resize requests from the
+/// RDP client.
 ///
 /// Supports sequential connections: when a client disconnects, the capture
 /// channels are returned to shared state so the next client can reuse them.
 pub struct LiveDisplay {
     width: u16,
     height: u16,
+    /// Last monitor layout accepted from the client.
+    layout: ClientLayout,
     channels: Arc<std::sync::Mutex<DisplayChannels>>,
     /// EGFX controller for H.264 delivery and resize (optional).
     /// Retained across connections (cloned into `LiveDisplayUpdates`).
     egfx: Option<EgfxController>,
+    /// Changes the real output mode on resize (optional).
+    output_resizer: Option<Arc<OutputResizer>>,
+    /// Scaling of captured frames onto the client's desktop.
+    scale: DesktopScale,
+    /// Resize requests for clients without EGFX, applied by
+    /// [`LiveDisplayUpdates`] through `DisplayUpdate::Resize`.
+    resize_tx: mpsc::UnboundedSender<(u16, u16)>,
+    /// Maximum frames per second sent to the client.
+    fps: u32,
 }
 
 impl LiveDisplay {
-    /// Create a live display from a capture event receiver and desktop info.
+    /// Create a live display showing the frames of `source`.
     ///
-    /// The caller must keep the [`rdp_capture::CaptureHandle`] alive for the
-    /// duration of the display, otherwise frames will stop arriving.
-    pub fn new(event_rx: mpsc::Receiver<CaptureEvent>, info: &DesktopInfo) -> Self {
+    /// For a [`rdp_capture::PipeWireSource`], the caller must keep the
+    /// [`rdp_capture::CaptureHandle`] alive for the duration of the display,
+    /// otherwise frames will stop arriving.
+    pub fn new(source: Box<dyn FrameSource>) -> Self {
+        let (resize_tx, resize_rx) = mpsc::unbounded_channel();
+        let info = source.desktop();
+        let (width, height) = (info.width, info.height);
+        let scale = DesktopScale::new(info);
         Self {
-            width: info.width,
-            height: info.height,
+            width,
+            height,
+            layout: ClientLayout::single(width, height),
             channels: Arc::new(std::sync::Mutex::new(DisplayChannels {
-                event_rx: Some(event_rx),
+                source: Some(source),
+                resize_rx: Some(resize_rx),
             })),
             egfx: None,
+            output_resizer: None,
+            scale,
+            resize_tx,
+            fps: 30,
         }
     }
 
+    /// Scaling state shared with the input handler for pointer mapping.
+    pub fn desktop_scale(&self) -> DesktopScale {
+        self.scale.clone()
+    }
+
+    /// Send a client-sized window onto the desktop that follows the
+    /// pointer, instead of scaling the whole desktop to the client.
+    pub fn enable_viewport(&mut self) {
+        self.scale.enable_viewport();
+    }
+
+    /// Offset pointer input by the capture's position in the compositor
+    /// layout, e.g. to bind it to a virtual monitor; see
+    /// [`DesktopScale::set_input_origin`].
+    pub fn set_input_origin(&mut self, origin: Option<(i32, i32)>) {
+        self.scale.set_input_origin(origin);
+    }
+
+    /// Set the compositor scale (percent) of the captured output, so input
+    /// and cursors are mapped correctly on scaled outputs.
+    pub fn set_output_scale(&mut self, percent: u32) {
+        self.scale.set_output_scale(percent);
+    }
+
+    /// Limit the frames sent to the client to `fps` per second (30 by
+    /// default); see [`crate::pacing`].
+    pub fn set_frame_rate(&mut self, fps: u32) {
+        self.fps = fps.max(1);
+    }
+
     /// Attach an EGFX controller for H.264 frame delivery.
     pub fn set_egfx(&mut self, controller: EgfxController) {
         self.egfx = Some(controller);
     }
+
+    /// Resize the captured output itself when the client resizes.
+    pub fn set_output_resizer(&mut self, resizer: OutputResizer) {
+        self.output_resizer = Some(Arc::new(resizer));
+    }
 }
 
 #[async_trait::async_trait]
@@ -317,10 +697,11 @@ impl RdpServerDisplay for LiveDisplay {
     async fn updates(&mut self) -> Result<Box<dyn RdpServerDisplayUpdates>> {
         let mut channels = self.channels.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
 
-        let event_rx = channels
-            .event_rx
+        let source = channels
+            .source
             .take()
             .ok_or_else(|| anyhow::anyhow!("capture already in use (only one connection at a time)"))?;
+        let resize_rx = channels.resize_rx.take();
 
         // Clone EGFX controller so LiveDisplay retains access for
         // request_layout() while LiveDisplayUpdates gets its own handle.
@@ -337,59 +718,122 @@ impl RdpServerDisplay for LiveDisplay {
         tracing::info!("Display channels acquired for new connection");
 
         Ok(Box::new(LiveDisplayUpdates {
-            event_rx: Some(event_rx),
+            source: Some(source),
+            resize_rx,
+            bitmap_resize: BitmapResize::Idle,
             channels: Arc::clone(&self.channels),
             pending_cursor: None,
             egfx,
+            output_resizer: self.output_resizer.clone(),
+            scale: self.scale.clone(),
             encoder: None,
-            encoder_width: 0,
-            encoder_height: 0,
-            frame_timestamp_ms: 0,
-            egfx_wait_frames: 0,
+            encoder_geometry: None,
+            clock: MediaClock::shared(),
+            last_frame: None,
+            pacer: FramePacer::new(self.fps),
+            fps: self.fps,
+            pointer: ShownPointer::default(),
         }))
     }
 
     fn request_layout(&mut self, layout: DisplayControlMonitorLayout) {
-        // Extract the primary monitor dimensions from the layout request.
-        let Some(primary) = layout.monitors().iter().find(|m| m.is_primary()) else {
-            tracing::debug!("No primary monitor in layout request, ignoring");
+        // The desktop is the bounding box of all client monitors.
+        let Some(client_layout) = ClientLayout::from_pdu(&layout) else {
+            tracing::warn!(
+                monitors = layout.monitors().len(),
+                "Unusable monitor layout requested (empty or larger than 65535 pixels), ignoring"
+            );
             return;
         };
+        let (width, height) = client_layout.size();
+
+        // MS-RDPEDISP: DesktopScaleFactor is 100-500 percent; other values
+        // (including 0 from clients that do not send one) are ignored.
+        let monitors = layout.monitors();
+        let scale_factor = monitors
+            .iter()
+            .find(|m| m.is_primary())
+            .or(monitors.first())
+            .map_or(0, |primary| primary.desktop_scale_factor());
+        let client_scale = (100..=500).contains(&scale_factor).then_some(scale_factor);
+        let old_scale = self.scale.current().client_scale;
+        let scale_changed = client_scale != old_scale;
+        if scale_changed {
+            tracing::info!(
+                scale_percent = ?client_scale,
+                old_scale_percent = ?old_scale,
+                "Client desktop scale factor changed"
+            );
+            self.scale.set_client_scale(client_scale);
+        }
 
-        let (width, height) = primary.dimensions();
-
-        let Ok(width) = u16::try_from(width) else {
-            tracing::warn!(width, "Requested width exceeds u16, ignoring resize");
-            return;
-        };
-        let Ok(height) = u16::try_from(height) else {
-            tracing::warn!(height, "Requested height exceeds u16, ignoring resize");
+        if client_layout == self.layout {
+            if scale_changed {
+                apply_output(self.output_resizer.as_deref(), &self.scale);
+            } else {
+                tracing::debug!(width, height, "Resize requested but layout unchanged");
+            }
             return;
-        };
+        }
 
+        // Same desktop size, monitors rearranged or rotated: only the
+        // mapping onto the desktop changes.
         if width == self.width && height == self.height {
-            tracing::debug!(width, height, "Resize requested but dimensions unchanged");
+            tracing::info!(
+                monitors = client_layout.monitors().len(),
+                width, height,
+                "Client monitor layout changed"
+            );
+            self.scale.set_client_layout(client_layout.clone());
+            apply_output(self.output_resizer.as_deref(), &self.scale);
+            self.layout = client_layout;
             return;
         }
 
+        tracing::info!(
+            monitors = client_layout.monitors().len(),
+            width, height,
+            "Client requested a new desktop layout"
+        );
+        self.scale.request_layout(client_layout.clone());
+
         // Route resize through EGFX ResetGraphics instead of
         // DisplayUpdate::Resize to avoid ironrdp-server 0.10's broken
-        // deactivation-reactivation sequence.
+        // deactivation-reactivation sequence. The controller debounces
+        // requests; `LiveDisplayUpdates` applies the settled size.
         if let Some(ref egfx) = self.egfx {
             if egfx.is_ready() {
-                tracing::info!(
+                tracing::debug!(
                     width, height,
                     old_width = self.width, old_height = self.height,
-                    "Resizing display via EGFX ResetGraphics"
+                    "Resize requested via EGFX"
                 );
-                egfx.resize(width, height);
+                egfx.request_resize(width, height);
                 self.width = width;
                 self.height = height;
+                self.layout = client_layout;
                 return;
             }
         }
 
-        // EGFX not available or not ready — cannot resize safely.
+        // The client never opened the EGFX channel, so it only takes
+        // bitmaps: resize through deactivation-reactivation instead (see
+        // `LiveDisplayUpdates::next_bitmap_resize`).
+        if !self.egfx.as_ref().is_some_and(EgfxController::is_channel_open) {
+            tracing::debug!(
+                width, height,
+                old_width = self.width, old_height = self.height,
+                "Resize requested via reactivation"
+            );
+            if self.resize_tx.send((width, height)).is_ok() {
+                self.width = width;
+                self.height = height;
+                self.layout = client_layout;
+            }
+            return;
+        }
+
+        // EGFX is still negotiating — neither path can resize safely.
         tracing::info!(
             width, height,
             "Client requested resize but EGFX not ready, ignoring"
@@ -397,15 +841,35 @@ impl RdpServerDisplay for LiveDisplay {
     }
 }
 
-/// Display updates that receive live frames from the `PipeWire` capture
+/// Switch the captured output to fit the client's primary monitor, if the
+/// server controls it, applying the client's scale factor as the output
+/// scale and the monitor's rotation as its transform.
+fn apply_output(resizer: Option<&OutputResizer>, scale: &DesktopScale) {
+    let Some(resizer) = resizer else {
+        return;
+    };
+    let state = scale.current();
+    let client_scale = state.client_scale;
+    let primary = state.layout.primary();
+    tracing::info!(output = resizer.output(), "Resizing captured output");
+    resizer.resize(primary.width, primary.height, client_scale, primary.rotation);
+    scale.set_output_scale(resizer.applied_scale(client_scale));
+}
+
+/// Display updates that receive frames from the display's [`FrameSource`]
 /// and handle dynamic resize events from the RDP client.
 ///
 /// When an [`EgfxController`] is present and ready, captured frames are
 /// encoded to H.264 via [`GstEncoder`] and delivered through the EGFX
 /// DVC channel instead of as raw bitmaps. Falls back to bitmaps when
-/// EGFX is not negotiated.
+/// EGFX is not negotiated. Either way frames are scaled to the client's
+/// desktop size, so they always fit its surface.
 struct LiveDisplayUpdates {
-    event_rx: Option<mpsc::Receiver<CaptureEvent>>,
+    source: Option<Box<dyn FrameSource>>,
+    /// Resize requests from bitmap-only clients.
+    resize_rx: Option<mpsc::UnboundedReceiver<(u16, u16)>>,
+    /// Progress of a bitmap-path resize.
+    bitmap_resize: BitmapResize,
     /// Shared state to return channels to on disconnect.
     channels: Arc<std::sync::Mutex<DisplayChannels>>,
     /// When a `FrameAndCursor` event arrives, we return the frame first
@@ -413,26 +877,223 @@ struct LiveDisplayUpdates {
     pending_cursor: Option<CursorInfo>,
     /// EGFX controller for H.264 frame delivery (if available).
     egfx: Option<EgfxController>,
+    /// Output resizer, so the original mode is restored on disconnect.
+    output_resizer: Option<Arc<OutputResizer>>,
+    /// Scaling of captured frames onto the client's desktop.
+    scale: DesktopScale,
     /// H.264 encoder, lazily initialized on first EGFX frame.
     encoder: Option<GstEncoder>,
-    /// Dimensions of the current encoder (0 = not yet initialized).
-    encoder_width: u32,
-    encoder_height: u32,
-    /// Frame timestamp counter (milliseconds), monotonically increasing.
-    frame_timestamp_ms: u32,
-    /// Frames skipped while waiting for EGFX to become ready.
-    /// After a timeout, fall back to bitmap delivery even if EGFX never
-    /// negotiates (e.g. client connected with /gfx:off).
-    egfx_wait_frames: u32,
+    /// Input and output geometry of the current encoder.
+    encoder_geometry: Option<Letterbox>,
+    /// Clock shared with audio, mapping frame presentation times to EGFX
+    /// frame timestamps.
+    clock: MediaClock,
+    /// Last frame delivered, re-sent after a resize so the new surface
+    /// (or reactivated desktop) is repainted even if the desktop is static.
+    last_frame: Option<CapturedFrame>,
+    /// Frame rate limit and idle keepalive.
+    pacer: FramePacer<CapturedFrame>,
+    /// Frame rate the encoder is configured for.
+    fps: u32,
+    /// Pointer shape the client is showing.
+    pointer: ShownPointer,
+}
+
+/// Resize of a bitmap-only session.
+///
+/// `ironrdp-server` tears the session down if updates race its
+/// deactivation-reactivation sequence, so resizes go through a fixed
+/// sequence: requests are debounced, a single `DisplayUpdate::Resize` is
+/// emitted, and the next update is a full frame at the new size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitmapResize {
+    Idle,
+    /// A size was requested; emit the resize once requests settle.
+    Pending { width: u16, height: u16, due: Instant },
+    /// The resize was emitted; repaint the whole desktop next.
+    Refresh,
+}
+
+/// What woke [`LiveDisplayUpdates::next_update`].
+enum Wake {
+    Capture(Option<CaptureEvent>),
+    Resize(u16, u16),
+    Timer,
+}
+
+impl LiveDisplayUpdates {
+    /// Apply a settled EGFX resize, if one is due.
+    ///
+    /// Updates the desktop scaling (which makes the next EGFX frame
+    /// recreate the encoder at the new size), resizes the captured output
+    /// if enabled, and re-sends the last frame as the first keyframe.
+    fn apply_pending_resize(&mut self) {
+        let Some(egfx) = self.egfx.as_ref() else {
+            return;
+        };
+        let Some((width, height)) = egfx.apply_pending_resize() else {
+            return;
+        };
+        self.scale.set_client_size(width, height);
+        apply_output(self.output_resizer.as_deref(), &self.scale);
+
+        self.resend_egfx_frame();
+    }
+
+    /// Re-send the last frame via EGFX, if there is one and EGFX is ready.
+    fn resend_egfx_frame(&mut self) {
+        if let Some(frame) = self.last_frame.take() {
+            let map = self.scale.fit_capture(frame.width, frame.height);
+            try_send_egfx_frame(
+                self.egfx.as_ref(),
+                &mut self.encoder,
+                &mut self.encoder_geometry,
+                self.clock,
+                self.fps,
+                &map,
+                &frame,
+            );
+            self.last_frame = Some(frame);
+            self.pacer.sent(Instant::now());
+        }
+    }
+
+    /// Deliver a captured frame now, hold it back to keep to the frame
+    /// rate, or drop it if its damage shows nothing changed.
+    fn offer_frame(&mut self, frame: CapturedFrame) -> Result<Option<DisplayUpdate>> {
+        let changed = frame
+            .damage
+            .as_ref()
+            .is_none_or(|damage| !damage.is_empty());
+        match self.pacer.offer(frame, changed, Instant::now()) {
+            Some(frame) => self.deliver_frame(frame),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the picture with a placeholder while the capture
+    /// reconnects, at the size of the last frame.
+    ///
+    /// The placeholder is kept for repaints like any frame until the new
+    /// session's first frame replaces it.
+    fn show_reconnecting(&mut self) -> Result<Option<DisplayUpdate>> {
+        tracing::warn!("Screen capture interrupted, showing a placeholder while it reconnects");
+        self.pacer.discard();
+        let Some((width, height)) = self.last_frame.as_ref().map(|f| (f.width, f.height)) else {
+            return Ok(None);
+        };
+        self.deliver_frame(placeholder_frame(width, height))
+    }
+
+    /// Deliver a held-back frame whose slot has come, or repeat the last
+    /// one if the desktop has been idle.
+    fn next_paced_frame(&mut self) -> Result<Option<DisplayUpdate>> {
+        match self.pacer.poll(Instant::now()) {
+            None => Ok(None),
+            Some(Due::Frame(frame)) => self.deliver_frame(frame),
+            Some(Due::Keepalive) => {
+                // Only over EGFX, where a repeated frame encodes to a few
+                // bytes; idle bitmap sessions send nothing.
+                self.pacer.sent(Instant::now());
+                self.resend_egfx_frame();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Deliver a frame via EGFX, or as a bitmap update if EGFX is not
+    /// ready. The frame is kept for repaints.
+    fn deliver_frame(&mut self, frame: CapturedFrame) -> Result<Option<DisplayUpdate>> {
+        let map = self.scale.fit_capture(frame.width, frame.height);
+        self.pacer.sent(Instant::now());
+        let update = if try_send_egfx_frame(
+            self.egfx.as_ref(),
+            &mut self.encoder,
+            &mut self.encoder_geometry,
+            self.clock,
+            self.fps,
+            &map,
+            &frame,
+        ) {
+            None
+        } else {
+            // Bitmaps are scaled to the client's desktop, so they can be
+            // sent while EGFX is still negotiating without tripping
+            // FreeRDP's "rectangle does not fit" check.
+            Some(DisplayUpdate::Bitmap(frame_to_bitmap(&frame, &map)?))
+        };
+        self.last_frame = Some(frame);
+        Ok(update)
+    }
+
+    /// Pointer update for a captured cursor.
+    fn cursor_update(&mut self, cursor: &CursorInfo) -> DisplayUpdate {
+        cursor_to_display_update(cursor, &self.scale.current(), &mut self.pointer)
+    }
+
+    /// Let the viewport (if any) follow the captured pointer position.
+    /// Returns whether the desktop needs repainting.
+    #[allow(clippy::cast_sign_loss)]
+    fn follow_cursor(&self, cursor: &CursorInfo) -> bool {
+        cursor.visible && self.scale.follow_pointer(cursor.x.max(0) as u32, cursor.y.max(0) as u32)
+    }
+
+    /// Record a resize requested by a bitmap-only client, restarting the
+    /// debounce. Returning to the current size cancels a pending resize.
+    fn request_bitmap_resize(&mut self, width: u16, height: u16) {
+        self.bitmap_resize = if self.scale.current().layout.size() == (width, height) {
+            BitmapResize::Idle
+        } else {
+            BitmapResize::Pending {
+                width,
+                height,
+                due: Instant::now() + RESIZE_DEBOUNCE,
+            }
+        };
+    }
+
+    /// Next step of a bitmap-path resize, if any is due.
+    ///
+    /// Emits `DisplayUpdate::Resize` once the requested size has settled
+    /// (resizing the captured output if enabled), then the last frame
+    /// scaled to the new desktop on the following call.
+    fn next_bitmap_resize(&mut self) -> Result<Option<DisplayUpdate>> {
+        match self.bitmap_resize {
+            BitmapResize::Idle => Ok(None),
+            BitmapResize::Pending { due, .. } if Instant::now() < due => Ok(None),
+            BitmapResize::Pending { width, height, .. } => {
+                tracing::info!(width, height, "Resizing bitmap session via reactivation");
+                self.bitmap_resize = BitmapResize::Refresh;
+                self.scale.set_client_size(width, height);
+                apply_output(self.output_resizer.as_deref(), &self.scale);
+                // Reactivation resets the client's pointer.
+                self.pointer.forget();
+                Ok(Some(DisplayUpdate::Resize(DesktopSize { width, height })))
+            }
+            BitmapResize::Refresh => {
+                self.bitmap_resize = BitmapResize::Idle;
+                let Some(frame) = self.last_frame.as_ref() else {
+                    return Ok(None);
+                };
+                let map = self.scale.fit_capture(frame.width, frame.height);
+                Ok(Some(DisplayUpdate::Bitmap(frame_to_bitmap(frame, &map)?)))
+            }
+        }
+    }
 }
 
 impl Drop for LiveDisplayUpdates {
     fn drop(&mut self) {
         let mut channels = self.channels.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
-        channels.event_rx = self.event_rx.take();
+        channels.source = self.source.take();
+        channels.resize_rx = self.resize_rx.take();
+        if let Some(ref resizer) = self.output_resizer {
+            resizer.restore();
+        }
         // EGFX controller is not returned — LiveDisplay retains its own clone.
         // Drop the encoder to release GStreamer resources.
         self.encoder = None;
+        self.last_frame = None;
         tracing::info!("Client disconnected, display channels released for next connection");
     }
 }
@@ -443,70 +1104,81 @@ impl RdpServerDisplayUpdates for LiveDisplayUpdates {
         // If we have a buffered cursor update from a previous FrameAndCursor,
         // return it immediately before reading more events.
         if let Some(cursor) = self.pending_cursor.take() {
-            return Ok(Some(cursor_to_display_update(&cursor)));
+            return Ok(Some(self.cursor_update(&cursor)));
         }
 
-        let event_rx = self.event_rx.as_mut().expect("event_rx missing during active connection");
-
         loop {
-            let Some(event) = event_rx.recv().await else {
-                return Ok(None);
+            self.apply_pending_resize();
+            if let Some(update) = self.next_bitmap_resize()? {
+                return Ok(Some(update));
+            }
+            if let Some(update) = self.next_paced_frame()? {
+                return Ok(Some(update));
+            }
+
+            // While a resize is pending or a frame is held back, also wake
+            // up when it becomes due so it is handled even if no frames
+            // arrive.
+            let bitmap_due = match self.bitmap_resize {
+                BitmapResize::Pending { due, .. } => Some(due),
+                _ => None,
+            };
+            let resize_due = self.egfx.as_ref().and_then(EgfxController::resize_due);
+            let due = resize_due
+                .into_iter()
+                .chain(bitmap_due)
+                .chain(self.pacer.deadline())
+                .min();
+
+            let source = self.source.as_mut().expect("source missing during active connection");
+            let resize_rx = self.resize_rx.as_mut().expect("resize_rx missing during active connection");
+            let sleep = tokio::time::sleep_until(due.unwrap_or_else(Instant::now).into());
+            let wake = tokio::select! {
+                event = source.next_event() => Wake::Capture(event),
+                Some((width, height)) = resize_rx.recv() => Wake::Resize(width, height),
+                () = sleep, if due.is_some() => Wake::Timer,
+            };
+            let event = match wake {
+                Wake::Capture(Some(event)) => event,
+                Wake::Capture(None) => return Ok(None),
+                Wake::Resize(width, height) => {
+                    self.request_bitmap_resize(width, height);
+                    continue;
+                }
+                Wake::Timer => continue,
             };
 
             match event {
-                CaptureEvent::Frame(mut frame) => {
-                    frame.ensure_alpha_opaque();
-                    if try_send_egfx_frame(
-                        self.egfx.as_ref(),
-                        &mut self.encoder,
-                        &mut self.encoder_width,
-                        &mut self.encoder_height,
-                        &mut self.frame_timestamp_ms,
-                        &frame,
-                    ) {
-                        continue;
+                CaptureEvent::Frame(frame) => {
+                    if let Some(update) = self.offer_frame(frame)? {
+                        return Ok(Some(update));
                     }
-                    // When EGFX is configured, skip bitmap fallback while the
-                    // DVC channel is still negotiating. Sending bitmaps at the
-                    // capture resolution (e.g. 1920x1080) crashes FreeRDP if
-                    // the client's desktop is smaller (e.g. 1662x860):
-                    //   "Invalid surface bits command rectangle does not fit"
-                    // After ~300 frames (~10s at 30fps) fall back to bitmap
-                    // for clients that don't support EGFX.
-                    if self.egfx.is_some() && self.egfx_wait_frames < 300 {
-                        self.egfx_wait_frames += 1;
-                        if self.egfx_wait_frames == 1 {
-                            tracing::info!(
-                                "EGFX not yet ready, suppressing bitmap fallback"
-                            );
-                        }
-                        continue;
-                    }
-                    let bitmap = frame_to_bitmap(frame)?;
-                    return Ok(Some(DisplayUpdate::Bitmap(bitmap)));
                 }
                 CaptureEvent::Cursor(cursor) => {
-                    return Ok(Some(cursor_to_display_update(&cursor)));
+                    // A viewport that panned must be repainted, and the
+                    // desktop under it may be static: re-send the last frame.
+                    if self.follow_cursor(&cursor) {
+                        if let Some(frame) = self.last_frame.take() {
+                            if let Some(update) = self.deliver_frame(frame)? {
+                                self.pending_cursor = Some(cursor);
+                                return Ok(Some(update));
+                            }
+                        }
+                    }
+                    return Ok(Some(self.cursor_update(&cursor)));
                 }
-                CaptureEvent::FrameAndCursor(mut frame, cursor) => {
-                    self.pending_cursor = Some(cursor);
-                    frame.ensure_alpha_opaque();
-                    if try_send_egfx_frame(
-                        self.egfx.as_ref(),
-                        &mut self.encoder,
-                        &mut self.encoder_width,
-                        &mut self.encoder_height,
-                        &mut self.frame_timestamp_ms,
-                        &frame,
-                    ) {
-                        continue;
+                CaptureEvent::FrameAndCursor(frame, cursor) => {
+                    self.follow_cursor(&cursor);
+                    if let Some(update) = self.offer_frame(frame)? {
+                        self.pending_cursor = Some(cursor);
+                        return Ok(Some(update));
                     }
-                    if self.egfx.is_some() && self.egfx_wait_frames < 300 {
-                        self.egfx_wait_frames += 1;
-                        continue;
+                    return Ok(Some(self.cursor_update(&cursor)));
+                }
+                CaptureEvent::Interrupted => {
+                    if let Some(update) = self.show_reconnecting()? {
+                        return Ok(Some(update));
                     }
-                    let bitmap = frame_to_bitmap(frame)?;
-                    return Ok(Some(DisplayUpdate::Bitmap(bitmap)));
                 }
             }
         }
@@ -515,19 +1187,24 @@ impl RdpServerDisplayUpdates for LiveDisplayUpdates {
 
 /// Try to encode a frame as H.264 and send it via EGFX.
 ///
-/// Returns `true` if the frame was sent via EGFX (caller should skip
-/// bitmap delivery), `false` if EGFX is not ready and bitmap fallback
-/// should be used.
+/// Returns `true` if the frame was handled via EGFX (sent, or dropped
+/// while a resize completes; caller should skip bitmap delivery), `false`
+/// if EGFX is not ready and bitmap fallback should be used.
 ///
-/// Detects frame dimension changes (from `PipeWire` resolution changes or
-/// EGFX resize) and recreates the encoder to match.
+/// The encoder scales frames to the client's desktop (the EGFX surface
+/// size) as described by `map`. It is recreated whenever either the
+/// capture size (`PipeWire` resolution change) or the desktop size (EGFX
+/// resize) changes. Layouts the encoder cannot scale to by itself
+/// (several client monitors) are composed on the CPU first; NV12 frames
+/// go to the encoder as they are unless they need composing.
 #[allow(clippy::cast_possible_truncation)]
 fn try_send_egfx_frame(
     egfx: Option<&EgfxController>,
     h264_encoder: &mut Option<GstEncoder>,
-    encoder_width: &mut u32,
-    encoder_height: &mut u32,
-    timestamp_ms: &mut u32,
+    encoder_geometry: &mut Option<Letterbox>,
+    clock: MediaClock,
+    fps: u32,
+    map: &DesktopMap,
     frame: &CapturedFrame,
 ) -> bool {
     let Some(egfx) = egfx else {
@@ -538,27 +1215,47 @@ fn try_send_egfx_frame(
         return false;
     }
 
-    // Detect frame dimension change: drop encoder so it gets recreated
-    // at the new size. This handles both client-initiated resize (via
-    // EGFX ResetGraphics in request_layout) and PipeWire resolution changes.
-    if h264_encoder.is_some() && (frame.width != *encoder_width || frame.height != *encoder_height) {
-        tracing::info!(
-            old_width = *encoder_width, old_height = *encoder_height,
-            new_width = frame.width, new_height = frame.height,
-            "EGFX: frame dimensions changed, recreating encoder"
-        );
-        *h264_encoder = None;
+    let converted;
+    let frame = if frame.format == FrameFormat::Nv12
+        && map.as_letterbox(frame.width, frame.height).is_none()
+    {
+        converted = nv12_to_bgra_frame(frame);
+        &converted
+    } else {
+        frame
+    };
 
-        // Ensure the EGFX surface matches the new frame dimensions.
-        egfx.resize(frame.width as u16, frame.height as u16);
+    let composed;
+    let (frame, letterbox) = match map.as_letterbox(frame.width, frame.height) {
+        Some(letterbox) => (frame, letterbox),
+        None => {
+            composed = compose_frame(frame, map);
+            (&composed, Letterbox::fit(map.width, map.height, map.width, map.height))
+        }
+    };
+
+    // Detect a geometry change: drop the encoder so it gets recreated
+    // for the new capture or desktop size.
+    if let Some(old) = encoder_geometry.filter(|_| h264_encoder.is_some()) {
+        if old != letterbox {
+            tracing::info!(
+                old_width = old.src_width, old_height = old.src_height,
+                new_width = letterbox.src_width, new_height = letterbox.src_height,
+                desktop_width = letterbox.dst_width, desktop_height = letterbox.dst_height,
+                "EGFX: frame or desktop dimensions changed, recreating encoder"
+            );
+            *h264_encoder = None;
+        }
     }
 
     // Lazily initialize the H.264 encoder on the first EGFX frame or
-    // after a dimension change.
+    // after a geometry change.
     if h264_encoder.is_none() {
         let config = EncoderConfig {
             width: frame.width,
             height: frame.height,
+            framerate: fps,
+            scale_to: Some((letterbox.dst_width, letterbox.dst_height)),
             ..EncoderConfig::default()
         };
         match GstEncoder::new(&config) {
@@ -566,6 +1263,8 @@ fn try_send_egfx_frame(
                 tracing::info!(
                     width = frame.width,
                     height = frame.height,
+                    desktop_width = letterbox.dst_width,
+                    desktop_height = letterbox.dst_height,
                     encoder_type = %enc.encoder_type(),
                     "EGFX: H.264 encoder initialized"
                 );
@@ -574,8 +1273,7 @@ fn try_send_egfx_frame(
                 if egfx.take_needs_keyframe() {
                     enc.force_keyframe();
                 }
-                *encoder_width = frame.width;
-                *encoder_height = frame.height;
+                *encoder_geometry = Some(letterbox);
                 *h264_encoder = Some(enc);
             }
             Err(e) => {
@@ -587,14 +1285,20 @@ fn try_send_egfx_frame(
 
     let enc = h264_encoder.as_mut().expect("encoder just initialized");
 
-    match enc.encode_frame(&frame.data) {
+    let encoded = match frame.format {
+        FrameFormat::Nv12 => enc.encode_nv12_frame(&frame.data, frame.stride as usize),
+        FrameFormat::Bgra | FrameFormat::Rgba => {
+            enc.encode_frame(&frame.data, frame.stride as usize)
+        }
+    };
+    match encoded {
         Ok(Some(h264_frame)) => {
-            let width = frame.width as u16;
-            let height = frame.height as u16;
-            let ts = *timestamp_ms;
-            *timestamp_ms = timestamp_ms.wrapping_add(33); // ~30 fps
+            let width = letterbox.dst_width as u16;
+            let height = letterbox.dst_height as u16;
+            let ts = clock.timestamp_ms(frame.pts_ns);
 
-            egfx.send_frame(&h264_frame.data, width, height, ts)
+            egfx.send_frame(&h264_frame.data, width, height, ts, h264_frame.is_keyframe)
+                != FrameDelivery::Unavailable
         }
         Ok(None) => {
             // Encoder is buffering, no output yet — fall back to bitmap
@@ -609,42 +1313,159 @@ fn try_send_egfx_frame(
 }
 
 /// Convert a [`CursorInfo`] to the appropriate [`DisplayUpdate`] variant.
-fn cursor_to_display_update(cursor: &CursorInfo) -> DisplayUpdate {
+///
+/// Positions are mapped onto the client's desktop, and bitmaps (with their
+/// hotspot) are scaled by the same factor as the frame and the client's
+/// scale factor, so the pointer matches the scaled desktop. A bitmap the
+/// client is already showing is sent as a position update instead.
+fn cursor_to_display_update(
+    cursor: &CursorInfo,
+    scale: &ScaleState,
+    pointer: &mut ShownPointer,
+) -> DisplayUpdate {
     if !cursor.visible {
+        pointer.forget();
         return DisplayUpdate::HidePointer;
     }
 
+    #[allow(clippy::cast_sign_loss)]
+    let (x, y) = (cursor.x.max(0) as u32, cursor.y.max(0) as u32);
+
     if let Some(ref bitmap) = cursor.bitmap {
-        #[allow(clippy::cast_possible_truncation)]
-        DisplayUpdate::RGBAPointer(RGBAPointer {
-            width: bitmap.width as u16,
-            height: bitmap.height as u16,
-            hot_x: bitmap.hot_x as u16,
-            hot_y: bitmap.hot_y as u16,
-            data: bitmap.data.clone(),
-        })
-    } else {
-        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-        DisplayUpdate::PointerPosition(PointerPositionAttribute {
-            x: cursor.x.max(0) as u16,
-            y: cursor.y.max(0) as u16,
-        })
+        let bitmap = scale_cursor(bitmap, scale.cursor_factor(x, y));
+        if pointer.show(&bitmap) {
+            tracing::trace!(bitmap.width, bitmap.height, "Pointer shape changed");
+            #[allow(clippy::cast_possible_truncation)]
+            return DisplayUpdate::RGBAPointer(RGBAPointer {
+                width: bitmap.width as u16,
+                height: bitmap.height as u16,
+                hot_x: bitmap.hot_x as u16,
+                hot_y: bitmap.hot_y as u16,
+                data: bitmap.data,
+            });
+        }
     }
+
+    let (x, y) = scale.map.to_dest(x, y);
+    #[allow(clippy::cast_possible_truncation)]
+    DisplayUpdate::PointerPosition(PointerPositionAttribute {
+        x: x as u16,
+        y: y as u16,
+    })
 }
 
-/// Convert a captured frame to an ironrdp `BitmapUpdate`.
-fn frame_to_bitmap(frame: CapturedFrame) -> Result<BitmapUpdate> {
-    let width = u16::try_from(frame.width)
-        .map_err(|_| anyhow::anyhow!("frame width {} exceeds u16", frame.width))?;
-    let height = u16::try_from(frame.height)
-        .map_err(|_| anyhow::anyhow!("frame height {} exceeds u16", frame.height))?;
+/// Scale a cursor bitmap and its hotspot by `factor`.
+///
+/// Factors within 1% of 1.0 leave the bitmap unchanged. The result is
+/// limited to [`MAX_CURSOR_SIZE`] on either side.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn scale_cursor(bitmap: &CursorBitmap, factor: f64) -> CursorBitmap {
+    if (factor - 1.0).abs() < 0.01 || bitmap.width == 0 || bitmap.height == 0 {
+        return bitmap.clone();
+    }
+    let longest = bitmap.width.max(bitmap.height);
+    let factor = factor.min(f64::from(MAX_CURSOR_SIZE) / f64::from(longest));
+    let scaled = |v: u32| ((f64::from(v) * factor).round() as u32).max(1);
+    let (width, height) = (scaled(bitmap.width), scaled(bitmap.height));
+
+    let letterbox = Letterbox::stretch(bitmap.width, bitmap.height, width, height);
+    CursorBitmap {
+        width,
+        height,
+        hot_x: scaled(bitmap.hot_x).min(width - 1),
+        hot_y: scaled(bitmap.hot_y).min(height - 1),
+        data: scale_bgra(&bitmap.data, bitmap.width as usize * 4, &letterbox),
+    }
+}
+
+/// Draw a frame onto the client's desktop as laid out by `map`.
+fn compose_frame(frame: &CapturedFrame, map: &DesktopMap) -> CapturedFrame {
+    CapturedFrame {
+        data: Bytes::from(map.compose(&frame.data, frame.stride as usize)),
+        width: map.width,
+        height: map.height,
+        format: frame.format,
+        stride: map.width * 4,
+        sequence: frame.sequence,
+        pts_ns: frame.pts_ns,
+        damage: None,
+    }
+}
+
+/// A frame in the blue of the static display, shown while the capture
+/// reconnects.
+fn placeholder_frame(width: u32, height: u32) -> CapturedFrame {
+    CapturedFrame {
+        data: Bytes::from(BLUE_BGRA.repeat(width as usize * height as usize)),
+        width,
+        height,
+        format: FrameFormat::Bgra,
+        stride: width * 4,
+        sequence: 0,
+        pts_ns: rdp_capture::monotonic_ns(),
+        damage: None,
+    }
+}
+
+/// Convert an NV12 frame to BGRA, for the paths that work on RGB.
+fn nv12_to_bgra_frame(frame: &CapturedFrame) -> CapturedFrame {
+    let stride = frame.width * 4;
+    let mut data = vec![0; stride as usize * frame.height as usize];
+    nv12_to_bgra(
+        &frame.data,
+        frame.stride as usize,
+        frame.width,
+        frame.height,
+        &mut data,
+        stride as usize,
+    );
+    CapturedFrame {
+        data: Bytes::from(data),
+        width: frame.width,
+        height: frame.height,
+        format: FrameFormat::Bgra,
+        stride,
+        sequence: frame.sequence,
+        pts_ns: frame.pts_ns,
+        damage: frame.damage.clone(),
+    }
+}
+
+/// Convert a captured frame to an ironrdp `BitmapUpdate` covering the
+/// client's desktop.
+///
+/// Frames that do not match the desktop are scaled and letterboxed onto
+/// it on the CPU first; others share the frame's buffer. NV12 frames are
+/// converted to BGRA.
+fn frame_to_bitmap(frame: &CapturedFrame, map: &DesktopMap) -> Result<BitmapUpdate> {
+    if frame.format == FrameFormat::Nv12 {
+        return frame_to_bitmap(&nv12_to_bgra_frame(frame), map);
+    }
+    let identity = map
+        .as_letterbox(frame.width, frame.height)
+        .is_some_and(|letterbox| letterbox.is_identity());
+    let (width, height, stride, data) = if identity {
+        (frame.width, frame.height, frame.stride as usize, frame.data.clone())
+    } else {
+        let data = Bytes::from(map.compose(&frame.data, frame.stride as usize));
+        (map.width, map.height, map.width as usize * 4, data)
+    };
+
+    let width = u16::try_from(width)
+        .map_err(|_| anyhow::anyhow!("frame width {width} exceeds u16"))?;
+    let height = u16::try_from(height)
+        .map_err(|_| anyhow::anyhow!("frame height {height} exceeds u16"))?;
 
     let width =
         NonZeroU16::new(width).ok_or_else(|| anyhow::anyhow!("frame width is zero"))?;
     let height =
         NonZeroU16::new(height).ok_or_else(|| anyhow::anyhow!("frame height is zero"))?;
-    let stride = NonZeroUsize::new(frame.stride as usize)
-        .ok_or_else(|| anyhow::anyhow!("frame stride is zero"))?;
+    let stride =
+        NonZeroUsize::new(stride).ok_or_else(|| anyhow::anyhow!("frame stride is zero"))?;
 
     Ok(BitmapUpdate {
         x: 0,
@@ -652,7 +1473,7 @@ fn frame_to_bitmap(frame: CapturedFrame) -> Result<BitmapUpdate> {
         width,
         height,
         format: PixelFormat::BgrA32,
-        data: Bytes::from(frame.data),
+        data,
         stride,
     })
 }
@@ -692,7 +1513,7 @@ pub fn build_server(
     auth: Option<&AuthCredentials>,
     cliprdr: Option<Box<dyn CliprdrServerFactory>>,
     sound: Option<Box<dyn SoundServerFactory>>,
-    egfx_factory: Option<Box<dyn ironrdp_dvc::DvcProcessorFactory>>,
+    dvc_factories: Vec<Box<dyn ironrdp_dvc::DvcProcessorFactory>>,
 ) -> RdpServer {
     let builder = RdpServer::builder().with_addr(bind_addr);
     let builder = with_security!(builder, tls, auth);
@@ -703,7 +1524,7 @@ pub fn build_server(
         .with_sound_factory(sound)
         .build();
     apply_credentials(&mut server, auth);
-    if let Some(factory) = egfx_factory {
+    for factory in dvc_factories {
         server.add_dvc_factory(factory);
     }
     server
@@ -711,8 +1532,8 @@ pub fn build_server(
 
 /// Build an RDP server with live screen capture and input injection.
 ///
-/// If `egfx_factory` is provided, it is registered as a DVC processor factory
-/// for EGFX/H.264 frame delivery through the DRDYNVC channel. A fresh
+/// Each entry of `dvc_factories` (EGFX/H.264 delivery, microphone input)
+/// is registered as a DVC processor factory on the DRDYNVC channel. A fresh
 /// processor is created for each RDP connection.
 #[allow(clippy::too_many_arguments)]
 pub fn build_live_server(
@@ -723,7 +1544,7 @@ pub fn build_live_server(
     input_handler: LiveInputHandler,
     cliprdr: Option<Box<dyn CliprdrServerFactory>>,
     sound: Option<Box<dyn SoundServerFactory>>,
-    egfx_factory: Option<Box<dyn ironrdp_dvc::DvcProcessorFactory>>,
+    dvc_factories: Vec<Box<dyn ironrdp_dvc::DvcProcessorFactory>>,
 ) -> RdpServer {
     let builder = RdpServer::builder().with_addr(bind_addr);
     let builder = with_security!(builder, tls, auth);
@@ -734,7 +1555,7 @@ pub fn build_live_server(
         .with_sound_factory(sound)
         .build();
     apply_credentials(&mut server, auth);
-    if let Some(factory) = egfx_factory {
+    for factory in dvc_factories {
         server.add_dvc_factory(factory);
     }
     server
@@ -748,6 +1569,7 @@ pub fn build_view_only_server(
     display: LiveDisplay,
     cliprdr: Option<Box<dyn CliprdrServerFactory>>,
     sound: Option<Box<dyn SoundServerFactory>>,
+    dvc_factories: Vec<Box<dyn ironrdp_dvc::DvcProcessorFactory>>,
 ) -> RdpServer {
     let builder = RdpServer::builder().with_addr(bind_addr);
     let builder = with_security!(builder, tls, auth);
@@ -758,6 +1580,9 @@ pub fn build_view_only_server(
         .with_sound_factory(sound)
         .build();
     apply_credentials(&mut server, auth);
+    for factory in dvc_factories {
+        server.add_dvc_factory(factory);
+    }
     server
 }
 
@@ -2,14 +2,151 @@
 //!
 //! Captures desktop audio via `PipeWire` and forwards it to the RDP client
 //! over the RDPSND virtual channel.
+//!
+//! Besides raw 16-bit PCM, Opus and AAC are offered when a `GStreamer`
+//! encoder is installed. The client's format list decides which one is
+//! used; compressed formats cut bandwidth from ~1.4 Mbit/s to the
+//! configured bitrate.
+//!
+//! By default the monitor of the default sink is captured. A specific
+//! sink or application node can be selected instead, or a dedicated null
+//! sink can be created per session and made the default output while the
+//! client is connected.
+//!
+//! Wave timestamps come from the `PipeWire` presentation time on the
+//! same [`MediaClock`] as EGFX frames, so clients keep audio in sync with
+//! video. While the client's playback falls too far behind, measured from
+//! the timestamps in its wave confirmations, captured audio is dropped.
+//!
+//! In redirect mode local outputs are muted while the client plays audio,
+//! so sound only comes out on the remote side.
 
 use ironrdp_rdpsnd::pdu::{AudioFormat, ClientAudioFormatPdu, WaveFormat};
 use ironrdp_server::{
     RdpsndServerHandler, RdpsndServerMessage, ServerEvent, ServerEventSender, SoundServerFactory,
 };
-use rdp_capture::{AudioChunk, PwAudioStream};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rdp_capture::{
+    AudioChunk, AudioSource, LocalOutputMute, MediaClock, PwAudioStream, PwSessionSink,
+};
+use rdp_encode::{aac_audio_specific_config, AudioCodec, AudioEncoderConfig, GstAudioEncoder};
 use tokio::sync::mpsc;
 
+/// Name of the per-session sink shown in sound settings.
+const SESSION_SINK_DESCRIPTION: &str = "RDP Session";
+
+/// Most client playback lag tolerated.
+///
+/// When the client or network falls behind, waves queue up on the client;
+/// playing them late would leave audio trailing the video, so captured
+/// audio is dropped instead until the client has caught up.
+const MAX_AUDIO_LAG: Duration = Duration::from_millis(250);
+
+/// Most waves tracked while waiting for their confirmation.
+const MAX_UNCONFIRMED_WAVES: usize = 256;
+
+/// Where captured audio comes from and what happens to local playback.
+#[derive(Debug, Clone)]
+pub struct AudioRouting {
+    /// What to capture when no session sink is used.
+    pub source: AudioSource,
+    /// Create a per-session null sink and capture it.
+    pub virtual_sink: bool,
+    /// Mute local outputs while the client plays audio ("redirect" mode).
+    pub mute_local: bool,
+    /// File recording muted outputs, for recovery after a crash.
+    pub mute_marker: Option<PathBuf>,
+}
+
+impl AudioRouting {
+    /// Build routing from the audio config values.
+    ///
+    /// `mode` is "mirror" or "redirect"; unknown values fall back to
+    /// mirroring.
+    pub fn new(source: &str, virtual_sink: bool, mode: &str, mute_marker: Option<PathBuf>) -> Self {
+        let mute_local = match mode {
+            "redirect" => true,
+            "mirror" => false,
+            other => {
+                tracing::warn!(mode = other, "Unknown audio mode, using mirror");
+                false
+            }
+        };
+        Self {
+            source: AudioSource::parse(source),
+            virtual_sink,
+            mute_local,
+            mute_marker,
+        }
+    }
+}
+
+impl Default for AudioRouting {
+    fn default() -> Self {
+        Self {
+            source: AudioSource::DefaultSink,
+            virtual_sink: false,
+            mute_local: false,
+            mute_marker: None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Client lag
+// ---------------------------------------------------------------------------
+
+/// Client playback lag, measured from RDPSND wave confirmations.
+///
+/// The client confirms each wave once played, echoing its timestamp plus
+/// the time the wave spent on the client. Confirmations arrive in order,
+/// so each one belongs to the oldest wave not yet confirmed.
+#[derive(Debug, Default)]
+struct ClientLag {
+    /// Timestamps of waves sent but not yet confirmed, oldest first.
+    unconfirmed: VecDeque<u16>,
+    /// Lag reported by the latest confirmation.
+    latest: Duration,
+}
+
+impl ClientLag {
+    /// Record a wave sent with timestamp `ts`.
+    fn sent(&mut self, ts: u32) {
+        if self.unconfirmed.len() == MAX_UNCONFIRMED_WAVES {
+            // The client is not confirming; forget the oldest wave.
+            self.unconfirmed.pop_front();
+        }
+        self.unconfirmed.push_back(wire_timestamp(ts));
+    }
+
+    /// Record a wave confirmation carrying `timestamp`.
+    fn confirmed(&mut self, timestamp: u16) {
+        if let Some(sent) = self.unconfirmed.pop_front() {
+            self.latest = Duration::from_millis(u64::from(timestamp.wrapping_sub(sent)));
+        }
+    }
+
+    /// How far the client's playback is behind. Once every wave sent has
+    /// been played there is no lag, whatever the last one reported.
+    fn lag(&self) -> Duration {
+        if self.unconfirmed.is_empty() {
+            Duration::ZERO
+        } else {
+            self.latest
+        }
+    }
+}
+
+/// Wave timestamps are 16 bits on the wire and wrap around.
+#[allow(clippy::cast_possible_truncation)] // wrapping is intended
+fn wire_timestamp(ts: u32) -> u16 {
+    ts as u16
+}
+
 // ---------------------------------------------------------------------------
 // Handler (one per RDP connection)
 // ---------------------------------------------------------------------------
@@ -18,56 +155,108 @@ use tokio::sync::mpsc;
 /// to the RDP client.
 #[derive(Debug)]
 pub struct PipeWireAudioHandler {
+    /// Formats offered to the client, in order of preference.
     formats: Vec<AudioFormat>,
+    /// Codec for each entry in `formats` (`None` = raw PCM).
+    codecs: Vec<Option<AudioCodec>>,
     channels: u16,
     sample_rate: u32,
+    bitrate: u32,
+    routing: AudioRouting,
     event_tx: mpsc::UnboundedSender<ServerEvent>,
+    session_sink: Option<PwSessionSink>,
+    /// Keeps local outputs muted in redirect mode.
+    output_mute: Option<LocalOutputMute>,
     audio_stream: Option<PwAudioStream>,
     pump_abort: Option<tokio::sync::oneshot::Sender<()>>,
+    /// Fed by wave confirmations, read by the pump.
+    client_lag: Arc<Mutex<ClientLag>>,
 }
 
 impl PipeWireAudioHandler {
     fn new(
         channels: u16,
         sample_rate: u32,
+        compressed: &[AudioCodec],
+        bitrate: u32,
+        routing: AudioRouting,
         event_tx: mpsc::UnboundedSender<ServerEvent>,
     ) -> Self {
-        let block_align = channels * 2; // 16-bit samples
-        let avg_bytes_per_sec = u32::from(block_align) * sample_rate;
-
-        let formats = vec![AudioFormat {
-            format: WaveFormat::PCM,
-            n_channels: channels,
-            n_samples_per_sec: sample_rate,
-            n_avg_bytes_per_sec: avg_bytes_per_sec,
-            n_block_align: block_align,
-            bits_per_sample: 16,
-            data: None,
-        }];
+        // Compressed codecs first (preferred), PCM last as the fallback
+        // every client supports.
+        let codecs: Vec<Option<AudioCodec>> = compressed
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .collect();
+        let formats = codecs
+            .iter()
+            .map(|&codec| audio_format(codec, channels, sample_rate, bitrate))
+            .collect();
 
         Self {
             formats,
+            codecs,
             channels,
             sample_rate,
+            bitrate,
+            routing,
             event_tx,
+            session_sink: None,
+            output_mute: None,
             audio_stream: None,
             pump_abort: None,
+            client_lag: Arc::default(),
         }
     }
 
-    /// Forward audio chunks from `PipeWire` to the RDP RDPSND channel.
+    /// Resolve the capture source, creating the session sink if enabled.
+    fn capture_source(&mut self) -> AudioSource {
+        if !self.routing.virtual_sink {
+            return self.routing.source.clone();
+        }
+        let node_name = format!("cosmic-ext-rdp-session-{}", std::process::id());
+        match PwSessionSink::start(
+            &node_name,
+            SESSION_SINK_DESCRIPTION,
+            self.channels,
+            self.sample_rate,
+            true,
+        ) {
+            Ok(sink) => {
+                let source = AudioSource::Sink(sink.node_name().to_string());
+                self.session_sink = Some(sink);
+                source
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to create session sink: {e}, capturing {}",
+                    self.routing.source
+                );
+                self.routing.source.clone()
+            }
+        }
+    }
+
+    /// Forward audio chunks from `PipeWire` to the RDP RDPSND channel,
+    /// compressing them first if `encoder` is set.
     fn start_pump(
         &self,
         audio_rx: mpsc::Receiver<AudioChunk>,
+        mut encoder: Option<GstAudioEncoder>,
     ) -> tokio::sync::oneshot::Sender<()> {
         let event_tx = self.event_tx.clone();
         let (abort_tx, mut abort_rx) = tokio::sync::oneshot::channel();
+        let clock = MediaClock::shared();
+        let client_lag = Arc::clone(&self.client_lag);
 
         // Use a tokio runtime handle. The handler runs on the server's
         // async context, so `Handle::current()` is available.
         let rt = tokio::runtime::Handle::current();
         rt.spawn(async move {
             let mut audio_rx = audio_rx;
+            let mut dropped: u64 = 0;
             loop {
                 tokio::select! {
                     chunk = audio_rx.recv() => {
@@ -75,11 +264,38 @@ impl PipeWireAudioHandler {
                             tracing::debug!("Audio capture channel closed");
                             break;
                         };
-                        // Timestamp in milliseconds (approximate from sequence).
-                        #[allow(clippy::cast_possible_truncation)]
-                        let ts = (chunk.sequence * 1000 / u64::from(chunk.sample_rate)) as u32;
-                        let msg = RdpsndServerMessage::Wave(chunk.data, ts);
-                        if event_tx.send(ServerEvent::Rdpsnd(msg)).is_err() {
+                        let lag = client_lag.lock().map_or(Duration::ZERO, |lag| lag.lag());
+                        if lag > MAX_AUDIO_LAG {
+                            dropped += 1;
+                            if dropped.is_power_of_two() {
+                                tracing::debug!(dropped, ?lag, "Client audio lagging behind, dropping chunks");
+                            }
+                            continue;
+                        }
+                        // Each wave is stamped with its own first sample,
+                        // which for encoded packets is not the chunk's.
+                        let waves = match encoder.as_mut() {
+                            Some(enc) => match enc.encode(&chunk.data, chunk.pts_ns) {
+                                Ok(packets) => packets
+                                    .into_iter()
+                                    .map(|packet| (packet.data, packet.pts_ns))
+                                    .collect(),
+                                Err(e) => {
+                                    tracing::warn!("Audio encoding failed: {e}");
+                                    break;
+                                }
+                            },
+                            None => vec![(chunk.data, chunk.pts_ns)],
+                        };
+                        let closed = waves.into_iter().any(|(wave, pts_ns)| {
+                            let ts = clock.timestamp_ms(pts_ns);
+                            if let Ok(mut lag) = client_lag.lock() {
+                                lag.sent(ts);
+                            }
+                            let msg = RdpsndServerMessage::Wave(wave, ts);
+                            event_tx.send(ServerEvent::Rdpsnd(msg)).is_err()
+                        });
+                        if closed {
                             tracing::debug!("Sound event channel closed");
                             break;
                         }
@@ -101,28 +317,83 @@ impl RdpsndServerHandler for PipeWireAudioHandler {
         &self.formats
     }
 
-    fn start(&mut self, _client_format: &ClientAudioFormatPdu) -> Option<u16> {
+    fn start(&mut self, client_format: &ClientAudioFormatPdu) -> Option<u16> {
+        let Some((server_idx, client_idx)) =
+            negotiate_format(&self.formats, &client_format.formats)
+        else {
+            tracing::warn!(
+                client_formats = ?client_format.formats,
+                "Client supports none of the offered audio formats"
+            );
+            return None;
+        };
+        let client_idx = u16::try_from(client_idx).ok()?;
+
+        // Set up the encoder, falling back to PCM if the client also
+        // listed it and the pipeline cannot be built.
+        let mut codec = self.codecs[server_idx];
+        let mut wave_format_no = client_idx;
+        let encoder = match codec {
+            Some(c) => match GstAudioEncoder::new(&AudioEncoderConfig {
+                codec: c,
+                channels: self.channels,
+                sample_rate: self.sample_rate,
+                bitrate: self.bitrate,
+            }) {
+                Ok(enc) => Some(enc),
+                Err(e) => {
+                    tracing::warn!("Failed to create {c} audio encoder: {e}, trying PCM");
+                    let pcm = audio_format(None, self.channels, self.sample_rate, self.bitrate);
+                    let idx = client_format
+                        .formats
+                        .iter()
+                        .position(|f| formats_match(f, &pcm))?;
+                    wave_format_no = u16::try_from(idx).ok()?;
+                    codec = None;
+                    None
+                }
+            },
+            None => None,
+        };
+
         tracing::info!(
             channels = self.channels,
             sample_rate = self.sample_rate,
+            codec = codec.map_or_else(|| "PCM".to_string(), |c| c.to_string()),
+            format_no = wave_format_no,
             "Starting audio capture for RDPSND"
         );
 
-        match PwAudioStream::start(self.channels, self.sample_rate, 32) {
+        let source = self.capture_source();
+        self.client_lag = Arc::default();
+        match PwAudioStream::start(self.channels, self.sample_rate, &source, 32) {
             Ok((stream, audio_rx)) => {
-                let abort = self.start_pump(audio_rx);
+                let abort = self.start_pump(audio_rx, encoder);
                 self.audio_stream = Some(stream);
                 self.pump_abort = Some(abort);
-                // Return format index 0 (our PCM format).
-                Some(0)
+                if self.routing.mute_local {
+                    match LocalOutputMute::start(self.routing.mute_marker.clone()) {
+                        Ok(mute) => self.output_mute = Some(mute),
+                        Err(e) => tracing::warn!("Failed to mute local outputs: {e}"),
+                    }
+                }
+                // Wave PDUs reference the client's format list.
+                Some(wave_format_no)
             }
             Err(e) => {
                 tracing::warn!("Failed to start PipeWire audio capture: {e}");
+                self.session_sink = None;
                 None
             }
         }
     }
 
+    fn wave_confirm(&mut self, timestamp: u16, _block_no: u8) {
+        if let Ok(mut lag) = self.client_lag.lock() {
+            lag.confirmed(timestamp);
+        }
+    }
+
     fn stop(&mut self) {
         tracing::info!("Stopping audio capture");
         // Abort the pump task first.
@@ -133,6 +404,14 @@ impl RdpsndServerHandler for PipeWireAudioHandler {
         if let Some(mut stream) = self.audio_stream.take() {
             stream.stop();
         }
+        // Then remove the session sink, restoring the previous default.
+        if let Some(mut sink) = self.session_sink.take() {
+            sink.stop();
+        }
+        // Finally unmute local outputs.
+        if let Some(mut mute) = self.output_mute.take() {
+            mute.stop();
+        }
     }
 }
 
@@ -146,14 +425,57 @@ impl RdpsndServerHandler for PipeWireAudioHandler {
 pub struct PipeWireAudioFactory {
     channels: u16,
     sample_rate: u32,
+    /// Compressed codecs with an installed encoder, in order of preference.
+    codecs: Vec<AudioCodec>,
+    bitrate: u32,
+    routing: AudioRouting,
     event_tx: Option<mpsc::UnboundedSender<ServerEvent>>,
 }
 
 impl PipeWireAudioFactory {
-    pub fn new(channels: u16, sample_rate: u32) -> Self {
+    /// Create a factory for the configured format.
+    ///
+    /// `codec` is the configured preference ("auto", "opus", "aac" or
+    /// "pcm"); codecs whose encoder is not installed are dropped here so
+    /// they are never advertised.
+    ///
+    /// `routing` selects what is captured and whether local outputs are
+    /// muted during playback.
+    pub fn new(
+        channels: u16,
+        sample_rate: u32,
+        codec: &str,
+        bitrate: u32,
+        routing: AudioRouting,
+    ) -> Self {
+        let wanted: &[AudioCodec] = match codec {
+            "opus" => &[AudioCodec::Opus],
+            "aac" => &[AudioCodec::Aac],
+            "pcm" => &[],
+            "auto" => &[AudioCodec::Opus, AudioCodec::Aac],
+            other => {
+                tracing::warn!(codec = other, "Unknown audio codec, using auto");
+                &[AudioCodec::Opus, AudioCodec::Aac]
+            }
+        };
+        let codecs = wanted
+            .iter()
+            .copied()
+            .filter(|codec| {
+                let available = codec.is_available();
+                if !available {
+                    tracing::info!(%codec, "No GStreamer encoder installed, not offering codec");
+                }
+                available
+            })
+            .collect();
+
         Self {
             channels,
             sample_rate,
+            codecs,
+            bitrate,
+            routing,
             event_tx: None,
         }
     }
@@ -174,11 +496,100 @@ impl SoundServerFactory for PipeWireAudioFactory {
         Box::new(PipeWireAudioHandler::new(
             self.channels,
             self.sample_rate,
+            &self.codecs,
+            self.bitrate,
+            self.routing.clone(),
             tx,
         ))
     }
 }
 
+// ---------------------------------------------------------------------------
+// Format helpers
+// ---------------------------------------------------------------------------
+
+/// Build the RDPSND format descriptor for a codec (`None` = 16-bit PCM).
+fn audio_format(
+    codec: Option<AudioCodec>,
+    channels: u16,
+    sample_rate: u32,
+    bitrate: u32,
+) -> AudioFormat {
+    let block_align = channels * 2; // 16-bit samples
+    match codec {
+        None => AudioFormat {
+            format: WaveFormat::PCM,
+            n_channels: channels,
+            n_samples_per_sec: sample_rate,
+            n_avg_bytes_per_sec: u32::from(block_align) * sample_rate,
+            n_block_align: block_align,
+            bits_per_sample: 16,
+            data: None,
+        },
+        // AAC frames vary in size, so the block is a single byte; the
+        // decoder is set up from the `HEAACWAVEINFO` extra data.
+        Some(AudioCodec::Aac) => AudioFormat {
+            format: WaveFormat::AAC_MS,
+            n_channels: channels,
+            n_samples_per_sec: sample_rate,
+            n_avg_bytes_per_sec: bitrate / 8,
+            n_block_align: 1,
+            bits_per_sample: 16,
+            data: Some(aac_wave_info(sample_rate, channels)),
+        },
+        Some(AudioCodec::Opus) => AudioFormat {
+            format: WaveFormat::OPUS,
+            n_channels: channels,
+            n_samples_per_sec: AudioCodec::Opus.output_rate(sample_rate),
+            n_avg_bytes_per_sec: bitrate / 8,
+            n_block_align: block_align,
+            bits_per_sample: 16,
+            data: None,
+        },
+    }
+}
+
+/// `HEAACWAVEINFO` extra data for the encoder's AAC stream: the fields
+/// following `WAVEFORMATEX`, then the `AudioSpecificConfig`.
+fn aac_wave_info(sample_rate: u32, channels: u16) -> Vec<u8> {
+    /// `wPayloadType`: ADTS frames, as `aacparse` emits them.
+    const PAYLOAD_ADTS: u16 = 1;
+    /// `wAudioProfileLevelIndication`: not specified.
+    const PROFILE_UNSPECIFIED: u16 = 0xFE;
+
+    let mut info = Vec::with_capacity(14);
+    info.extend_from_slice(&PAYLOAD_ADTS.to_le_bytes());
+    info.extend_from_slice(&PROFILE_UNSPECIFIED.to_le_bytes());
+    info.extend_from_slice(&0u16.to_le_bytes()); // wStructType
+    info.extend_from_slice(&0u16.to_le_bytes()); // wReserved1
+    info.extend_from_slice(&0u32.to_le_bytes()); // dwReserved2
+    info.extend_from_slice(&aac_audio_specific_config(sample_rate, channels));
+    info
+}
+
+/// Whether a client format entry describes the same stream as ours.
+///
+/// Compressed formats are matched on tag, channels and rate; the
+/// bits-per-sample field is only meaningful for PCM.
+fn formats_match(client: &AudioFormat, server: &AudioFormat) -> bool {
+    client.format == server.format
+        && client.n_channels == server.n_channels
+        && client.n_samples_per_sec == server.n_samples_per_sec
+        && (server.format != WaveFormat::PCM || client.bits_per_sample == server.bits_per_sample)
+}
+
+/// Pick the most preferred server format that the client also lists.
+///
+/// Returns `(server_index, client_index)`.
+fn negotiate_format(server: &[AudioFormat], client: &[AudioFormat]) -> Option<(usize, usize)> {
+    server.iter().enumerate().find_map(|(server_idx, ours)| {
+        client
+            .iter()
+            .position(|theirs| formats_match(theirs, ours))
+            .map(|client_idx| (server_idx, client_idx))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -188,6 +599,9 @@ mod tests {
         let handler = PipeWireAudioHandler::new(
             2,
             44100,
+            &[],
+            128_000,
+            AudioRouting::default(),
             mpsc::unbounded_channel().0,
         );
         let formats = handler.get_formats();
@@ -199,4 +613,94 @@ mod tests {
         assert_eq!(formats[0].n_block_align, 4); // 2 channels * 2 bytes
         assert_eq!(formats[0].n_avg_bytes_per_sec, 176_400); // 44100 * 4
     }
+
+    #[test]
+    fn compressed_formats_preferred_over_pcm() {
+        let handler = PipeWireAudioHandler::new(
+            2,
+            44100,
+            &[AudioCodec::Opus, AudioCodec::Aac],
+            128_000,
+            AudioRouting::default(),
+            mpsc::unbounded_channel().0,
+        );
+        let formats = handler.get_formats();
+        assert_eq!(formats.len(), 3);
+        assert_eq!(formats[0].format, WaveFormat::OPUS);
+        assert_eq!(formats[0].n_samples_per_sec, 48000);
+        assert_eq!(formats[1].format, WaveFormat::AAC_MS);
+        assert_eq!(formats[1].n_samples_per_sec, 44100);
+        assert_eq!(formats[1].n_block_align, 1);
+        assert_eq!(
+            formats[1].data.as_deref(),
+            Some(&[1, 0, 0xFE, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x12, 0x10][..])
+        );
+        assert_eq!(formats[2].format, WaveFormat::PCM);
+    }
+
+    #[test]
+    fn negotiate_picks_best_client_supported_format() {
+        let server = [
+            audio_format(Some(AudioCodec::Opus), 2, 44100, 128_000),
+            audio_format(Some(AudioCodec::Aac), 2, 44100, 128_000),
+            audio_format(None, 2, 44100, 128_000),
+        ];
+        // Client lists PCM first and AAC second, but no Opus.
+        let client = [
+            audio_format(None, 2, 44100, 128_000),
+            audio_format(Some(AudioCodec::Aac), 2, 44100, 64_000),
+        ];
+        assert_eq!(negotiate_format(&server, &client), Some((1, 1)));
+    }
+
+    #[test]
+    fn negotiate_falls_back_to_pcm() {
+        let server = [
+            audio_format(Some(AudioCodec::Aac), 2, 44100, 128_000),
+            audio_format(None, 2, 44100, 128_000),
+        ];
+        let client = [audio_format(None, 2, 44100, 128_000)];
+        assert_eq!(negotiate_format(&server, &client), Some((1, 0)));
+    }
+
+    #[test]
+    fn negotiate_rejects_mismatched_rate() {
+        let server = [audio_format(None, 2, 44100, 128_000)];
+        let client = [audio_format(None, 2, 22050, 128_000)];
+        assert_eq!(negotiate_format(&server, &client), None);
+    }
+
+    #[test]
+    fn client_lag_follows_confirmations() {
+        let mut lag = ClientLag::default();
+        assert_eq!(lag.lag(), Duration::ZERO);
+
+        lag.sent(1000);
+        lag.sent(1020);
+        lag.sent(1040);
+        lag.confirmed(1300);
+        assert_eq!(lag.lag(), Duration::from_millis(300));
+        lag.confirmed(1060);
+        assert_eq!(lag.lag(), Duration::from_millis(40));
+
+        // Everything sent has been played.
+        lag.confirmed(1400);
+        assert_eq!(lag.lag(), Duration::ZERO);
+    }
+
+    #[test]
+    fn client_lag_handles_timestamp_wrap() {
+        let mut lag = ClientLag::default();
+        lag.sent(0xFFF0);
+        lag.sent(0x1_0000);
+        lag.confirmed(0x0010);
+        assert_eq!(lag.lag(), Duration::from_millis(32));
+    }
+
+    #[test]
+    fn routing_mode_parsing() {
+        assert!(AudioRouting::new("", false, "redirect", None).mute_local);
+        assert!(!AudioRouting::new("", false, "mirror", None).mute_local);
+        assert!(!AudioRouting::new("", false, "bogus", None).mute_local);
+    }
 }
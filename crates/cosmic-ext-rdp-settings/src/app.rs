@@ -199,6 +199,7 @@ impl App {
                 channel_capacity: self.buffer_capacity.parse().unwrap_or(4),
                 multi_monitor: self.multi_monitor,
                 swap_colors: true,
+                ..rdp_dbus::config::CaptureConfig::default()
             },
             encode: rdp_dbus::config::EncodeConfig {
                 encoder,
@@ -207,11 +208,13 @@ impl App {
             },
             clipboard: rdp_dbus::config::ClipboardConfig {
                 enable: self.clipboard_enable,
+                ..rdp_dbus::config::ClipboardConfig::default()
             },
             audio: rdp_dbus::config::AudioConfig {
                 enable: self.audio_enable,
                 sample_rate,
                 channels,
+                ..rdp_dbus::config::AudioConfig::default()
             },
         }
     }
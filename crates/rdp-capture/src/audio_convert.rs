@@ -0,0 +1,458 @@
+//! Sample format conversion, channel remixing and resampling for audio
+//! capture.
+//!
+//! `PipeWire` normally converts to the S16LE format we request, but some
+//! graphs (e.g. after switching output devices) deliver the sink's native
+//! format instead. Passing that through unchanged makes the client play
+//! at the wrong speed or pitch, so anything that does not match the
+//! requested format is converted here.
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+
+/// Interleaved sample formats accepted from `PipeWire`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed 16-bit little-endian.
+    S16Le,
+    /// Signed 32-bit little-endian.
+    S32Le,
+    /// 32-bit float little-endian.
+    F32Le,
+}
+
+impl SampleFormat {
+    /// Size of one sample in bytes.
+    #[must_use]
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::S16Le => 2,
+            Self::S32Le | Self::F32Le => 4,
+        }
+    }
+}
+
+/// Audio format negotiated with `PipeWire`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormatInfo {
+    /// Sample format.
+    pub format: SampleFormat,
+    /// Sample rate in Hz.
+    pub rate: u32,
+    /// Number of interleaved channels.
+    pub channels: u16,
+}
+
+/// Converts captured audio to interleaved S16LE at a target rate and
+/// channel count.
+///
+/// Rates are converted by `GStreamer`'s `audioresample`, whose filter
+/// keeps downsampling (e.g. 48 kHz to 44.1 kHz) free of aliasing. Its
+/// output trails the input by a chunk or so. Without `GStreamer`, rates are
+/// interpolated linearly instead, which is only clean for upsampling by
+/// an integer ratio; other conversions alias. Buffers are kept between
+/// chunks, so a `PipeWire` quantum only allocates the chunk it returns.
+#[derive(Debug)]
+pub struct AudioConverter {
+    input: AudioFormatInfo,
+    out_rate: u32,
+    out_channels: u16,
+    /// Decoded, remixed samples of the current chunk.
+    samples: Vec<f32>,
+    /// Rate conversion, if the rates differ.
+    resampler: Option<Resampler>,
+}
+
+impl AudioConverter {
+    /// Create a converter from `input` to S16LE at `out_rate` / `out_channels`.
+    #[must_use]
+    pub fn new(input: AudioFormatInfo, out_rate: u32, out_channels: u16) -> Self {
+        let resampler = (input.rate != out_rate && input.rate != 0 && out_channels != 0)
+            .then(|| Resampler::new(out_channels, input.rate, out_rate));
+        Self::with_resampler(input, out_rate, out_channels, resampler)
+    }
+
+    fn with_resampler(
+        input: AudioFormatInfo,
+        out_rate: u32,
+        out_channels: u16,
+        resampler: Option<Resampler>,
+    ) -> Self {
+        Self {
+            input,
+            out_rate,
+            out_channels,
+            samples: Vec::new(),
+            resampler,
+        }
+    }
+
+    /// Input format this converter was created for.
+    #[must_use]
+    pub fn input(&self) -> AudioFormatInfo {
+        self.input
+    }
+
+    /// Whether the input already matches the output (no work needed).
+    #[must_use]
+    pub fn is_passthrough(&self) -> bool {
+        self.input.format == SampleFormat::S16Le
+            && self.input.rate == self.out_rate
+            && self.input.channels == self.out_channels
+    }
+
+    /// Convert one chunk of interleaved input to interleaved S16LE.
+    #[must_use]
+    pub fn process(&mut self, input: &[u8]) -> Vec<u8> {
+        if self.is_passthrough() {
+            return input.to_vec();
+        }
+        if self.input.channels == 0 || self.out_channels == 0 || self.input.rate == 0 {
+            return Vec::new();
+        }
+
+        self.decode_and_remix(input);
+        let samples = match self.resampler.as_mut() {
+            Some(resampler) => resampler.process(&self.samples),
+            None => &self.samples,
+        };
+
+        let mut out = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            #[allow(clippy::cast_possible_truncation)]
+            let value = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    /// Decode interleaved input into `samples`: normalised `f32`, with the
+    /// output channel count.
+    fn decode_and_remix(&mut self, input: &[u8]) {
+        let bps = self.input.format.bytes_per_sample();
+        let in_channels = usize::from(self.input.channels);
+        let out_channels = usize::from(self.out_channels);
+
+        self.samples.clear();
+        let mut frame = [0.0f32; MAX_CHANNELS];
+        for bytes in input.chunks_exact(bps * in_channels) {
+            let decoded = bytes.chunks_exact(bps).take(MAX_CHANNELS);
+            let count = decoded.len();
+            for (slot, sample) in frame.iter_mut().zip(decoded) {
+                *slot = decode_sample(self.input.format, sample);
+            }
+            remix(&frame[..count], out_channels, &mut self.samples);
+        }
+    }
+}
+
+/// Most input channels read per frame; further ones are dropped.
+const MAX_CHANNELS: usize = 64;
+
+/// Sample rate conversion of interleaved `f32` samples.
+#[derive(Debug)]
+enum Resampler {
+    Gst(GstResampler),
+    Linear(LinearResampler),
+}
+
+impl Resampler {
+    /// `GStreamer`'s resampler if it is installed, else linear.
+    fn new(channels: u16, in_rate: u32, out_rate: u32) -> Self {
+        match GstResampler::new(channels, in_rate, out_rate) {
+            Ok(resampler) => Self::Gst(resampler),
+            Err(e) => {
+                tracing::warn!(
+                    in_rate,
+                    out_rate,
+                    "GStreamer audioresample unavailable ({e}), resampling linearly; \
+                     downsampling will alias"
+                );
+                Self::Linear(LinearResampler::new(channels, in_rate, out_rate))
+            }
+        }
+    }
+
+    /// Resample `samples`, returning the output that is ready.
+    fn process(&mut self, samples: &[f32]) -> &[f32] {
+        match self {
+            Self::Gst(resampler) => resampler.process(samples),
+            Self::Linear(resampler) => resampler.process(samples),
+        }
+    }
+}
+
+/// `appsrc ! audioresample ! appsink`, fed from the `PipeWire` thread.
+#[derive(Debug)]
+struct GstResampler {
+    pipeline: gst::Pipeline,
+    appsrc: gst_app::AppSrc,
+    appsink: gst_app::AppSink,
+    in_rate: u32,
+    channels: usize,
+    /// Input frames pushed so far, for buffer timestamps.
+    frames_in: u64,
+    /// Resampled samples of the current chunk.
+    out: Vec<f32>,
+}
+
+impl GstResampler {
+    fn new(channels: u16, in_rate: u32, out_rate: u32) -> Result<Self, gst::glib::Error> {
+        gst::init()?;
+        let caps = |rate: u32| {
+            gst::Caps::builder("audio/x-raw")
+                .field("format", "F32LE")
+                .field("layout", "interleaved")
+                .field("rate", i32::try_from(rate).unwrap_or(i32::MAX))
+                .field("channels", i32::from(channels))
+                .build()
+        };
+
+        let appsrc = gst_app::AppSrc::builder()
+            .name("resample-source")
+            .caps(&caps(in_rate))
+            .format(gst::Format::Time)
+            .build();
+        let resample = gst::ElementFactory::make("audioresample")
+            .name("resample")
+            .build()
+            .map_err(|e| gst_error(gst::CoreError::MissingPlugin, e))?;
+        let appsink = gst_app::AppSink::builder()
+            .name("resample-sink")
+            .caps(&caps(out_rate))
+            .sync(false)
+            .build();
+
+        let pipeline = gst::Pipeline::new();
+        let elements: [&gst::Element; 3] = [appsrc.upcast_ref(), &resample, appsink.upcast_ref()];
+        pipeline
+            .add_many(elements)
+            .and_then(|()| gst::Element::link_many(elements))
+            .map_err(|e| gst_error(gst::CoreError::Negotiation, e))?;
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|e| gst_error(gst::CoreError::StateChange, e))?;
+
+        Ok(Self {
+            pipeline,
+            appsrc,
+            appsink,
+            in_rate,
+            channels: usize::from(channels),
+            frames_in: 0,
+            out: Vec::new(),
+        })
+    }
+
+    fn process(&mut self, samples: &[f32]) -> &[f32] {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let frames = (samples.len() / self.channels) as u64;
+        let mut buffer = gst::Buffer::from_mut_slice(bytes);
+        if let Some(buffer) = buffer.get_mut() {
+            let rate = u64::from(self.in_rate);
+            buffer.set_pts(gst::ClockTime::from_nseconds(
+                self.frames_in * 1_000_000_000 / rate,
+            ));
+            buffer.set_duration(gst::ClockTime::from_nseconds(frames * 1_000_000_000 / rate));
+        }
+        self.frames_in += frames;
+        if let Err(e) = self.appsrc.push_buffer(buffer) {
+            tracing::warn!("Failed to push audio for resampling: {e}");
+        }
+
+        self.out.clear();
+        while let Some(sample) = self.appsink.try_pull_sample(gst::ClockTime::ZERO) {
+            let Some(map) = sample.buffer().and_then(|b| b.map_readable().ok()) else {
+                continue;
+            };
+            self.out.extend(
+                map.chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            );
+        }
+        &self.out
+    }
+}
+
+/// A `GStreamer` error for `code`, described by `e`.
+fn gst_error(code: gst::CoreError, e: impl std::fmt::Display) -> gst::glib::Error {
+    gst::glib::Error::new(code, &e.to_string())
+}
+
+impl Drop for GstResampler {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Linear interpolation, keeping state between calls so consecutive
+/// chunks join without clicks.
+#[derive(Debug)]
+struct LinearResampler {
+    channels: usize,
+    /// Input frames per output frame.
+    step: f64,
+    /// Fractional read position, in frames into `stream`.
+    pos: f64,
+    /// Last frame of the previous chunk, followed by the current chunk.
+    stream: Vec<f32>,
+    /// Resampled samples of the current chunk.
+    out: Vec<f32>,
+}
+
+impl LinearResampler {
+    fn new(channels: u16, in_rate: u32, out_rate: u32) -> Self {
+        Self {
+            channels: usize::from(channels),
+            step: f64::from(in_rate) / f64::from(out_rate),
+            pos: 0.0,
+            stream: Vec::new(),
+            out: Vec::new(),
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn process(&mut self, samples: &[f32]) -> &[f32] {
+        let channels = self.channels;
+        self.stream.extend_from_slice(samples);
+        self.out.clear();
+
+        let frames = self.stream.len() / channels;
+        if frames == 0 {
+            return &self.out;
+        }
+        let last = frames - 1;
+        while self.pos < last as f64 {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let i = self.pos as usize;
+            #[allow(clippy::cast_possible_truncation)]
+            let t = (self.pos - i as f64) as f32;
+            let (a, b) = self.stream[i * channels..(i + 2) * channels].split_at(channels);
+            self.out
+                .extend(a.iter().zip(b).map(|(&a, &b)| a + (b - a) * t));
+            self.pos += self.step;
+        }
+
+        // Keep the last frame to interpolate from next time.
+        self.pos -= last as f64;
+        self.stream.drain(..last * channels);
+        &self.out
+    }
+}
+
+/// Decode one little-endian sample to `f32` in `-1.0..=1.0`.
+fn decode_sample(format: SampleFormat, bytes: &[u8]) -> f32 {
+    match format {
+        SampleFormat::S16Le => f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+        SampleFormat::S32Le => {
+            #[allow(clippy::cast_precision_loss)]
+            let v = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32;
+            v / 2_147_483_648.0
+        }
+        SampleFormat::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+/// Remix one frame to `out_channels`, appending it to `out`.
+///
+/// Downmix to mono averages all channels, mono is duplicated to every
+/// output channel, and otherwise channels are mapped by position (extra
+/// input channels are dropped, missing ones repeat the last input).
+fn remix(samples: &[f32], out_channels: usize, out: &mut Vec<f32>) {
+    if samples.len() == out_channels {
+        out.extend_from_slice(samples);
+    } else if out_channels == 1 {
+        #[allow(clippy::cast_precision_loss)]
+        let avg = samples.iter().sum::<f32>() / samples.len() as f32;
+        out.push(avg);
+    } else {
+        out.extend((0..out_channels).map(|c| samples[c.min(samples.len() - 1)]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s16(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    fn to_i16(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .chunks_exact(2)
+            .map(|c| i16::from_le_bytes([c[0], c[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn passthrough_when_formats_match() {
+        let input = AudioFormatInfo {
+            format: SampleFormat::S16Le,
+            rate: 44100,
+            channels: 2,
+        };
+        let mut conv = AudioConverter::new(input, 44100, 2);
+        assert!(conv.is_passthrough());
+        let data = s16(&[1, 2, 3, 4]);
+        assert_eq!(conv.process(&data), data);
+    }
+
+    #[test]
+    fn f32_to_s16() {
+        let input = AudioFormatInfo {
+            format: SampleFormat::F32Le,
+            rate: 48000,
+            channels: 1,
+        };
+        let mut conv = AudioConverter::new(input, 48000, 1);
+        let data: Vec<u8> = [0.0f32, 1.0, -1.0]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(to_i16(&conv.process(&data)), vec![0, i16::MAX, -i16::MAX]);
+    }
+
+    #[test]
+    fn mono_to_stereo_duplicates() {
+        let input = AudioFormatInfo {
+            format: SampleFormat::S16Le,
+            rate: 48000,
+            channels: 1,
+        };
+        let mut conv = AudioConverter::new(input, 48000, 2);
+        let out = to_i16(&conv.process(&s16(&[16384])));
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0], out[1]);
+    }
+
+    #[test]
+    fn stereo_to_mono_averages() {
+        let input = AudioFormatInfo {
+            format: SampleFormat::S16Le,
+            rate: 48000,
+            channels: 2,
+        };
+        let mut conv = AudioConverter::new(input, 48000, 1);
+        let out = to_i16(&conv.process(&s16(&[16384, 0])));
+        assert_eq!(out.len(), 1);
+        assert!((i32::from(out[0]) - 8191).abs() <= 1);
+    }
+
+    #[test]
+    fn resample_48k_to_44k1_keeps_duration() {
+        let input = AudioFormatInfo {
+            format: SampleFormat::S16Le,
+            rate: 48000,
+            channels: 1,
+        };
+        let linear = Resampler::Linear(LinearResampler::new(1, 48000, 44100));
+        let mut conv = AudioConverter::with_resampler(input, 44100, 1, Some(linear));
+        // Ten 10 ms chunks = 100 ms of input.
+        let chunk = s16(&[1000; 480]);
+        let total: usize = (0..10).map(|_| conv.process(&chunk).len() / 2).sum();
+        // 100 ms at 44.1 kHz = 4410 frames (one frame of latency allowed).
+        assert!((4408..=4410).contains(&total), "got {total} frames");
+    }
+}
@@ -0,0 +1,341 @@
+//! Muting local audio outputs while audio is redirected to an RDP client.
+//!
+//! Every hardware sink that is currently unmuted gets muted through its
+//! node `Props`; only sinks muted here are unmuted again afterwards, so a
+//! device the user had muted stays muted.
+//!
+//! The session manager persists mute state, so a crash while sinks are
+//! muted would leave the machine silent. The names of muted sinks are
+//! therefore written to a marker file, and [`LocalOutputMute::recover`]
+//! unmutes them on the next start.
+
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use pipewire as pw;
+use pw::node::{Node, NodeListener};
+use pw::spa::param::ParamType;
+use pw::spa::pod::{Object, Pod, Property, Value};
+use pw::spa::sys as spa_sys;
+use pw::types::ObjectType;
+
+use crate::audio_sink::roundtrip;
+use crate::audio_stream::AudioCaptureError;
+
+/// Nodes created by this server; never muted.
+const OWN_NODE_PREFIX: &str = "cosmic-ext-rdp-";
+
+/// Handle that keeps local outputs muted.
+///
+/// Dropping this unmutes the sinks it muted and removes the marker file.
+pub struct LocalOutputMute {
+    running: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for LocalOutputMute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalOutputMute")
+            .field("running", &self.running.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalOutputMute {
+    /// Mute all local hardware sinks until this handle is stopped.
+    ///
+    /// Sinks that appear later (e.g. a headset plugged in mid-session) are
+    /// muted too. If `marker` is set, the names of muted sinks are
+    /// persisted there for [`recover`](Self::recover).
+    ///
+    /// # Errors
+    ///
+    /// Returns `AudioCaptureError` if the thread cannot be spawned.
+    pub fn start(marker: Option<PathBuf>) -> Result<Self, AudioCaptureError> {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = Arc::clone(&running);
+
+        let thread = std::thread::Builder::new()
+            .name("pw-mute".into())
+            .spawn(move || {
+                if let Err(e) = run_mute_loop(marker.as_deref(), &running_clone) {
+                    tracing::error!("PipeWire output mute thread exited with error: {e}");
+                }
+            })
+            .map_err(AudioCaptureError::SpawnThread)?;
+
+        Ok(Self {
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// Unmute the sinks that were muted and join the thread.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Unmute sinks left muted by a previous run that did not shut down
+    /// cleanly, then remove the marker.
+    ///
+    /// Does nothing if `marker` does not exist. Blocks for at most a few
+    /// seconds.
+    pub fn recover(marker: &Path) {
+        let Ok(contents) = std::fs::read_to_string(marker) else {
+            return;
+        };
+        let names: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if !names.is_empty() {
+            tracing::warn!(
+                sinks = ?names,
+                "Previous session left local outputs muted, restoring"
+            );
+            let result = std::thread::Builder::new()
+                .name("pw-unmute".into())
+                .spawn(move || run_recover(&names))
+                .map(|handle| handle.join());
+            match result {
+                Ok(Ok(Ok(()))) => {}
+                Ok(Ok(Err(e))) => tracing::warn!("Failed to restore muted outputs: {e}"),
+                Ok(Err(_)) => tracing::warn!("Output restore thread panicked"),
+                Err(e) => tracing::warn!("Failed to spawn output restore thread: {e}"),
+            }
+        }
+
+        if let Err(e) = std::fs::remove_file(marker) {
+            tracing::warn!(path = %marker.display(), "Failed to remove mute marker: {e}");
+        }
+    }
+}
+
+impl Drop for LocalOutputMute {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A bound sink node and its observed mute state.
+struct Sink {
+    name: String,
+    node: Node,
+    _listener: NodeListener,
+    /// Last mute value reported in `Props`, once known.
+    muted: Rc<Cell<Option<bool>>>,
+    /// Whether we muted it (and must unmute it).
+    muted_by_us: bool,
+}
+
+type SharedSinks = Rc<RefCell<Vec<Sink>>>;
+
+/// Run the `PipeWire` main loop that mutes sinks.
+fn run_mute_loop(marker: Option<&Path>, running: &AtomicBool) -> Result<(), AudioCaptureError> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|_| AudioCaptureError::MainLoop)?;
+    let context = pw::context::Context::new(&mainloop).map_err(|_| AudioCaptureError::Context)?;
+    let core = context
+        .connect(None)
+        .map_err(|_| AudioCaptureError::Connect)?;
+
+    let sinks: SharedSinks = Rc::new(RefCell::new(Vec::new()));
+    let registry = Rc::new(
+        core.get_registry()
+            .map_err(|_| AudioCaptureError::Connect)?,
+    );
+    let _registry_listener =
+        watch_sinks(&registry, &sinks, |name| !name.starts_with(OWN_NODE_PREFIX));
+
+    roundtrip(&mainloop, &core);
+
+    while running.load(Ordering::SeqCst) {
+        mute_pending(&sinks, marker);
+        mainloop.loop_().iterate(Duration::from_millis(50));
+    }
+
+    let restored: Vec<String> = sinks
+        .borrow()
+        .iter()
+        .filter(|s| s.muted_by_us)
+        .map(|s| {
+            set_mute(&s.node, false);
+            s.name.clone()
+        })
+        .collect();
+    roundtrip(&mainloop, &core);
+
+    if !restored.is_empty() {
+        tracing::info!(sinks = ?restored, "Local outputs unmuted");
+    }
+    if let Some(marker) = marker {
+        let _ = std::fs::remove_file(marker);
+    }
+
+    tracing::info!("PipeWire output mute main loop exiting");
+    Ok(())
+}
+
+/// Unmute the named sinks (crash recovery).
+fn run_recover(names: &[String]) -> Result<(), AudioCaptureError> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|_| AudioCaptureError::MainLoop)?;
+    let context = pw::context::Context::new(&mainloop).map_err(|_| AudioCaptureError::Context)?;
+    let core = context
+        .connect(None)
+        .map_err(|_| AudioCaptureError::Connect)?;
+
+    let sinks: SharedSinks = Rc::new(RefCell::new(Vec::new()));
+    let registry = Rc::new(
+        core.get_registry()
+            .map_err(|_| AudioCaptureError::Connect)?,
+    );
+    let wanted = names.to_vec();
+    let _registry_listener = watch_sinks(&registry, &sinks, move |name| {
+        wanted.iter().any(|w| w == name)
+    });
+
+    roundtrip(&mainloop, &core);
+    for sink in sinks.borrow().iter() {
+        set_mute(&sink.node, false);
+        tracing::info!(sink = %sink.name, "Unmuted local output");
+    }
+    roundtrip(&mainloop, &core);
+    Ok(())
+}
+
+/// Bind every `Audio/Sink` node accepted by `filter` and track its mute
+/// state.
+fn watch_sinks(
+    registry: &Rc<pw::registry::Registry>,
+    sinks: &SharedSinks,
+    filter: impl Fn(&str) -> bool + 'static,
+) -> pw::registry::Listener {
+    let registry_weak = Rc::downgrade(registry);
+    let sinks = Rc::clone(sinks);
+    registry
+        .add_listener_local()
+        .global(move |global| {
+            if global.type_ != ObjectType::Node {
+                return;
+            }
+            let Some(props) = global.props else {
+                return;
+            };
+            if props.get("media.class") != Some("Audio/Sink") {
+                return;
+            }
+            let Some(name) = props.get("node.name").filter(|n| filter(n)) else {
+                return;
+            };
+            let Some(registry) = registry_weak.upgrade() else {
+                return;
+            };
+            let node: Node = match registry.bind(global) {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::warn!(sink = name, "Failed to bind sink node: {e}");
+                    return;
+                }
+            };
+
+            let muted = Rc::new(Cell::new(None));
+            let muted_cb = Rc::clone(&muted);
+            let listener = node
+                .add_listener_local()
+                .param(move |_seq, id, _index, _next, pod| {
+                    if id != ParamType::Props {
+                        return;
+                    }
+                    if let Some(mute) = pod.and_then(parse_mute) {
+                        muted_cb.set(Some(mute));
+                    }
+                })
+                .register();
+            node.subscribe_params(&[ParamType::Props]);
+
+            sinks.borrow_mut().push(Sink {
+                name: name.to_string(),
+                node,
+                _listener: listener,
+                muted,
+                muted_by_us: false,
+            });
+        })
+        .register()
+}
+
+/// Mute sinks whose state is known to be unmuted and update the marker.
+fn mute_pending(sinks: &SharedSinks, marker: Option<&Path>) {
+    let mut sinks = sinks.borrow_mut();
+    let mut changed = false;
+    for sink in sinks.iter_mut() {
+        if sink.muted_by_us || sink.muted.get() != Some(false) {
+            continue;
+        }
+        set_mute(&sink.node, true);
+        sink.muted_by_us = true;
+        changed = true;
+        tracing::info!(sink = %sink.name, "Muted local output");
+    }
+
+    if let (true, Some(marker)) = (changed, marker) {
+        let names: String = sinks
+            .iter()
+            .filter(|s| s.muted_by_us)
+            .map(|s| format!("{}\n", s.name))
+            .collect();
+        if let Some(dir) = marker.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = std::fs::write(marker, names) {
+            tracing::warn!(path = %marker.display(), "Failed to write mute marker: {e}");
+        }
+    }
+}
+
+/// Extract the `mute` property from a `Props` param.
+fn parse_mute(pod: &Pod) -> Option<bool> {
+    let (_, Value::Object(obj)) =
+        pw::spa::pod::deserialize::PodDeserializer::deserialize_any_from(pod.as_bytes()).ok()?
+    else {
+        return None;
+    };
+    obj.properties
+        .iter()
+        .find(|p| p.key == spa_sys::SPA_PROP_mute)
+        .and_then(|p| match p.value {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        })
+}
+
+/// Set the `mute` property on a node.
+fn set_mute(node: &Node, mute: bool) {
+    let value = Value::Object(Object {
+        type_: pw::spa::utils::SpaTypes::ObjectParamProps.as_raw(),
+        id: ParamType::Props.as_raw(),
+        properties: vec![Property::new(spa_sys::SPA_PROP_mute, Value::Bool(mute))],
+    });
+    let Ok((cursor, _)) =
+        pw::spa::pod::serialize::PodSerializer::serialize(std::io::Cursor::new(Vec::new()), &value)
+    else {
+        return;
+    };
+    let bytes = cursor.into_inner();
+    if let Some(pod) = Pod::from_bytes(&bytes) {
+        node.set_param(ParamType::Props, 0, pod);
+    }
+}
@@ -0,0 +1,334 @@
+//! Per-session `PipeWire` null sink.
+//!
+//! Creates a dedicated virtual sink for an RDP session so remote audio
+//! only contains what applications in that session play, instead of
+//! mirroring every local device. Optionally makes the sink the default
+//! output while it exists and restores the previous default afterwards.
+//!
+//! The default is switched through the `default` metadata object's
+//! `default.configured.audio.sink` key, the same one `wpctl set-default`
+//! uses, so the session manager moves existing streams over.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use pipewire as pw;
+use pw::metadata::{Metadata, MetadataListener};
+use pw::properties::Properties;
+use pw::types::ObjectType;
+
+use crate::audio_stream::AudioCaptureError;
+
+/// Metadata key holding the user-selected default sink.
+const CONFIGURED_SINK_KEY: &str = "default.configured.audio.sink";
+
+/// Metadata subject for global defaults.
+const DEFAULT_SUBJECT: u32 = 0;
+
+/// How long to wait for `PipeWire` to acknowledge requests.
+const ROUNDTRIP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Handle to a per-session null sink.
+///
+/// Dropping this restores the previous default sink (if it was changed)
+/// and removes the sink node.
+pub struct PwSessionSink {
+    node_name: String,
+    running: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for PwSessionSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PwSessionSink")
+            .field("node_name", &self.node_name)
+            .field("running", &self.running.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl PwSessionSink {
+    /// Create a null sink named `node_name` and, if `make_default` is set,
+    /// make it the default output.
+    ///
+    /// Blocks until `PipeWire` has created the node (bounded by a short
+    /// timeout) so a capture stream targeting it can link immediately.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AudioCaptureError` if the thread cannot be spawned or the
+    /// sink cannot be created.
+    pub fn start(
+        node_name: &str,
+        description: &str,
+        channels: u16,
+        sample_rate: u32,
+        make_default: bool,
+    ) -> Result<Self, AudioCaptureError> {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = Arc::clone(&running);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let name = node_name.to_string();
+        let description = description.to_string();
+
+        let thread = std::thread::Builder::new()
+            .name("pw-sink".into())
+            .spawn(move || {
+                let config = SinkConfig {
+                    node_name: &name,
+                    description: &description,
+                    channels,
+                    sample_rate,
+                    make_default,
+                };
+                if let Err(e) = run_sink_loop(&config, &running_clone, &ready_tx) {
+                    tracing::error!("PipeWire session sink thread exited with error: {e}");
+                    let _ = ready_tx.send(Err(e));
+                }
+            })
+            .map_err(AudioCaptureError::SpawnThread)?;
+
+        let mut sink = Self {
+            node_name: node_name.to_string(),
+            running,
+            thread: Some(thread),
+        };
+
+        match ready_rx.recv_timeout(ROUNDTRIP_TIMEOUT * 3) {
+            Ok(Ok(())) => Ok(sink),
+            Ok(Err(e)) => {
+                sink.stop();
+                Err(e)
+            }
+            Err(_) => {
+                tracing::warn!(
+                    node_name,
+                    "Timed out waiting for PipeWire session sink, continuing"
+                );
+                Ok(sink)
+            }
+        }
+    }
+
+    /// `node.name` of the sink, for targeting a capture stream at it.
+    #[must_use]
+    pub fn node_name(&self) -> &str {
+        &self.node_name
+    }
+
+    /// Restore the previous default, remove the sink and join the thread.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for PwSessionSink {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Parameters for the sink thread.
+struct SinkConfig<'a> {
+    node_name: &'a str,
+    description: &'a str,
+    channels: u16,
+    sample_rate: u32,
+    make_default: bool,
+}
+
+/// Bound `default` metadata object plus the listener recording its
+/// previous configured sink.
+struct DefaultMetadata {
+    metadata: Metadata,
+    _listener: MetadataListener,
+}
+
+/// Run the `PipeWire` main loop owning the sink node.
+fn run_sink_loop(
+    config: &SinkConfig<'_>,
+    running: &AtomicBool,
+    ready_tx: &mpsc::Sender<Result<(), AudioCaptureError>>,
+) -> Result<(), AudioCaptureError> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|_| AudioCaptureError::MainLoop)?;
+    let context = pw::context::Context::new(&mainloop).map_err(|_| AudioCaptureError::Context)?;
+    let core = context
+        .connect(None)
+        .map_err(|_| AudioCaptureError::Connect)?;
+
+    let props = sink_properties(config);
+    let _node: pw::node::Node = core
+        .create_object("adapter", &props)
+        .map_err(|_| AudioCaptureError::CreateSink)?;
+
+    // Bind the `default` metadata object and remember the configured
+    // sink it held before we touch it.
+    let registry = Rc::new(
+        core.get_registry()
+            .map_err(|_| AudioCaptureError::Connect)?,
+    );
+    let default_meta: Rc<RefCell<Option<DefaultMetadata>>> = Rc::new(RefCell::new(None));
+    let previous: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    let applied = Rc::new(Cell::new(false));
+
+    let _registry_listener = config.make_default.then(|| {
+        let registry_weak = Rc::downgrade(&registry);
+        let default_meta = Rc::clone(&default_meta);
+        let previous = Rc::clone(&previous);
+        let applied = Rc::clone(&applied);
+        registry
+            .add_listener_local()
+            .global(move |global| {
+                if global.type_ != ObjectType::Metadata || default_meta.borrow().is_some() {
+                    return;
+                }
+                let is_default = global
+                    .props
+                    .and_then(|p| p.get("metadata.name"))
+                    .is_some_and(|name| name == "default");
+                if !is_default {
+                    return;
+                }
+                let Some(registry) = registry_weak.upgrade() else {
+                    return;
+                };
+                let metadata: Metadata = match registry.bind(global) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        tracing::warn!("Failed to bind PipeWire default metadata: {e}");
+                        return;
+                    }
+                };
+                let previous = Rc::clone(&previous);
+                let applied = Rc::clone(&applied);
+                let listener = metadata
+                    .add_listener_local()
+                    .property(move |subject, key, _type, value| {
+                        if subject == DEFAULT_SUBJECT
+                            && key == Some(CONFIGURED_SINK_KEY)
+                            && !applied.get()
+                        {
+                            *previous.borrow_mut() = value.map(str::to_string);
+                        }
+                        0
+                    })
+                    .register();
+                *default_meta.borrow_mut() = Some(DefaultMetadata {
+                    metadata,
+                    _listener: listener,
+                });
+            })
+            .register()
+    });
+
+    // First roundtrip: node created, globals enumerated, metadata bound.
+    // Second: the metadata's current properties have been delivered.
+    roundtrip(&mainloop, &core);
+    roundtrip(&mainloop, &core);
+
+    tracing::info!(
+        node_name = config.node_name,
+        channels = config.channels,
+        sample_rate = config.sample_rate,
+        "PipeWire session sink created"
+    );
+
+    if config.make_default {
+        if let Some(meta) = default_meta.borrow().as_ref() {
+            let value = format!("{{ \"name\": \"{}\" }}", config.node_name);
+            applied.set(true);
+            meta.metadata.set_property(
+                DEFAULT_SUBJECT,
+                CONFIGURED_SINK_KEY,
+                Some("Spa:String:JSON"),
+                Some(&value),
+            );
+            tracing::info!(
+                previous = previous.borrow().as_deref().unwrap_or("<none>"),
+                "Session sink set as default output"
+            );
+        } else {
+            tracing::warn!("PipeWire default metadata not found, default sink unchanged");
+        }
+        roundtrip(&mainloop, &core);
+    }
+
+    let _ = ready_tx.send(Ok(()));
+
+    while running.load(Ordering::SeqCst) {
+        mainloop.loop_().iterate(Duration::from_millis(50));
+    }
+
+    if applied.get() {
+        if let Some(meta) = default_meta.borrow().as_ref() {
+            let previous = previous.borrow();
+            let restore = previous.as_deref();
+            meta.metadata.set_property(
+                DEFAULT_SUBJECT,
+                CONFIGURED_SINK_KEY,
+                restore.map(|_| "Spa:String:JSON"),
+                restore,
+            );
+            tracing::info!(
+                previous = restore.unwrap_or("<none>"),
+                "Restored previous default output"
+            );
+        }
+        roundtrip(&mainloop, &core);
+    }
+
+    tracing::info!("PipeWire session sink main loop exiting");
+    Ok(())
+}
+
+/// Properties for a `support.null-audio-sink` adapter node.
+fn sink_properties(config: &SinkConfig<'_>) -> Properties {
+    let mut props = Properties::new();
+    props.insert(*pw::keys::FACTORY_NAME, "support.null-audio-sink");
+    props.insert(*pw::keys::NODE_NAME, config.node_name);
+    props.insert(*pw::keys::NODE_DESCRIPTION, config.description);
+    props.insert(*pw::keys::MEDIA_CLASS, "Audio/Sink");
+    props.insert(*pw::keys::AUDIO_CHANNELS, config.channels.to_string());
+    props.insert(*pw::keys::AUDIO_RATE, config.sample_rate.to_string());
+    match config.channels {
+        1 => props.insert("audio.position", "MONO"),
+        2 => props.insert("audio.position", "FL,FR"),
+        _ => {}
+    }
+    // Remove the node when this client disconnects, even on a crash.
+    props.insert(*pw::keys::OBJECT_LINGER, "false");
+    props
+}
+
+/// Wait until `PipeWire` has processed all previously issued requests.
+pub(crate) fn roundtrip(mainloop: &pw::main_loop::MainLoop, core: &pw::core::Core) {
+    let Ok(pending) = core.sync(0) else {
+        return;
+    };
+    let done = Rc::new(Cell::new(false));
+    let done_cb = Rc::clone(&done);
+    let _listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id == pw::core::PW_ID_CORE && seq == pending {
+                done_cb.set(true);
+            }
+        })
+        .register();
+
+    let deadline = Instant::now() + ROUNDTRIP_TIMEOUT;
+    while !done.get() && Instant::now() < deadline {
+        mainloop.loop_().iterate(Duration::from_millis(10));
+    }
+    if !done.get() {
+        tracing::warn!("PipeWire roundtrip timed out");
+    }
+}
@@ -0,0 +1,237 @@
+//! `PipeWire` virtual audio source for microphone redirection.
+//!
+//! Exposes audio received from the RDP client (via the `AUDIO_INPUT`
+//! dynamic channel) as a source node inside the session, so applications
+//! can record from it like any other microphone. Runs on a dedicated OS
+//! thread with its own main loop; PCM is handed over through a small
+//! ring buffer.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use pipewire as pw;
+use pw::properties::properties;
+use pw::stream::{Stream, StreamFlags, StreamState};
+
+use crate::audio_stream::{s16_format_pod, AudioCaptureError};
+
+/// Maximum buffered audio before the oldest samples are dropped.
+///
+/// Keeps latency bounded when nothing is recording from the source.
+const MAX_BUFFERED_MS: usize = 200;
+
+/// Shared PCM ring buffer between the RDP side and the `PipeWire` thread.
+type SharedRing = Arc<Mutex<VecDeque<u8>>>;
+
+/// Handle to a running `PipeWire` virtual source.
+///
+/// Dropping this removes the source node and stops its thread.
+pub struct PwVirtualSource {
+    running: Arc<AtomicBool>,
+    ring: SharedRing,
+    max_buffered: usize,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for PwVirtualSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PwVirtualSource")
+            .field("running", &self.running.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl PwVirtualSource {
+    /// Create a virtual source node fed with interleaved S16LE PCM.
+    ///
+    /// `description` is the human-readable name shown in sound settings
+    /// (e.g. "RDP Microphone").
+    ///
+    /// # Errors
+    ///
+    /// Returns `AudioCaptureError` if the thread cannot be spawned.
+    pub fn start(
+        description: &str,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<Self, AudioCaptureError> {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = Arc::clone(&running);
+        let ring: SharedRing = Arc::new(Mutex::new(VecDeque::new()));
+        let ring_clone = Arc::clone(&ring);
+        let description = description.to_string();
+
+        let thread = std::thread::Builder::new()
+            .name("pw-mic".into())
+            .spawn(move || {
+                if let Err(e) = run_source_loop(
+                    &description,
+                    channels,
+                    sample_rate,
+                    &ring_clone,
+                    &running_clone,
+                ) {
+                    tracing::error!("PipeWire virtual source thread exited with error: {e}");
+                }
+            })
+            .map_err(AudioCaptureError::SpawnThread)?;
+
+        let frame_size = usize::from(channels) * 2;
+        let max_buffered = sample_rate as usize * MAX_BUFFERED_MS / 1000 * frame_size;
+
+        Ok(Self {
+            running,
+            ring,
+            max_buffered,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue interleaved S16LE PCM for playback into the source.
+    pub fn push(&self, pcm: &[u8]) {
+        let Ok(mut ring) = self.ring.lock() else {
+            return;
+        };
+        ring.extend(pcm);
+        if ring.len() > self.max_buffered {
+            let excess = ring.len() - self.max_buffered;
+            ring.drain(..excess);
+            tracing::trace!(excess, "Virtual source buffer full, dropped oldest samples");
+        }
+    }
+
+    /// Remove the source node and join the thread.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for PwVirtualSource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Run the `PipeWire` main loop for the virtual source.
+fn run_source_loop(
+    description: &str,
+    channels: u16,
+    sample_rate: u32,
+    ring: &SharedRing,
+    running: &AtomicBool,
+) -> Result<(), AudioCaptureError> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None).map_err(|_| AudioCaptureError::MainLoop)?;
+    let context = pw::context::Context::new(&mainloop).map_err(|_| AudioCaptureError::Context)?;
+    let core = context
+        .connect(None)
+        .map_err(|_| AudioCaptureError::Connect)?;
+
+    let stream = Stream::new(
+        &core,
+        "cosmic-ext-rdp-microphone",
+        properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Playback",
+            *pw::keys::MEDIA_ROLE => "Communication",
+            *pw::keys::MEDIA_CLASS => "Audio/Source/Virtual",
+            *pw::keys::NODE_NAME => "cosmic-ext-rdp-microphone",
+            *pw::keys::NODE_DESCRIPTION => description,
+        },
+    )
+    .map_err(|_| AudioCaptureError::CreateStream)?;
+
+    let frame_size = usize::from(channels) * 2;
+    let ring_cb = Arc::clone(ring);
+
+    let _listener = stream
+        .add_local_listener_with_user_data(())
+        .state_changed(|_stream, (), old, new| {
+            tracing::debug!("PipeWire virtual source state: {old:?} -> {new:?}");
+            if new == StreamState::Error(String::new()) {
+                tracing::error!("PipeWire virtual source entered error state");
+            }
+        })
+        .process(move |stream_ref, ()| {
+            fill_buffer(stream_ref, &ring_cb, frame_size);
+        })
+        .register()
+        .map_err(|_| AudioCaptureError::RegisterListener)?;
+
+    let values = s16_format_pod(channels, sample_rate)?;
+    let mut params = [pw::spa::pod::Pod::from_bytes(&values).expect("valid pod")];
+
+    // No AUTOCONNECT: the source sits in the graph until an application
+    // (or the session manager, if it is made the default) links to it.
+    stream
+        .connect(
+            pw::spa::utils::Direction::Output,
+            None,
+            StreamFlags::MAP_BUFFERS,
+            &mut params,
+        )
+        .map_err(|_| AudioCaptureError::StreamConnect)?;
+
+    tracing::info!(
+        description,
+        channels,
+        sample_rate,
+        "PipeWire virtual source created"
+    );
+
+    while running.load(Ordering::SeqCst) {
+        mainloop
+            .loop_()
+            .iterate(std::time::Duration::from_millis(50));
+    }
+
+    tracing::info!("PipeWire virtual source main loop exiting");
+    Ok(())
+}
+
+/// Fill one output buffer from the ring, padding with silence on underrun.
+fn fill_buffer(stream: &pw::stream::StreamRef, ring: &SharedRing, frame_size: usize) {
+    let Some(mut buffer) = stream.dequeue_buffer() else {
+        return;
+    };
+
+    let requested = usize::try_from(buffer.requested()).unwrap_or(0);
+    let datas = buffer.datas_mut();
+    if datas.is_empty() {
+        return;
+    }
+
+    let data = &mut datas[0];
+    let Some(slice) = data.data() else {
+        return;
+    };
+
+    let mut frames = slice.len() / frame_size;
+    if requested > 0 {
+        frames = frames.min(requested);
+    }
+    let size = frames * frame_size;
+    let out = &mut slice[..size];
+
+    let filled = ring.lock().map_or(0, |mut ring| {
+        let n = ring.len().min(size) / frame_size * frame_size;
+        for (dst, src) in out.iter_mut().zip(ring.drain(..n)) {
+            *dst = src;
+        }
+        n
+    });
+    out[filled..].fill(0);
+
+    let chunk = data.chunk_mut();
+    *chunk.offset_mut() = 0;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    {
+        *chunk.stride_mut() = frame_size as i32;
+        *chunk.size_mut() = size as u32;
+    }
+}
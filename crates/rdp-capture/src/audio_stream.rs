@@ -1,24 +1,102 @@
 //! `PipeWire` audio capture for RDPSND forwarding.
 //!
-//! Captures desktop audio by connecting to the default audio sink's monitor
-//! port via `PipeWire`. Runs on a dedicated OS thread with its own main loop,
+//! Captures desktop audio by connecting to an audio sink's monitor port
+//! (the default sink unless an [`AudioSource`] says otherwise) via
+//! `PipeWire`. Runs on a dedicated OS thread with its own main loop,
 //! sending [`AudioChunk`] samples to a tokio mpsc channel.
+//!
+//! S16LE at the configured rate and channel count is requested explicitly.
+//! If the graph negotiates something else, the samples are converted with
+//! [`AudioConverter`] so consumers always receive the configured format.
 
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use pipewire as pw;
 use pw::properties::properties;
+use pw::spa::param::audio::{AudioFormat, AudioInfoRaw};
+use pw::spa::sys as spa_sys;
 use pw::stream::{Stream, StreamFlags, StreamState};
 use tokio::sync::mpsc;
 
+use crate::audio_convert::{AudioConverter, AudioFormatInfo, SampleFormat};
 use crate::frame::AudioChunk;
 
+/// Format negotiated with `PipeWire`, shared with the stream handle.
+type SharedFormat = Arc<Mutex<Option<AudioFormatInfo>>>;
+
+/// What the audio capture stream records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AudioSource {
+    /// Monitor of whatever the default sink currently is.
+    #[default]
+    DefaultSink,
+    /// Monitor of a specific sink, by `node.name`.
+    Sink(String),
+    /// Output of a specific node (e.g. an application stream), by
+    /// `node.name`.
+    Node(String),
+}
+
+impl AudioSource {
+    /// Parse a source specification.
+    ///
+    /// `""` or `"default"` selects the default sink, `"sink:<name>"` a
+    /// specific sink, `"node:<name>"` an application or other node. A bare
+    /// name is treated as a sink.
+    #[must_use]
+    pub fn parse(spec: &str) -> Self {
+        let spec = spec.trim();
+        if spec.is_empty() || spec == "default" {
+            Self::DefaultSink
+        } else if let Some(name) = spec.strip_prefix("sink:") {
+            Self::Sink(name.to_string())
+        } else if let Some(name) = spec.strip_prefix("node:") {
+            Self::Node(name.to_string())
+        } else {
+            Self::Sink(spec.to_string())
+        }
+    }
+
+    /// Stream properties that link the capture stream to this source.
+    fn stream_properties(&self) -> pw::properties::Properties {
+        let mut props = properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Capture",
+            *pw::keys::MEDIA_ROLE => "Music",
+        };
+        match self {
+            Self::DefaultSink => {
+                props.insert(*pw::keys::STREAM_CAPTURE_SINK, "true");
+            }
+            Self::Sink(name) => {
+                props.insert(*pw::keys::STREAM_CAPTURE_SINK, "true");
+                props.insert(*pw::keys::TARGET_OBJECT, name.as_str());
+            }
+            Self::Node(name) => {
+                props.insert(*pw::keys::TARGET_OBJECT, name.as_str());
+            }
+        }
+        props
+    }
+}
+
+impl std::fmt::Display for AudioSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DefaultSink => write!(f, "default"),
+            Self::Sink(name) => write!(f, "sink:{name}"),
+            Self::Node(name) => write!(f, "node:{name}"),
+        }
+    }
+}
+
 /// Handle to a running `PipeWire` audio capture stream.
 ///
 /// Dropping this stops the audio capture thread.
 pub struct PwAudioStream {
     running: Arc<AtomicBool>,
+    negotiated: SharedFormat,
     thread: Option<std::thread::JoinHandle<()>>,
 }
 
@@ -26,12 +104,17 @@ impl std::fmt::Debug for PwAudioStream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PwAudioStream")
             .field("running", &self.running.load(Ordering::Relaxed))
+            .field("negotiated", &self.negotiated_format())
             .finish_non_exhaustive()
     }
 }
 
 impl PwAudioStream {
-    /// Start capturing audio from the default sink monitor.
+    /// Start capturing audio from `source`.
+    ///
+    /// Chunks are always delivered as interleaved S16LE at `sample_rate`
+    /// with `channels` channels, converting if the graph cannot provide
+    /// that format directly.
     ///
     /// # Errors
     ///
@@ -39,18 +122,27 @@ impl PwAudioStream {
     pub fn start(
         channels: u16,
         sample_rate: u32,
+        source: &AudioSource,
         channel_capacity: usize,
     ) -> Result<(Self, mpsc::Receiver<AudioChunk>), AudioCaptureError> {
         let (tx, rx) = mpsc::channel(channel_capacity);
         let running = Arc::new(AtomicBool::new(true));
         let running_clone = Arc::clone(&running);
+        let negotiated: SharedFormat = Arc::new(Mutex::new(None));
+        let negotiated_clone = Arc::clone(&negotiated);
+        let source = source.clone();
 
         let thread = std::thread::Builder::new()
             .name("pw-audio".into())
             .spawn(move || {
-                if let Err(e) =
-                    run_audio_loop(channels, sample_rate, tx, running_clone)
-                {
+                if let Err(e) = run_audio_loop(
+                    channels,
+                    sample_rate,
+                    &source,
+                    tx,
+                    running_clone,
+                    negotiated_clone,
+                ) {
                     tracing::error!("PipeWire audio thread exited with error: {e}");
                 }
             })
@@ -59,12 +151,22 @@ impl PwAudioStream {
         Ok((
             Self {
                 running,
+                negotiated,
                 thread: Some(thread),
             },
             rx,
         ))
     }
 
+    /// The format actually negotiated with `PipeWire`, once known.
+    ///
+    /// This is the format before conversion; chunks are always delivered
+    /// in the format requested in [`start`](Self::start).
+    #[must_use]
+    pub fn negotiated_format(&self) -> Option<AudioFormatInfo> {
+        self.negotiated.lock().ok().and_then(|guard| *guard)
+    }
+
     /// Stop the audio capture and join the thread.
     pub fn stop(&mut self) {
         self.running.store(false, Ordering::SeqCst);
@@ -85,8 +187,10 @@ impl Drop for PwAudioStream {
 fn run_audio_loop(
     channels: u16,
     sample_rate: u32,
+    source: &AudioSource,
     audio_tx: mpsc::Sender<AudioChunk>,
     running: Arc<AtomicBool>,
+    negotiated: SharedFormat,
 ) -> Result<(), AudioCaptureError> {
     pw::init();
 
@@ -97,21 +201,14 @@ fn run_audio_loop(
         .connect(None)
         .map_err(|_| AudioCaptureError::Connect)?;
 
-    let stream = Stream::new(
-        &core,
-        "cosmic-ext-rdp-audio",
-        properties! {
-            *pw::keys::MEDIA_TYPE => "Audio",
-            *pw::keys::MEDIA_CATEGORY => "Capture",
-            *pw::keys::MEDIA_ROLE => "Music",
-            *pw::keys::STREAM_CAPTURE_SINK => "true",
-        },
-    )
-    .map_err(|_| AudioCaptureError::CreateStream)?;
+    let stream = Stream::new(&core, "cosmic-ext-rdp-audio", source.stream_properties())
+        .map_err(|_| AudioCaptureError::CreateStream)?;
 
     let seq = Arc::new(AtomicU64::new(0));
     let ch = channels;
     let rate = sample_rate;
+    let negotiated_cb = Arc::clone(&negotiated);
+    let mut converter: Option<AudioConverter> = None;
 
     let _listener = stream
         .add_local_listener_with_user_data(audio_tx)
@@ -121,27 +218,64 @@ fn run_audio_loop(
                 tracing::error!("PipeWire audio stream entered error state");
             }
         })
+        .param_changed(move |stream, _tx, id, pod| {
+            if id != pw::spa::param::ParamType::Format.as_raw() {
+                return;
+            }
+            let Some(pod) = pod else {
+                return;
+            };
+            let mut info = AudioInfoRaw::new();
+            if info.parse(pod).is_err() {
+                tracing::warn!("Failed to parse negotiated PipeWire audio format");
+                return;
+            }
+            let format = negotiated_audio_format(&info);
+            match format {
+                Some(f) => tracing::info!(
+                    format = ?f.format,
+                    rate = f.rate,
+                    channels = f.channels,
+                    requested_rate = rate,
+                    requested_channels = ch,
+                    "PipeWire negotiated audio format"
+                ),
+                None => tracing::warn!(
+                    format = ?info.format(),
+                    "PipeWire negotiated an unsupported audio format, dropping audio"
+                ),
+            }
+            if let Ok(mut guard) = negotiated_cb.lock() {
+                *guard = format;
+            }
+
+            // Ask for buffer headers, which carry the presentation time.
+            let header_pod = crate::pipewire_stream::header_meta_pod();
+            let mut params = [pw::spa::pod::Pod::from_bytes(&header_pod).expect("valid meta pod")];
+            if let Err(e) = stream.update_params(&mut params) {
+                tracing::warn!("Failed to request audio buffer metadata: {e}");
+            }
+        })
         .process(move |stream_ref, tx| {
-            process_audio(stream_ref, tx, &seq, ch, rate);
+            let Some(input) = negotiated.lock().ok().and_then(|guard| *guard) else {
+                // No usable format yet; drain the buffer.
+                let _ = stream_ref.dequeue_buffer();
+                return;
+            };
+            if !matches!(&converter, Some(c) if c.input() == input) {
+                converter = Some(AudioConverter::new(input, rate, ch));
+            }
+            if let Some(conv) = converter.as_mut() {
+                process_audio(stream_ref, tx, &seq, conv, ch, rate);
+            }
         })
         .register()
         .map_err(|_| AudioCaptureError::RegisterListener)?;
 
-    // Build SPA audio info params for negotiation.
-    // S16LE format, specified channels and sample rate.
-    let audio_info = pw::spa::param::audio::AudioInfoRaw::new();
-    let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
-        std::io::Cursor::new(Vec::new()),
-        &pw::spa::pod::Value::Object(pw::spa::pod::Object {
-            type_: pw::spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
-            id: pw::spa::param::ParamType::EnumFormat.as_raw(),
-            properties: audio_info.into(),
-        }),
-    )
-    .map_err(|_| AudioCaptureError::CreateStream)?
-    .0
-    .into_inner();
-
+    // Request S16LE at the configured rate and channel count. Without
+    // these properties PipeWire hands us the sink's native format
+    // (usually F32LE at 48 kHz), which the client would misinterpret.
+    let values = s16_format_pod(channels, sample_rate)?;
     let mut params = [pw::spa::pod::Pod::from_bytes(&values).expect("valid pod")];
 
     stream
@@ -153,7 +287,12 @@ fn run_audio_loop(
         )
         .map_err(|_| AudioCaptureError::StreamConnect)?;
 
-    tracing::info!(channels, sample_rate, "PipeWire audio stream connected");
+    tracing::info!(
+        channels,
+        sample_rate,
+        %source,
+        "PipeWire audio stream connected"
+    );
 
     while running.load(Ordering::SeqCst) {
         mainloop.loop_().iterate(std::time::Duration::from_millis(50));
@@ -163,36 +302,89 @@ fn run_audio_loop(
     Ok(())
 }
 
+/// Serialize an `EnumFormat` pod requesting interleaved S16LE at the given
+/// rate and channel count.
+pub(crate) fn s16_format_pod(channels: u16, sample_rate: u32) -> Result<Vec<u8>, AudioCaptureError> {
+    let audio_info = requested_audio_info(channels, sample_rate);
+    let values = pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(pw::spa::pod::Object {
+            type_: pw::spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+            id: pw::spa::param::ParamType::EnumFormat.as_raw(),
+            properties: audio_info.into(),
+        }),
+    )
+    .map_err(|_| AudioCaptureError::CreateStream)?
+    .0
+    .into_inner();
+    Ok(values)
+}
+
+/// Build the `AudioInfoRaw` requested from `PipeWire`.
+fn requested_audio_info(channels: u16, sample_rate: u32) -> AudioInfoRaw {
+    let mut info = AudioInfoRaw::new();
+    info.set_format(AudioFormat::S16LE);
+    info.set_rate(sample_rate);
+    info.set_channels(u32::from(channels));
+
+    // Explicit positions let the adapter remix correctly; other layouts
+    // stay unpositioned.
+    let mut position = [0u32; 64];
+    match channels {
+        1 => position[0] = spa_sys::SPA_AUDIO_CHANNEL_MONO,
+        2 => {
+            position[0] = spa_sys::SPA_AUDIO_CHANNEL_FL;
+            position[1] = spa_sys::SPA_AUDIO_CHANNEL_FR;
+        }
+        _ => {}
+    }
+    info.set_position(position);
+    info
+}
+
+/// Map a negotiated `AudioInfoRaw` to a format the converter handles.
+fn negotiated_audio_format(info: &AudioInfoRaw) -> Option<AudioFormatInfo> {
+    let format = match info.format() {
+        AudioFormat::S16LE => SampleFormat::S16Le,
+        AudioFormat::S32LE => SampleFormat::S32Le,
+        AudioFormat::F32LE => SampleFormat::F32Le,
+        _ => return None,
+    };
+    let channels = u16::try_from(info.channels()).ok().filter(|&c| c > 0)?;
+    let rate = Some(info.rate()).filter(|&r| r > 0)?;
+    Some(AudioFormatInfo {
+        format,
+        rate,
+        channels,
+    })
+}
+
 /// Process a single audio buffer from the `PipeWire` stream.
+///
+/// Uses the raw buffer API so the `SPA_META_Header` timestamp can be read.
 fn process_audio(
     stream: &pw::stream::StreamRef,
     tx: &mut mpsc::Sender<AudioChunk>,
     seq: &AtomicU64,
+    converter: &mut AudioConverter,
     channels: u16,
     sample_rate: u32,
 ) {
-    let Some(mut buffer) = stream.dequeue_buffer() else {
-        return;
-    };
-
-    let datas = buffer.datas_mut();
-    if datas.is_empty() {
+    // Safety: stream is valid within the process callback.
+    let raw_pw_buf = unsafe { stream.dequeue_raw_buffer() };
+    if raw_pw_buf.is_null() {
         return;
     }
 
-    let data = &mut datas[0];
-    let chunk = data.chunk();
-    let size = chunk.size() as usize;
+    // Safety: raw_pw_buf is valid while dequeued; it is queued back below
+    // once the samples have been converted.
+    let spa_buf = unsafe { (*raw_pw_buf).buffer };
+    let chunk = unsafe { read_audio_buffer(spa_buf, converter) };
+    unsafe { stream.queue_raw_buffer(raw_pw_buf) };
 
-    let Some(slice) = data.data() else {
+    let Some((audio_data, pts_ns)) = chunk else {
         return;
     };
-
-    if size == 0 || size > slice.len() {
-        return;
-    }
-
-    let audio_data = slice[..size].to_vec();
     let sequence = seq.fetch_add(1, Ordering::Relaxed);
 
     let chunk = AudioChunk {
@@ -201,6 +393,7 @@ fn process_audio(
         sample_rate,
         bits_per_sample: 16,
         sequence,
+        pts_ns,
     };
 
     if tx.try_send(chunk).is_err() {
@@ -208,6 +401,49 @@ fn process_audio(
     }
 }
 
+/// Convert the samples in `spa_buf` and work out their presentation time.
+///
+/// Without header metadata the samples are assumed to have just finished
+/// recording, so the timestamp is backdated by the buffer's duration.
+///
+/// # Safety
+///
+/// `spa_buf` must be null or point to a dequeued buffer.
+unsafe fn read_audio_buffer(
+    spa_buf: *const spa_sys::spa_buffer,
+    converter: &mut AudioConverter,
+) -> Option<(Vec<u8>, u64)> {
+    if spa_buf.is_null() || (*spa_buf).n_datas == 0 || (*spa_buf).datas.is_null() {
+        return None;
+    }
+
+    // Safety: n_datas > 0 and datas is valid; Data is #[repr(transparent)].
+    let data: &mut pw::spa::buffer::Data =
+        &mut *(*spa_buf).datas.cast::<pw::spa::buffer::Data>();
+    let size = data.chunk().size() as usize;
+    let slice = data.data()?;
+    if size == 0 || size > slice.len() {
+        return None;
+    }
+
+    let audio_data = converter.process(&slice[..size]);
+    if audio_data.is_empty() {
+        return None;
+    }
+
+    let pts_ns = match crate::spa_meta::extract_pts(spa_buf) {
+        Some(pts) => crate::clock::presentation_time(Some(pts)),
+        None => {
+            let input = converter.input();
+            let frame_size = input.format.bytes_per_sample() * usize::from(input.channels);
+            let frames = (size / frame_size) as u64;
+            let duration_ns = frames * 1_000_000_000 / u64::from(input.rate);
+            crate::clock::monotonic_ns().saturating_sub(duration_ns)
+        }
+    };
+    Some((audio_data, pts_ns))
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AudioCaptureError {
     #[error("failed to create PipeWire MainLoop")]
@@ -228,6 +464,32 @@ pub enum AudioCaptureError {
     #[error("failed to connect audio stream")]
     StreamConnect,
 
+    #[error("failed to create PipeWire null sink")]
+    CreateSink,
+
     #[error("failed to spawn PipeWire audio thread")]
     SpawnThread(#[source] std::io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_audio_source() {
+        assert_eq!(AudioSource::parse(""), AudioSource::DefaultSink);
+        assert_eq!(AudioSource::parse("default"), AudioSource::DefaultSink);
+        assert_eq!(
+            AudioSource::parse("sink:alsa_output.usb"),
+            AudioSource::Sink("alsa_output.usb".to_string())
+        );
+        assert_eq!(
+            AudioSource::parse("node:Firefox"),
+            AudioSource::Node("Firefox".to_string())
+        );
+        assert_eq!(
+            AudioSource::parse("alsa_output.pci"),
+            AudioSource::Sink("alsa_output.pci".to_string())
+        );
+    }
+}
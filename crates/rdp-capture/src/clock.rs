@@ -0,0 +1,122 @@
+//! Shared media clock for audio/video synchronisation.
+//!
+//! `PipeWire` stamps buffers with presentation timestamps on the
+//! `CLOCK_MONOTONIC` timeline (the `SPA_META_Header` `pts`). Audio and
+//! video are captured by independent streams, so both are mapped onto one
+//! [`MediaClock`] to produce the millisecond timestamps RDPSND and EGFX
+//! carry. Clients schedule playback from these, keeping lip-sync.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Current `CLOCK_MONOTONIC` time in nanoseconds.
+///
+/// Used as the presentation timestamp for buffers without header metadata.
+#[must_use]
+pub fn monotonic_ns() -> u64 {
+    let ts = rustix::time::clock_gettime(rustix::time::ClockId::Monotonic);
+    let secs = u64::try_from(ts.tv_sec).unwrap_or(0);
+    let nanos = u64::try_from(ts.tv_nsec).unwrap_or(0);
+    secs * 1_000_000_000 + nanos
+}
+
+/// Largest distance from the current time a header timestamp may have.
+///
+/// Producers that stamp buffers on a different timeline (e.g. starting
+/// at zero) would otherwise push their stream far from the other one.
+const MAX_PTS_SKEW: Duration = Duration::from_secs(1);
+
+/// Presentation time for a buffer captured now.
+///
+/// Uses the header timestamp `pts_ns` when present and plausibly on the
+/// `CLOCK_MONOTONIC` timeline, otherwise the current time.
+#[must_use]
+pub fn presentation_time(pts_ns: Option<u64>) -> u64 {
+    let now = monotonic_ns();
+    pts_ns
+        .filter(|&pts| Duration::from_nanos(now.abs_diff(pts)) <= MAX_PTS_SKEW)
+        .unwrap_or(now)
+}
+
+/// Maps monotonic presentation timestamps to session-relative milliseconds.
+///
+/// Consumers use [`MediaClock::shared`] so audio and video timestamps
+/// count from the same epoch.
+#[derive(Debug, Clone, Copy)]
+pub struct MediaClock {
+    epoch_ns: u64,
+}
+
+impl MediaClock {
+    /// The process-wide clock, with its epoch at first use.
+    #[must_use]
+    pub fn shared() -> Self {
+        static EPOCH: OnceLock<u64> = OnceLock::new();
+        Self::with_epoch(*EPOCH.get_or_init(monotonic_ns))
+    }
+
+    /// Create a clock with an explicit epoch (in `CLOCK_MONOTONIC` ns).
+    #[must_use]
+    pub fn with_epoch(epoch_ns: u64) -> Self {
+        Self { epoch_ns }
+    }
+
+    /// Timestamp in milliseconds since the epoch, as sent on the wire.
+    ///
+    /// Timestamps before the epoch map to 0. The value wraps after
+    /// ~49 days like the 32-bit protocol fields it feeds.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)] // wrapping is intended
+    pub fn timestamp_ms(self, pts_ns: u64) -> u32 {
+        (pts_ns.saturating_sub(self.epoch_ns) / 1_000_000) as u32
+    }
+
+    /// How long ago `pts_ns` was, relative to the current monotonic time.
+    ///
+    /// Timestamps in the future report zero.
+    #[must_use]
+    pub fn age(pts_ns: u64) -> Duration {
+        Duration::from_nanos(monotonic_ns().saturating_sub(pts_ns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_relative_to_epoch() {
+        let clock = MediaClock::with_epoch(5_000_000_000);
+        assert_eq!(clock.timestamp_ms(5_000_000_000), 0);
+        assert_eq!(clock.timestamp_ms(5_033_400_000), 33);
+        // Before the epoch saturates instead of wrapping.
+        assert_eq!(clock.timestamp_ms(1_000), 0);
+    }
+
+    #[test]
+    fn shared_clock_has_one_epoch() {
+        let a = MediaClock::shared();
+        std::thread::sleep(Duration::from_millis(2));
+        let b = MediaClock::shared();
+        let now = monotonic_ns();
+        assert_eq!(a.timestamp_ms(now), b.timestamp_ms(now));
+    }
+
+    #[test]
+    fn presentation_time_rejects_foreign_timeline() {
+        let now = monotonic_ns();
+        let recent = now - 20_000_000;
+        assert_eq!(presentation_time(Some(recent)), recent);
+        // A stream-relative timestamp (near zero) is replaced by now.
+        assert!(presentation_time(Some(1_000)) >= now);
+        assert!(presentation_time(None) >= now);
+    }
+
+    #[test]
+    fn age_of_future_timestamp_is_zero() {
+        let future = monotonic_ns() + 10_000_000_000;
+        assert_eq!(MediaClock::age(future), Duration::ZERO);
+        let past = monotonic_ns().saturating_sub(1_000_000_000);
+        assert!(MediaClock::age(past) >= Duration::from_secs(1));
+    }
+}
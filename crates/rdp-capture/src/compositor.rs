@@ -6,6 +6,7 @@
 use tokio::sync::mpsc;
 
 use crate::frame::{CaptureEvent, CapturedFrame, CursorInfo, DamageRect, PixelFormat};
+use crate::pool::BufferPool;
 
 /// Information about a single captured monitor.
 #[derive(Debug, Clone)]
@@ -70,6 +71,7 @@ pub struct FrameCompositor {
     canvas_height: u16,
     output_tx: mpsc::Sender<CaptureEvent>,
     sequence: u64,
+    pool: BufferPool,
 }
 
 impl FrameCompositor {
@@ -83,6 +85,20 @@ impl FrameCompositor {
         output_capacity: usize,
     ) -> (Self, mpsc::Receiver<CaptureEvent>) {
         let (output_tx, output_rx) = mpsc::channel(output_capacity);
+        (
+            Self::with_output(monitor_infos, monitor_rxs, output_tx),
+            output_rx,
+        )
+    }
+
+    /// Create a compositor sending composed output events to `output_tx`.
+    #[must_use]
+    pub fn with_output(
+        monitor_infos: &[MonitorInfo],
+        monitor_rxs: Vec<mpsc::Receiver<CaptureEvent>>,
+        output_tx: mpsc::Sender<CaptureEvent>,
+    ) -> Self {
+        let output_capacity = output_tx.max_capacity();
         let (canvas_width, canvas_height) = bounding_box(monitor_infos);
 
         let monitors: Vec<MonitorInput> = monitor_infos
@@ -95,16 +111,14 @@ impl FrameCompositor {
             })
             .collect();
 
-        (
-            Self {
-                monitors,
-                canvas_width,
-                canvas_height,
-                output_tx,
-                sequence: 0,
-            },
-            output_rx,
-        )
+        Self {
+            monitors,
+            canvas_width,
+            canvas_height,
+            output_tx,
+            sequence: 0,
+            pool: BufferPool::new(output_capacity + 2),
+        }
     }
 
     /// Run the compositor loop, selecting across all monitor inputs.
@@ -148,6 +162,10 @@ impl FrameCompositor {
                                 );
                                 let _ = self.output_tx.try_send(CaptureEvent::Cursor(adjusted));
                             }
+                            CaptureEvent::Interrupted => {
+                                // One lost monitor ends the merged desktop.
+                                let _ = self.output_tx.send(CaptureEvent::Interrupted).await;
+                            }
                         }
                     }
                     Err(
@@ -182,7 +200,11 @@ impl FrameCompositor {
         let h = usize::from(self.canvas_height);
         let bpp = 4usize;
         let canvas_stride = w * bpp;
-        let mut canvas = vec![0u8; canvas_stride * h];
+        // Areas no monitor covers are opaque black.
+        let mut canvas = self.pool.take(canvas_stride * h);
+        for pixel in canvas.chunks_exact_mut(bpp) {
+            pixel.copy_from_slice(&[0, 0, 0, 0xFF]);
+        }
 
         let mut any_frame = false;
 
@@ -208,14 +230,23 @@ impl FrameCompositor {
 
         self.sequence += 1;
 
+        // The composite is as recent as its newest monitor frame.
+        let pts_ns = frames
+            .iter()
+            .flatten()
+            .map(|frame| frame.pts_ns)
+            .max()
+            .unwrap_or_else(crate::clock::monotonic_ns);
+
         #[allow(clippy::cast_possible_truncation)]
         Some(CapturedFrame {
-            data: canvas,
+            data: canvas.freeze(),
             width: u32::from(self.canvas_width),
             height: u32::from(self.canvas_height),
             format: PixelFormat::Bgra,
             stride: canvas_stride as u32,
             sequence: self.sequence,
+            pts_ns,
             damage: Some(vec![DamageRect::full_frame(
                 u32::from(self.canvas_width),
                 u32::from(self.canvas_height),
@@ -357,13 +388,14 @@ mod tests {
 
         // 2x2 red frame at offset (1, 1)
         let frame = CapturedFrame {
-            data: vec![0xFF; 2 * 2 * bpp],
+            data: vec![0xFF; 2 * 2 * bpp].into(),
             width: 2,
             height: 2,
             format: PixelFormat::Bgra,
             #[allow(clippy::cast_possible_truncation)]
             stride: (2 * bpp) as u32,
             sequence: 0,
+            pts_ns: 0,
             damage: None,
         };
 
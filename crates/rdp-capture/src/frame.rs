@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 /// A rectangular region of damage (changed pixels).
 #[derive(Debug, Clone, PartialEq)]
 pub struct DamageRect {
@@ -37,13 +39,18 @@ pub enum PixelFormat {
     Bgra,
     /// RGBA with 8 bits per channel.
     Rgba,
+    /// NV12: a Y plane of `stride * height` bytes followed by an
+    /// interleaved UV plane of `stride * height.div_ceil(2)` bytes.
+    Nv12,
 }
 
 impl PixelFormat {
+    /// Bytes per pixel; for NV12, of the Y plane.
     #[must_use] 
     pub fn bytes_per_pixel(self) -> usize {
         match self {
             Self::Bgra | Self::Rgba => 4,
+            Self::Nv12 => 1,
         }
     }
 }
@@ -103,6 +110,9 @@ pub struct AudioChunk {
     pub bits_per_sample: u16,
     /// Monotonically increasing sequence number.
     pub sequence: u64,
+    /// Presentation time of the first sample, in `CLOCK_MONOTONIC`
+    /// nanoseconds (see [`MediaClock`](crate::MediaClock)).
+    pub pts_ns: u64,
 }
 
 /// Events produced by the capture pipeline.
@@ -114,39 +124,38 @@ pub enum CaptureEvent {
     Cursor(CursorInfo),
     /// A new video frame and cursor update arrived together.
     FrameAndCursor(CapturedFrame, CursorInfo),
+    /// The capture stopped: a stream failed or the connection to
+    /// `PipeWire` dropped. Frames resume once a new session is up,
+    /// possibly at another size.
+    Interrupted,
 }
 
 /// A single captured video frame.
+///
+/// Cloning is cheap: the pixel data is reference-counted, usually in a
+/// buffer from a [`BufferPool`](crate::BufferPool).
 #[derive(Debug, Clone)]
 pub struct CapturedFrame {
-    /// Raw pixel data (BGRA or RGBA, top-to-bottom row order).
-    pub data: Vec<u8>,
+    /// Raw pixel data (BGRA or RGBA, top-to-bottom row order, alpha
+    /// opaque), `stride * height` bytes; see [`PixelFormat::Nv12`] for
+    /// NV12 frames.
+    pub data: Bytes,
     /// Frame width in pixels.
     pub width: u32,
     /// Frame height in pixels.
     pub height: u32,
     /// Pixel format.
     pub format: PixelFormat,
-    /// Row stride in bytes.
+    /// Row stride in bytes. Rows may be padded, so this can exceed
+    /// `width * 4`.
     pub stride: u32,
     /// Frame sequence number (monotonically increasing).
     pub sequence: u64,
+    /// Presentation time in `CLOCK_MONOTONIC` nanoseconds, from the
+    /// buffer's header metadata or the capture time if it has none.
+    pub pts_ns: u64,
     /// Damage regions, if available.
     /// `None` means no damage info (treat as full frame).
     /// Empty vec means no damage (frame identical to previous).
     pub damage: Option<Vec<DamageRect>>,
 }
-
-impl CapturedFrame {
-    /// Convert `BGRx` data to BGRA by setting alpha to 0xFF.
-    ///
-    /// `PipeWire` typically delivers `BGRx` format where the 'x' padding byte
-    /// is undefined. This ensures the alpha channel is fully opaque.
-    pub fn ensure_alpha_opaque(&mut self) {
-        if self.format == PixelFormat::Bgra {
-            for chunk in self.data.chunks_exact_mut(4) {
-                chunk[3] = 0xFF;
-            }
-        }
-    }
-}
@@ -3,25 +3,63 @@
 //! Provides screen capture via the XDG `ScreenCast` portal and `PipeWire`.
 //!
 //! Use [`start_capture`] for a high-level API that handles portal negotiation
-//! and `PipeWire` stream setup.
+//! and `PipeWire` stream setup. Consumers read frames through the
+//! [`FrameSource`] trait, which is also implemented by sources that need no
+//! compositor ([`SyntheticSource`], [`ReplaySource`]).
 
+pub mod audio_convert;
+pub mod audio_mute;
+pub mod audio_sink;
+pub mod audio_source;
 pub mod audio_stream;
+pub mod clock;
 pub mod compositor;
 pub mod frame;
 pub mod pipewire_stream;
+pub mod pool;
 pub mod portal;
+pub mod replay;
+pub mod source;
 pub mod spa_meta;
+pub mod synthetic;
 
-pub use audio_stream::{AudioCaptureError, PwAudioStream};
+pub use audio_convert::{AudioConverter, AudioFormatInfo, SampleFormat};
+pub use audio_mute::LocalOutputMute;
+pub use audio_sink::PwSessionSink;
+pub use audio_source::PwVirtualSource;
+pub use audio_stream::{AudioCaptureError, AudioSource, PwAudioStream};
+pub use clock::{monotonic_ns, MediaClock};
 pub use compositor::{bounding_box, FrameCompositor, MonitorInfo};
 pub use frame::{
     AudioChunk, CaptureEvent, CapturedFrame, CursorBitmap, CursorInfo, DamageRect, PixelFormat,
 };
 pub use pipewire_stream::{PwError, PwStream};
-pub use portal::{start_screencast, PortalError, PortalSession, PortalStream};
+pub use pool::{BufferPool, PooledBuffer};
+pub use portal::{start_screencast, CaptureSource, PortalError, PortalSession, PortalStream};
+pub use replay::{ReplayError, ReplaySource};
+pub use source::{FrameSource, PipeWireSource};
+pub use synthetic::SyntheticSource;
+
+use std::os::fd::OwnedFd;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 use ashpd::desktop::screencast::Screencast;
-use tokio::sync::mpsc;
+use futures_util::StreamExt;
+use tokio::sync::{mpsc, watch};
+
+/// How the monitors selected in the portal dialog are served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonitorMode {
+    /// One monitor, chosen in the portal dialog.
+    #[default]
+    Single,
+    /// Every selected monitor, merged into one virtual desktop.
+    Merged,
+    /// Every selected monitor is offered but only one is served at a time;
+    /// see [`CaptureHandle::switch_to`].
+    Switchable,
+}
 
 /// Information about the captured desktop.
 #[derive(Debug, Clone)]
@@ -30,27 +68,266 @@ pub struct DesktopInfo {
     pub width: u16,
     /// Desktop height in pixels.
     pub height: u16,
-    /// `PipeWire` node ID.
+    /// `PipeWire` node ID (of the first monitor when several are captured).
     pub node_id: u32,
+    /// Captured monitors and their place in the desktop. A single monitor
+    /// covers the whole desktop at the origin.
+    pub monitors: Vec<MonitorInfo>,
+    /// Every monitor selected in the portal, in the compositor's layout.
+    /// Same as `monitors`, except with [`MonitorMode::Switchable`] where
+    /// `monitors` only holds the served one.
+    pub sources: Vec<MonitorInfo>,
+    /// Compositor position of the desktop's top-left corner; `None` if
+    /// the portal did not report where every captured monitor is.
+    pub origin: Option<(i32, i32)>,
     /// Restore token for reconnecting to the same session.
     pub restore_token: Option<String>,
 }
 
 /// Handle that keeps the capture session alive.
 ///
-/// Dropping this stops the `PipeWire` stream and releases the portal session.
-/// Must be kept alive for the duration of the capture.
+/// Dropping this stops the `PipeWire` streams, the reconnection task and
+/// releases the portal session. Must be kept alive for the duration of
+/// the capture.
 pub struct CaptureHandle {
-    _session: ashpd::desktop::Session<'static, Screencast<'static>>,
+    /// Streams of the current portal session; `None` while reconnecting.
+    active: Arc<Mutex<Option<ActiveStreams>>>,
+    /// Settings for new sessions, updated when a virtual monitor resizes.
+    settings: Arc<Mutex<CaptureSettings>>,
+    restore_token: watch::Receiver<Option<String>>,
+    origin: watch::Receiver<Option<(i32, i32)>>,
+    ended: watch::Receiver<Option<CaptureEnd>>,
+    supervisor: tokio::task::JoinHandle<()>,
+}
+
+/// Why a capture stopped for good instead of reconnecting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum CaptureEnd {
+    #[error("screen sharing was stopped by the user")]
+    Revoked,
+
+    #[error("user cancelled the screen sharing dialog")]
+    Cancelled,
+
+    #[error("no restore token to reconnect without asking the user")]
+    NoRestoreToken,
+}
+
+/// The `PipeWire` side of a portal session.
+struct ActiveStreams {
+    pw_streams: Vec<PwStream>,
+    switcher: Option<Switcher>,
+}
+
+/// What is needed to restart the stream on another monitor.
+struct Switcher {
+    pipewire_fd: OwnedFd,
+    frame_tx: mpsc::Sender<CaptureEvent>,
+    swap_colors: bool,
+    fps: u32,
+    sources: Vec<MonitorInfo>,
+    current: usize,
+}
+
+/// A portal session, held open while its streams run.
+struct PortalHold {
+    session: ashpd::desktop::Session<'static, Screencast<'static>>,
     _proxy: Screencast<'static>,
-    _pw_stream: PwStream,
+}
+
+/// How sessions are started, kept for reconnecting.
+#[derive(Debug, Clone, Copy)]
+struct CaptureSettings {
+    channel_capacity: usize,
+    swap_colors: bool,
+    fps: u32,
+    mode: MonitorMode,
+    source: CaptureSource,
+}
+
+impl CaptureHandle {
+    /// Index into [`DesktopInfo::sources`] of the served monitor, if the
+    /// session was started with [`MonitorMode::Switchable`].
+    #[must_use]
+    pub fn current_source(&self) -> Option<usize> {
+        let active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+        active.as_ref()?.switcher.as_ref().map(|s| s.current)
+    }
+
+    /// Handle to resize the virtual monitor, if the session was started
+    /// with [`CaptureSource::Virtual`].
+    #[must_use]
+    pub fn virtual_output(&self) -> Option<VirtualOutput> {
+        let settings = *self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        matches!(settings.source, CaptureSource::Virtual { .. }).then(|| VirtualOutput {
+            active: Arc::clone(&self.active),
+            settings: Arc::clone(&self.settings),
+        })
+    }
+
+    /// The latest restore token; it changes when a reconnection starts a
+    /// new portal session.
+    #[must_use]
+    pub fn restore_token(&self) -> watch::Receiver<Option<String>> {
+        self.restore_token.clone()
+    }
+
+    /// The latest [`DesktopInfo::origin`]; it changes when a reconnection
+    /// starts a new portal session, which may place the monitors elsewhere.
+    #[must_use]
+    pub fn origin(&self) -> watch::Receiver<Option<(i32, i32)>> {
+        self.origin.clone()
+    }
+
+    /// Set once the capture has stopped for good; its events end then.
+    #[must_use]
+    pub fn ended(&self) -> watch::Receiver<Option<CaptureEnd>> {
+        self.ended.clone()
+    }
+
+    /// Serve another of the monitors selected in the portal.
+    ///
+    /// The current stream is stopped and a new one feeds the same frame
+    /// receiver, so consumers only see the frame size change. Returns the
+    /// newly served monitor, placed at the origin.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CaptureError` if the session is not switchable or is
+    /// reconnecting, `index` is out of range, or the new stream cannot be
+    /// started.
+    pub fn switch_to(&mut self, index: usize) -> Result<MonitorInfo, CaptureError> {
+        let mut active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+        let active = active.as_mut().ok_or(CaptureError::Reconnecting)?;
+        let switcher = active
+            .switcher
+            .as_mut()
+            .ok_or(CaptureError::NotSwitchable)?;
+        let source = switcher
+            .sources
+            .get(index)
+            .cloned()
+            .ok_or(CaptureError::NoSuchSource(index))?;
+        if index == switcher.current {
+            return Ok(served(source));
+        }
+
+        let fd = switcher
+            .pipewire_fd
+            .try_clone()
+            .map_err(CaptureError::DuplicateFd)?;
+        // Stop the old stream first so its frames don't interleave with
+        // the new monitor's.
+        active.pw_streams.clear();
+        let pw_stream = PwStream::start_with_sender(
+            fd,
+            source.node_id,
+            switcher.frame_tx.clone(),
+            switcher.swap_colors,
+            switcher.fps,
+            true,
+            None,
+        )
+        .map_err(CaptureError::PipeWire)?;
+        active.pw_streams.push(pw_stream);
+        switcher.current = index;
+
+        tracing::info!(
+            node_id = source.node_id,
+            width = source.width,
+            height = source.height,
+            "Switched captured monitor"
+        );
+        Ok(served(source))
+    }
+}
+
+/// Resizes a virtual monitor to follow the client.
+///
+/// Cloned from [`CaptureHandle::virtual_output`]; the size also applies to
+/// the virtual monitor created when the capture reconnects.
+#[derive(Clone)]
+pub struct VirtualOutput {
+    active: Arc<Mutex<Option<ActiveStreams>>>,
+    settings: Arc<Mutex<CaptureSettings>>,
+}
+
+impl VirtualOutput {
+    /// Current size of the virtual monitor.
+    #[must_use]
+    pub fn size(&self) -> (u16, u16) {
+        let settings = self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        match settings.source {
+            CaptureSource::Virtual { width, height } => (width, height),
+            CaptureSource::Monitor => (0, 0),
+        }
+    }
+
+    /// Ask the compositor to resize the virtual monitor. Frames at the new
+    /// size follow once the stream is renegotiated.
+    pub fn resize(&self, width: u16, height: u16) {
+        let mut settings = self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+        settings.source = CaptureSource::Virtual { width, height };
+        drop(settings);
+        let active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+        for stream in active.iter().flat_map(|active| &active.pw_streams) {
+            stream.request_size(u32::from(width), u32::from(height));
+        }
+    }
+}
+
+impl std::fmt::Debug for VirtualOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width, height) = self.size();
+        f.debug_struct("VirtualOutput")
+            .field("width", &width)
+            .field("height", &height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for CaptureHandle {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+    }
+}
+
+/// A monitor as the only one in the captured desktop.
+fn served(monitor: MonitorInfo) -> MonitorInfo {
+    MonitorInfo {
+        x: 0,
+        y: 0,
+        ..monitor
+    }
 }
 
 /// Start a screen capture session: portal negotiation + `PipeWire` stream.
 ///
 /// Shows the system permission dialog if no valid `restore_token` is provided.
 /// Returns a handle (must be kept alive), a receiver for captured frames,
-/// and information about the captured desktop.
+/// and information about the captured desktop; [`PipeWireSource`] wraps
+/// the latter two as a [`FrameSource`].
+///
+/// With [`MonitorMode::Merged`], the user may select several monitors;
+/// each gets its own `PipeWire` stream and a [`FrameCompositor`] merges
+/// them into one virtual desktop laid out as in the compositor. With
+/// [`MonitorMode::Switchable`], the first selected monitor is served until
+/// [`CaptureHandle::switch_to`] picks another. [`CaptureSource::Virtual`]
+/// creates a new monitor instead, resized through
+/// [`CaptureHandle::virtual_output`].
+///
+/// The compositor is asked for at most `fps` frames per second, and only
+/// sends frames when the screen changes. Frames are BGRA, or NV12 if the
+/// compositor prefers it and monitors are not merged.
+///
+/// If a stream fails or the compositor restarts,
+/// [`CaptureEvent::Interrupted`] is sent and a new session is started in
+/// the background with the latest restore token, retrying with backoff.
+/// Its frames arrive on the same receiver. If the user stops sharing
+/// (the portal closes the session), cancels the dialog of a new session
+/// or there is no restore token to start one without asking, the capture
+/// ends instead: the receiver closes and [`CaptureHandle::ended`] says
+/// why.
 ///
 /// # Errors
 ///
@@ -59,22 +336,98 @@ pub async fn start_capture(
     restore_token: Option<&str>,
     channel_capacity: usize,
     swap_colors: bool,
+    fps: u32,
+    mode: MonitorMode,
+    source: CaptureSource,
 ) -> Result<(CaptureHandle, mpsc::Receiver<CaptureEvent>, DesktopInfo), CaptureError> {
-    let portal_session = start_screencast(restore_token, true, false)
+    let settings = CaptureSettings {
+        channel_capacity,
+        swap_colors,
+        fps,
+        mode,
+        source,
+    };
+    let (session_tx, session_rx) = mpsc::channel(channel_capacity);
+    let (portal, active, info) = open_session(restore_token, settings, session_tx, 0).await?;
+
+    let (frame_tx, frame_rx) = mpsc::channel(channel_capacity);
+    let active = Arc::new(Mutex::new(Some(active)));
+    let settings = Arc::new(Mutex::new(settings));
+    let (token_tx, token_rx) = watch::channel(info.restore_token.clone());
+    let (origin_tx, origin_rx) = watch::channel(info.origin);
+    let (ended_tx, ended_rx) = watch::channel(None);
+    let supervisor = Supervisor {
+        settings: Arc::clone(&settings),
+        active: Arc::clone(&active),
+        frame_tx,
+        restore_token: token_tx,
+        origin: origin_tx,
+        ended: ended_tx,
+    };
+    let handle = CaptureHandle {
+        active,
+        settings,
+        restore_token: token_rx,
+        origin: origin_rx,
+        ended: ended_rx,
+        supervisor: tokio::spawn(supervisor.run(portal, session_rx)),
+    };
+
+    tracing::info!(
+        width = info.width,
+        height = info.height,
+        node_id = info.node_id,
+        monitors = info.monitors.len(),
+        sources = info.sources.len(),
+        "Screen capture session started"
+    );
+
+    Ok((handle, frame_rx, info))
+}
+
+/// Start a portal session and its `PipeWire` streams, feeding `frame_tx`.
+///
+/// With [`MonitorMode::Switchable`], source `current` is served if the
+/// session still has it.
+async fn open_session(
+    restore_token: Option<&str>,
+    settings: CaptureSettings,
+    frame_tx: mpsc::Sender<CaptureEvent>,
+    current: usize,
+) -> Result<(PortalHold, ActiveStreams, DesktopInfo), CaptureError> {
+    let CaptureSettings {
+        channel_capacity,
+        swap_colors,
+        fps,
+        mode,
+        source,
+    } = settings;
+    let portal_session = start_screencast(restore_token, true, mode != MonitorMode::Single, source)
         .await
         .map_err(CaptureError::Portal)?;
 
-    let stream = &portal_session.streams[0];
+    // A virtual monitor is created at the size its stream asks for.
+    let (size, default_size) = match source {
+        CaptureSource::Virtual { width, height } => {
+            (Some((u32::from(width), u32::from(height))), (width, height))
+        }
+        CaptureSource::Monitor => (None, (1920, 1080)),
+    };
+    let (sources, origin) = monitor_layout(&portal_session.streams, default_size);
+    let current = if current < sources.len() { current } else { 0 };
+    let monitors = if mode == MonitorMode::Switchable {
+        vec![served(sources[current].clone())]
+    } else {
+        sources.clone()
+    };
+    let (width, height) = bounding_box(&monitors);
     let info = DesktopInfo {
-        width: stream
-            .width
-            .and_then(|w| u16::try_from(w).ok())
-            .unwrap_or(1920),
-        height: stream
-            .height
-            .and_then(|h| u16::try_from(h).ok())
-            .unwrap_or(1080),
-        node_id: stream.node_id,
+        width,
+        height,
+        node_id: monitors[0].node_id,
+        monitors,
+        sources,
+        origin,
         restore_token: portal_session.restore_token.clone(),
     };
 
@@ -86,24 +439,285 @@ pub async fn start_capture(
         pipewire_fd,
     } = portal_session;
 
-    let (pw_stream, frame_rx) =
-        PwStream::start(pipewire_fd, info.node_id, channel_capacity, swap_colors)
+    let mut switcher = None;
+    let pw_streams = if mode == MonitorMode::Switchable {
+        let fd = pipewire_fd.try_clone().map_err(CaptureError::DuplicateFd)?;
+        let pw_stream = PwStream::start_with_sender(
+            fd,
+            info.node_id,
+            frame_tx.clone(),
+            swap_colors,
+            fps,
+            true,
+            size,
+        )
+        .map_err(CaptureError::PipeWire)?;
+        switcher = Some(Switcher {
+            pipewire_fd,
+            frame_tx,
+            swap_colors,
+            fps,
+            sources: info.sources.clone(),
+            current,
+        });
+        vec![pw_stream]
+    } else if let [monitor] = &info.monitors[..] {
+        let pw_stream = PwStream::start_with_sender(
+            pipewire_fd,
+            monitor.node_id,
+            frame_tx,
+            swap_colors,
+            fps,
+            true,
+            size,
+        )
+        .map_err(CaptureError::PipeWire)?;
+        vec![pw_stream]
+    } else {
+        let mut pw_streams = Vec::with_capacity(info.monitors.len());
+        let mut monitor_rxs = Vec::with_capacity(info.monitors.len());
+        for monitor in &info.monitors {
+            let fd = pipewire_fd.try_clone().map_err(CaptureError::DuplicateFd)?;
+            // The compositor blits BGRA, so merged monitors stay in RGB.
+            let (pw_stream, rx) = PwStream::start(
+                fd,
+                monitor.node_id,
+                channel_capacity,
+                swap_colors,
+                fps,
+                false,
+                size,
+            )
             .map_err(CaptureError::PipeWire)?;
+            pw_streams.push(pw_stream);
+            monitor_rxs.push(rx);
+        }
+        let compositor = FrameCompositor::with_output(&info.monitors, monitor_rxs, frame_tx);
+        tokio::spawn(compositor.run());
+        pw_streams
+    };
 
-    let handle = CaptureHandle {
-        _session: session,
+    let portal = PortalHold {
+        session,
         _proxy: proxy,
-        _pw_stream: pw_stream,
     };
+    Ok((
+        portal,
+        ActiveStreams {
+            pw_streams,
+            switcher,
+        },
+        info,
+    ))
+}
 
-    tracing::info!(
-        width = info.width,
-        height = info.height,
-        node_id = info.node_id,
-        "Screen capture session started"
-    );
+/// First wait before reconnecting; doubled after each failed attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
-    Ok((handle, frame_rx, info))
+/// Longest wait between reconnection attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Forwards a session's events to the consumer and replaces the session
+/// when it is lost.
+struct Supervisor {
+    settings: Arc<Mutex<CaptureSettings>>,
+    active: Arc<Mutex<Option<ActiveStreams>>>,
+    frame_tx: mpsc::Sender<CaptureEvent>,
+    restore_token: watch::Sender<Option<String>>,
+    origin: watch::Sender<Option<(i32, i32)>>,
+    ended: watch::Sender<Option<CaptureEnd>>,
+}
+
+/// How forwarding a session's events stopped.
+enum SessionEnd {
+    /// A stream failed or `PipeWire` went away; worth reconnecting.
+    Interrupted,
+    /// The portal closed the session: the user stopped sharing.
+    Closed,
+    /// The consumer dropped its receiver.
+    Unused,
+}
+
+impl Supervisor {
+    /// Run until the consumer drops its receiver or the capture ends for
+    /// good (see [`CaptureEnd`]).
+    async fn run(self, mut portal: PortalHold, mut events: mpsc::Receiver<CaptureEvent>) {
+        loop {
+            match self.forward(&portal, &mut events).await {
+                SessionEnd::Interrupted => {}
+                SessionEnd::Closed => {
+                    self.end(CaptureEnd::Revoked);
+                    return;
+                }
+                SessionEnd::Unused => return,
+            }
+            // Without a token the portal would ask the user again.
+            if self.restore_token.borrow().is_none() {
+                self.end(CaptureEnd::NoRestoreToken);
+                return;
+            }
+            tracing::warn!("Screen capture interrupted, reconnecting");
+            // Without a client reading the channel may be full; the next
+            // session's frames matter more than the notice.
+            if let Err(mpsc::error::TrySendError::Closed(_)) =
+                self.frame_tx.try_send(CaptureEvent::Interrupted)
+            {
+                return;
+            }
+
+            // Stop what is left of the old session before starting anew.
+            // Closing the channel first keeps a stream thread from waiting
+            // on it while being stopped.
+            drop(events);
+            let current = self
+                .active
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .and_then(|active| active.switcher.map(|s| s.current))
+                .unwrap_or(0);
+            if let Err(e) = portal.session.close().await {
+                tracing::debug!("Failed to close the old ScreenCast session: {e}");
+            }
+
+            let Some((new_portal, new_events)) = self.reconnect(current).await else {
+                return;
+            };
+            portal = new_portal;
+            events = new_events;
+        }
+    }
+
+    /// Forward events until the session is lost or the consumer goes away.
+    ///
+    /// Frames are dropped rather than queued if the consumer falls behind,
+    /// as the streams themselves do.
+    async fn forward(
+        &self,
+        portal: &PortalHold,
+        events: &mut mpsc::Receiver<CaptureEvent>,
+    ) -> SessionEnd {
+        let closed = session_closed(&portal.session);
+        tokio::pin!(closed);
+        loop {
+            let event = tokio::select! {
+                () = &mut closed => {
+                    tracing::warn!("ScreenCast portal closed the session");
+                    return SessionEnd::Closed;
+                }
+                () = self.frame_tx.closed() => return SessionEnd::Unused,
+                event = events.recv() => event,
+            };
+            match event {
+                Some(CaptureEvent::Interrupted) | None => return SessionEnd::Interrupted,
+                Some(event) => match self.frame_tx.try_send(event) {
+                    Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {}
+                    Err(mpsc::error::TrySendError::Closed(_)) => return SessionEnd::Unused,
+                },
+            }
+        }
+    }
+
+    /// Stop for good: the consumer's events end after `end` is published.
+    fn end(&self, end: CaptureEnd) {
+        tracing::error!("Screen capture stopped: {end}");
+        self.ended.send_replace(Some(end));
+    }
+
+    /// Start a new session, retrying with backoff. Returns `None` if the
+    /// consumer went away meanwhile or the user cancelled the dialog.
+    async fn reconnect(
+        &self,
+        current: usize,
+    ) -> Option<(PortalHold, mpsc::Receiver<CaptureEvent>)> {
+        let mut delay = RECONNECT_DELAY;
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                () = self.frame_tx.closed() => return None,
+            }
+
+            let restore_token = self.restore_token.borrow().clone();
+            let settings = *self.settings.lock().unwrap_or_else(PoisonError::into_inner);
+            let (session_tx, session_rx) = mpsc::channel(settings.channel_capacity);
+            match open_session(restore_token.as_deref(), settings, session_tx, current).await {
+                Ok((portal, active, info)) => {
+                    tracing::info!(
+                        width = info.width,
+                        height = info.height,
+                        node_id = info.node_id,
+                        "Screen capture reconnected"
+                    );
+                    if info.restore_token.is_some() {
+                        self.restore_token.send_replace(info.restore_token);
+                    }
+                    self.origin.send_replace(info.origin);
+                    *self.active.lock().unwrap_or_else(PoisonError::into_inner) = Some(active);
+                    return Some((portal, session_rx));
+                }
+                Err(CaptureError::Portal(PortalError::Cancelled)) => {
+                    self.end(CaptureEnd::Cancelled);
+                    return None;
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    tracing::warn!(retry_in = ?delay, "Screen capture reconnection failed: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Resolves when the portal closes `session`; never, if that cannot be
+/// watched.
+async fn session_closed(session: &ashpd::desktop::Session<'static, Screencast<'static>>) {
+    match session.receive_closed().await {
+        Ok(closed) => {
+            let mut closed = std::pin::pin!(closed);
+            if closed.next().await.is_some() {
+                return;
+            }
+        }
+        Err(e) => tracing::debug!("Cannot watch the ScreenCast session for closing: {e}"),
+    }
+    std::future::pending().await
+}
+
+/// Place the portal's streams in a virtual desktop.
+///
+/// Positions are shifted so the layout starts at the origin; the shift is
+/// returned as the compositor position of the desktop, unless a stream
+/// has no position. Those are placed to the right of the others; streams
+/// without a size default to `default_size`.
+fn monitor_layout(
+    streams: &[PortalStream],
+    default_size: (u16, u16),
+) -> (Vec<MonitorInfo>, Option<(i32, i32)>) {
+    let size =
+        |v: Option<i32>, default: u16| v.and_then(|v| u16::try_from(v).ok()).unwrap_or(default);
+    let mut monitors: Vec<MonitorInfo> = Vec::with_capacity(streams.len());
+    for stream in streams {
+        let (x, y) = stream.position.unwrap_or_else(|| {
+            let right = monitors.iter().map(|m| m.x + i32::from(m.width)).max();
+            (right.unwrap_or(0), 0)
+        });
+        monitors.push(MonitorInfo {
+            node_id: stream.node_id,
+            width: size(stream.width, default_size.0),
+            height: size(stream.height, default_size.1),
+            x,
+            y,
+        });
+    }
+
+    let min_x = monitors.iter().map(|m| m.x).min().unwrap_or(0);
+    let min_y = monitors.iter().map(|m| m.y).min().unwrap_or(0);
+    for monitor in &mut monitors {
+        monitor.x -= min_x;
+        monitor.y -= min_y;
+    }
+    let positioned = streams.iter().all(|stream| stream.position.is_some());
+    (monitors, positioned.then_some((min_x, min_y)))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -113,4 +727,16 @@ pub enum CaptureError {
 
     #[error("PipeWire stream failed")]
     PipeWire(#[source] PwError),
+
+    #[error("failed to duplicate the PipeWire fd for another monitor")]
+    DuplicateFd(#[source] std::io::Error),
+
+    #[error("capture session was not started with switchable monitors")]
+    NotSwitchable,
+
+    #[error("capture is reconnecting")]
+    Reconnecting,
+
+    #[error("no selected monitor with index {0}")]
+    NoSuchSource(usize),
 }
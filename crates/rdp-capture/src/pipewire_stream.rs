@@ -1,22 +1,28 @@
 use std::os::fd::OwnedFd;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
 use pipewire as pw;
 use pw::properties::properties;
+use pw::spa::param::video::{VideoFormat, VideoInfoRaw};
 use pw::spa::pod::serialize::PodSerializer;
 use pw::spa::pod::Pod;
 use pw::stream::{Stream, StreamFlags, StreamState};
 use tokio::sync::mpsc;
 
 use crate::frame::{CaptureEvent, CapturedFrame, PixelFormat};
+use crate::pool::{BufferPool, PooledBuffer};
 
 /// Handle to a running `PipeWire` capture stream.
 ///
 /// The stream runs on a dedicated OS thread with its own `PipeWire` `MainLoop`.
-/// Frames are delivered via a tokio mpsc channel.
+/// Frames are delivered via a tokio mpsc channel. If the stream fails, its
+/// node goes away or the connection to `PipeWire` drops, the thread sends
+/// [`CaptureEvent::Interrupted`] and exits.
 pub struct PwStream {
     running: Arc<AtomicBool>,
+    /// Size asked of a virtual monitor, packed by [`pack_size`].
+    requested_size: Arc<AtomicU64>,
     thread: Option<std::thread::JoinHandle<()>>,
 }
 
@@ -24,6 +30,11 @@ impl PwStream {
     /// Start capturing from the given `PipeWire` node using the portal's fd.
     ///
     /// Returns a `PwStream` handle and a receiver for captured frames.
+    /// The compositor is asked for at most `fps` frames per second. With
+    /// `allow_nv12`, NV12 is offered next to the RGB formats and passed on
+    /// as [`PixelFormat::Nv12`] if chosen; 10-bit RGB is always accepted
+    /// and reduced to 8-bit BGRA. `size` is only given for virtual
+    /// monitors, which are created at the size their consumer asks for.
     ///
     /// # Errors
     ///
@@ -33,29 +44,73 @@ impl PwStream {
         node_id: u32,
         channel_capacity: usize,
         swap_colors: bool,
+        fps: u32,
+        allow_nv12: bool,
+        size: Option<(u32, u32)>,
     ) -> Result<(Self, mpsc::Receiver<CaptureEvent>), PwError> {
         let (tx, rx) = mpsc::channel(channel_capacity);
+        let stream =
+            Self::start_with_sender(pipewire_fd, node_id, tx, swap_colors, fps, allow_nv12, size)?;
+        Ok((stream, rx))
+    }
+
+    /// Start capturing from the given `PipeWire` node into an existing
+    /// channel.
+    ///
+    /// Used to replace a stream without disturbing the channel's consumer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PwError` if the `PipeWire` thread cannot be spawned.
+    pub fn start_with_sender(
+        pipewire_fd: OwnedFd,
+        node_id: u32,
+        tx: mpsc::Sender<CaptureEvent>,
+        swap_colors: bool,
+        fps: u32,
+        allow_nv12: bool,
+        size: Option<(u32, u32)>,
+    ) -> Result<Self, PwError> {
         let running = Arc::new(AtomicBool::new(true));
         let running_clone = Arc::clone(&running);
+        let requested_size = Arc::new(AtomicU64::new(pack_size(size)));
+        let requested_size_clone = Arc::clone(&requested_size);
 
         let thread = std::thread::Builder::new()
             .name("pw-capture".into())
             .spawn(move || {
-                if let Err(e) =
-                    run_pipewire_loop(pipewire_fd, node_id, tx, running_clone, swap_colors)
-                {
+                if let Err(e) = run_pipewire_loop(
+                    pipewire_fd,
+                    node_id,
+                    tx.clone(),
+                    Arc::clone(&running_clone),
+                    swap_colors,
+                    fps,
+                    allow_nv12,
+                    &requested_size_clone,
+                ) {
                     tracing::error!("PipeWire thread exited with error: {e}");
+                    interrupt(&running_clone, &tx);
                 }
             })
             .map_err(PwError::SpawnThread)?;
 
-        Ok((
-            Self {
-                running,
-                thread: Some(thread),
-            },
-            rx,
-        ))
+        Ok(Self {
+            running,
+            requested_size,
+            thread: Some(thread),
+        })
+    }
+
+    /// Ask a virtual monitor's stream for a new size; the compositor
+    /// resizes the virtual output to match.
+    ///
+    /// Only meaningful for streams started with a `size`: a monitor's size
+    /// is set by the compositor, and a fixed size it cannot produce would
+    /// fail negotiation.
+    pub fn request_size(&self, width: u32, height: u32) {
+        self.requested_size
+            .store(pack_size(Some((width, height))), Ordering::SeqCst);
     }
 
     /// Stop the `PipeWire` stream and join the thread.
@@ -73,14 +128,107 @@ impl Drop for PwStream {
     }
 }
 
+/// The raw video format negotiated for the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct NegotiatedFormat {
+    format: VideoFormat,
+    width: u32,
+    height: u32,
+    /// Frame rate as a fraction (`0/1` for variable rate).
+    framerate: (u32, u32),
+    /// DRM format modifier; 0 (linear) for shared-memory buffers.
+    modifier: u64,
+}
+
+impl NegotiatedFormat {
+    /// Parse a `Format` param.
+    ///
+    /// Returns `None` for anything other than raw video.
+    fn parse(pod: &Pod) -> Option<Self> {
+        let (media_type, media_subtype) = pw::spa::param::format_utils::parse_format(pod).ok()?;
+        if media_type != pw::spa::param::format::MediaType::Video
+            || media_subtype != pw::spa::param::format::MediaSubtype::Raw
+        {
+            return None;
+        }
+        let mut info = VideoInfoRaw::new();
+        info.parse(pod).ok()?;
+        Some(Self {
+            format: info.format(),
+            width: info.size().width,
+            height: info.size().height,
+            framerate: (info.framerate().num, info.framerate().denom),
+            modifier: info.modifier(),
+        })
+    }
+
+    /// How pixels are laid out in buffers of this format, or `None` if
+    /// the format was not one we offered.
+    fn layout(self) -> Option<SourceLayout> {
+        let layout = match self.format {
+            VideoFormat::BGRx | VideoFormat::BGRA => SourceLayout::Rgb8 { rgb_order: false },
+            VideoFormat::RGBx | VideoFormat::RGBA => SourceLayout::Rgb8 { rgb_order: true },
+            VideoFormat::xRGB_210LE | VideoFormat::ARGB_210LE => {
+                SourceLayout::Rgb10 { rgb_order: false }
+            }
+            VideoFormat::xBGR_210LE | VideoFormat::ABGR_210LE => {
+                SourceLayout::Rgb10 { rgb_order: true }
+            }
+            VideoFormat::NV12 => SourceLayout::Nv12,
+            _ => return None,
+        };
+        Some(layout)
+    }
+}
+
+/// Pixel layout of `PipeWire` buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceLayout {
+    /// 8 bits per channel, 4 bytes per pixel; R first if `rgb_order`,
+    /// else B first.
+    Rgb8 { rgb_order: bool },
+    /// 10 bits per channel packed into little-endian 32-bit words with
+    /// the top two bits unused; R in the low bits if `rgb_order`, else B.
+    Rgb10 { rgb_order: bool },
+    /// A Y plane followed by a half-resolution interleaved UV plane.
+    Nv12,
+}
+
+/// State shared by the stream listener callbacks.
+struct StreamData {
+    tx: mpsc::Sender<CaptureEvent>,
+    /// Frame buffers, recycled once the server is done with them.
+    pool: BufferPool,
+    /// Format from the last `Format` param; frames are dropped until one
+    /// has been negotiated.
+    format: Option<NegotiatedFormat>,
+}
+
+/// Pack a requested size into one atomic value; 0 means none.
+fn pack_size(size: Option<(u32, u32)>) -> u64 {
+    size.map_or(0, |(width, height)| {
+        (u64::from(width) << 32) | u64::from(height)
+    })
+}
+
+/// Inverse of [`pack_size`].
+#[allow(clippy::cast_possible_truncation)] // each half holds a u32
+fn unpack_size(packed: u64) -> Option<(u32, u32)> {
+    (packed != 0).then_some(((packed >> 32) as u32, packed as u32))
+}
+
 /// Run the `PipeWire` main loop on a dedicated thread.
 #[allow(clippy::needless_pass_by_value)] // Arc is moved from a thread spawn closure
+#[allow(clippy::too_many_arguments)]
 fn run_pipewire_loop(
     pipewire_fd: OwnedFd,
     node_id: u32,
     frame_tx: mpsc::Sender<CaptureEvent>,
     running: Arc<AtomicBool>,
     swap_colors: bool,
+    fps: u32,
+    allow_nv12: bool,
+    requested_size: &AtomicU64,
 ) -> Result<(), PwError> {
     pw::init();
 
@@ -101,42 +249,82 @@ fn run_pipewire_loop(
     )
     .map_err(|_| PwError::CreateStream)?;
 
-    let seq = Arc::new(AtomicU64::new(0));
-    // Track the negotiated pixel format (SPA_VIDEO_FORMAT_* value).
-    // Default to BGRx since that's our preferred format.
-    let negotiated_format = Arc::new(AtomicU32::new(
-        pw::spa::param::video::VideoFormat::BGRx.as_raw(),
-    ));
-    let negotiated_format_cb = Arc::clone(&negotiated_format);
+    let seq = AtomicU64::new(0);
+    // Frames may be queued in the channel, in the server's repaint cache
+    // and in the encoder at the same time.
+    let pool = BufferPool::new(frame_tx.max_capacity() + 4);
+    let data = StreamData {
+        tx: frame_tx,
+        pool,
+        format: None,
+    };
+
+    // A compositor restart shows up as an error on the core.
+    let _core_listener = core
+        .add_listener_local()
+        .error({
+            let running = Arc::clone(&running);
+            let tx = data.tx.clone();
+            move |id, _seq, res, message| {
+                if id == pw::core::PW_ID_CORE {
+                    tracing::error!(res, error = message, "PipeWire connection failed");
+                    interrupt(&running, &tx);
+                }
+            }
+        })
+        .register();
 
     let _listener = stream
-        .add_local_listener_with_user_data(frame_tx)
-        .state_changed(|_stream, _tx, old, new| {
-            tracing::debug!("PipeWire stream state: {old:?} -> {new:?}");
-            if new == StreamState::Error(String::new()) {
-                tracing::error!("PipeWire stream entered error state");
+        .add_local_listener_with_user_data(data)
+        .state_changed({
+            let running = Arc::clone(&running);
+            move |_stream, data, old, new| {
+                tracing::debug!("PipeWire stream state: {old:?} -> {new:?}");
+                match (&old, &new) {
+                    (_, StreamState::Error(message)) => {
+                        tracing::error!(error = %message, "PipeWire stream entered error state");
+                        interrupt(&running, &data.tx);
+                    }
+                    (StreamState::Streaming | StreamState::Paused, StreamState::Unconnected) => {
+                        tracing::warn!("PipeWire stream lost its node");
+                        interrupt(&running, &data.tx);
+                    }
+                    _ => {}
+                }
             }
         })
-        .param_changed(move |_stream, _tx, id, pod| {
+        .param_changed(|stream, data, id, pod| {
             if id != pw::spa::param::ParamType::Format.as_raw() {
                 return;
             }
-            if let Some(pod) = pod {
-                // Parse the format pod to extract the negotiated video format.
-                if let Ok((_, pw::spa::pod::Value::Object(obj))) = pw::spa::pod::deserialize::PodDeserializer::deserialize_any_from(pod.as_bytes()) {
-                    for prop in &obj.properties {
-                        if prop.key == pw::spa::param::format::FormatProperties::VideoFormat.as_raw() {
-                            if let pw::spa::pod::Value::Id(fmt_id) = prop.value {
-                                negotiated_format_cb.store(fmt_id.0, Ordering::SeqCst);
-                                tracing::info!(format_id = fmt_id.0, "PipeWire negotiated video format");
-                            }
-                        }
-                    }
-                }
+            let Some(pod) = pod else {
+                return;
+            };
+            data.format = NegotiatedFormat::parse(pod);
+            match data.format {
+                Some(format) => tracing::info!(
+                    format = ?format.format,
+                    width = format.width,
+                    height = format.height,
+                    framerate = format_args!("{}/{}", format.framerate.0, format.framerate.1),
+                    modifier = format_args!("{:#x}", format.modifier),
+                    "PipeWire negotiated video format"
+                ),
+                None => tracing::warn!("PipeWire negotiated a format that is not raw video"),
+            }
+
+            // Ask for timestamps, damage and cursor metadata on buffers.
+            let meta_pods = build_meta_pods();
+            let mut params: Vec<&Pod> = meta_pods
+                .iter()
+                .map(|pod| Pod::from_bytes(pod).expect("valid meta pod"))
+                .collect();
+            if let Err(e) = stream.update_params(&mut params) {
+                tracing::warn!("Failed to request buffer metadata: {e}");
             }
         })
-        .process(move |stream_ref, tx| {
-            process_frame(stream_ref, tx, &seq, &negotiated_format, swap_colors);
+        .process(move |stream_ref, data| {
+            process_frame(stream_ref, data, &seq, swap_colors);
         })
         .register()
         .map_err(|_| PwError::RegisterListener)?;
@@ -144,7 +332,8 @@ fn run_pipewire_loop(
     // Request BGRx/BGRA SHM format explicitly. Without format params,
     // PipeWire may negotiate DMA-BUF which yields black frames when
     // MAP_BUFFERS maps GPU memory that hasn't been synced to CPU.
-    let format_pod = build_video_format_pod();
+    let mut size = requested_size.load(Ordering::SeqCst);
+    let format_pod = build_video_format_pod(fps, allow_nv12, unpack_size(size));
     let mut params = [Pod::from_bytes(&format_pod).expect("valid format pod")];
 
     stream
@@ -160,17 +349,91 @@ fn run_pipewire_loop(
 
     while running.load(Ordering::SeqCst) {
         mainloop.loop_().iterate(std::time::Duration::from_millis(50));
+
+        // Renegotiate a virtual monitor at its new size.
+        let requested = requested_size.load(Ordering::SeqCst);
+        if requested != size {
+            size = requested;
+            if let Some((width, height)) = unpack_size(size) {
+                tracing::info!(width, height, "Requesting a new virtual monitor size");
+            }
+            let format_pod = build_video_format_pod(fps, allow_nv12, unpack_size(size));
+            let mut params = [Pod::from_bytes(&format_pod).expect("valid format pod")];
+            if let Err(e) = stream.update_params(&mut params) {
+                tracing::warn!("Failed to request a new video size: {e}");
+            }
+        }
     }
 
     tracing::info!("PipeWire main loop exiting");
     Ok(())
 }
 
+/// Stop the stream's main loop and tell the consumer the capture is gone.
+///
+/// Does nothing once the stream is stopping, so stopping it on purpose is
+/// not reported.
+fn interrupt(running: &AtomicBool, tx: &mpsc::Sender<CaptureEvent>) {
+    if running.swap(false, Ordering::SeqCst) {
+        // Not a tokio thread; wait for room, as this event must not be lost.
+        let _ = tx.blocking_send(CaptureEvent::Interrupted);
+    }
+}
+
 /// Build a SPA format pod requesting BGRx/BGRA raw video in SHM.
 ///
 /// This tells `PipeWire` to prefer shared-memory buffers with CPU-readable
 /// pixel data instead of DMA-BUF handles that may yield black frames.
-fn build_video_format_pod() -> Vec<u8> {
+/// The other 8-bit RGB orders, 10-bit RGB (HDR-capable outputs) and, with
+/// `allow_nv12`, NV12 are accepted for compositors that prefer them.
+///
+/// The frame rate is variable (`0/1`, frames only come when the screen
+/// changes) and capped at `fps`. The size is fixed to `size` if given
+/// (virtual monitors) and otherwise left to the compositor.
+fn build_video_format_pod(fps: u32, allow_nv12: bool, size: Option<(u32, u32)>) -> Vec<u8> {
+    let fps = fps.max(1);
+    let mut alternatives = vec![
+        VideoFormat::BGRA,
+        VideoFormat::RGBx,
+        VideoFormat::RGBA,
+        VideoFormat::xRGB_210LE,
+        VideoFormat::xBGR_210LE,
+        VideoFormat::ARGB_210LE,
+        VideoFormat::ABGR_210LE,
+    ];
+    if allow_nv12 {
+        alternatives.push(VideoFormat::NV12);
+    }
+    let video_format = pw::spa::pod::Property {
+        key: pw::spa::param::format::FormatProperties::VideoFormat.as_raw(),
+        flags: pw::spa::pod::PropertyFlags::empty(),
+        value: pw::spa::pod::Value::Choice(pw::spa::pod::ChoiceValue::Id(pw::spa::utils::Choice(
+            pw::spa::utils::ChoiceFlags::empty(),
+            pw::spa::utils::ChoiceEnum::Enum {
+                default: pw::spa::utils::Id(VideoFormat::BGRx.as_raw()),
+                alternatives: alternatives
+                    .into_iter()
+                    .map(|format| pw::spa::utils::Id(format.as_raw()))
+                    .collect(),
+            },
+        ))),
+    };
+    let video_size = match size {
+        Some((width, height)) => pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::VideoSize,
+            Rectangle,
+            pw::spa::utils::Rectangle { width, height }
+        ),
+        None => pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::VideoSize,
+            Choice,
+            Range,
+            Rectangle,
+            pw::spa::utils::Rectangle { width: 1920, height: 1080 },
+            pw::spa::utils::Rectangle { width: 1, height: 1 },
+            pw::spa::utils::Rectangle { width: 8192, height: 8192 }
+        ),
+    };
     let obj = pw::spa::pod::object!(
         pw::spa::utils::SpaTypes::ObjectParamFormat,
         pw::spa::param::ParamType::EnumFormat,
@@ -184,33 +447,21 @@ fn build_video_format_pod() -> Vec<u8> {
             Id,
             pw::spa::param::format::MediaSubtype::Raw
         ),
+        video_format,
+        video_size,
         pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoFormat,
-            Choice,
-            Enum,
-            Id,
-            pw::spa::param::video::VideoFormat::BGRx,
-            pw::spa::param::video::VideoFormat::BGRA,
-            pw::spa::param::video::VideoFormat::RGBx,
-            pw::spa::param::video::VideoFormat::RGBA
-        ),
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoSize,
-            Choice,
-            Range,
-            Rectangle,
-            pw::spa::utils::Rectangle { width: 1920, height: 1080 },
-            pw::spa::utils::Rectangle { width: 1, height: 1 },
-            pw::spa::utils::Rectangle { width: 8192, height: 8192 }
+            pw::spa::param::format::FormatProperties::VideoFramerate,
+            Fraction,
+            pw::spa::utils::Fraction { num: 0, denom: 1 }
         ),
         pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoFramerate,
+            pw::spa::param::format::FormatProperties::VideoMaxFramerate,
             Choice,
             Range,
             Fraction,
-            pw::spa::utils::Fraction { num: 30, denom: 1 },
+            pw::spa::utils::Fraction { num: fps, denom: 1 },
             pw::spa::utils::Fraction { num: 1, denom: 1 },
-            pw::spa::utils::Fraction { num: 120, denom: 1 }
+            pw::spa::utils::Fraction { num: fps, denom: 1 }
         ),
     );
 
@@ -223,17 +474,92 @@ fn build_video_format_pod() -> Vec<u8> {
     .into_inner()
 }
 
+/// Damage regions requested per buffer; more changes than this are
+/// reported as the bounding region by the compositor.
+const MAX_DAMAGE_REGIONS: usize = 16;
+
+/// Largest cursor bitmap requested per buffer, in pixels per side.
+const MAX_CURSOR_META_SIZE: usize = crate::spa_meta::MAX_CURSOR_SIZE as usize;
+
+/// Build SPA meta params asking for buffer headers (presentation times),
+/// damage regions and cursor metadata.
+fn build_meta_pods() -> Vec<Vec<u8>> {
+    use pw::spa::sys as spa_sys;
+
+    let cursor_size = std::mem::size_of::<spa_sys::spa_meta_cursor>()
+        + std::mem::size_of::<spa_sys::spa_meta_bitmap>()
+        + MAX_CURSOR_META_SIZE * MAX_CURSOR_META_SIZE * 4;
+    vec![
+        header_meta_pod(),
+        meta_pod(
+            spa_sys::SPA_META_VideoDamage,
+            std::mem::size_of::<spa_sys::spa_meta_region>() * MAX_DAMAGE_REGIONS,
+        ),
+        meta_pod(spa_sys::SPA_META_Cursor, cursor_size),
+    ]
+}
+
+/// Build the SPA meta param asking for buffer headers, which carry the
+/// presentation time of each buffer.
+pub(crate) fn header_meta_pod() -> Vec<u8> {
+    use pw::spa::sys as spa_sys;
+
+    meta_pod(
+        spa_sys::SPA_META_Header,
+        std::mem::size_of::<spa_sys::spa_meta_header>(),
+    )
+}
+
+/// Serialize a `Meta` param pod for metadata of `type_` taking `size`
+/// bytes per buffer.
+fn meta_pod(type_: u32, size: usize) -> Vec<u8> {
+    use pw::spa::sys as spa_sys;
+
+    let obj = pw::spa::pod::Object {
+        type_: pw::spa::utils::SpaTypes::ObjectParamMeta.as_raw(),
+        id: pw::spa::param::ParamType::Meta.as_raw(),
+        properties: vec![
+            pw::spa::pod::Property {
+                key: spa_sys::SPA_PARAM_META_type,
+                flags: pw::spa::pod::PropertyFlags::empty(),
+                value: pw::spa::pod::Value::Id(pw::spa::utils::Id(type_)),
+            },
+            pw::spa::pod::Property {
+                key: spa_sys::SPA_PARAM_META_size,
+                flags: pw::spa::pod::PropertyFlags::empty(),
+                value: pw::spa::pod::Value::Int(
+                    i32::try_from(size).expect("meta size fits in i32"),
+                ),
+            },
+        ],
+    };
+    PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(obj),
+    )
+    .expect("meta pod serialization")
+    .0
+    .into_inner()
+}
+
 /// Process a single frame from the `PipeWire` stream.
 ///
 /// Uses the raw `PipeWire` buffer API to access SPA metadata (damage rects,
 /// cursor info) that the safe `dequeue_buffer()` wrapper does not expose.
+///
+/// Geometry comes from the negotiated format; rows may be padded, so the
+/// stride is passed on separately.
 fn process_frame(
     stream: &pw::stream::StreamRef,
-    tx: &mut mpsc::Sender<CaptureEvent>,
+    data: &StreamData,
     seq: &AtomicU64,
-    negotiated_format: &AtomicU32,
     swap_colors: bool,
 ) {
+    let Some(format) = data.format else {
+        tracing::trace!("Buffer before format negotiation, skipping");
+        return;
+    };
+
     // Dequeue buffer using raw API for SPA metadata access.
     // Safety: stream is valid within the process callback.
     let raw_pw_buf = unsafe { stream.dequeue_raw_buffer() };
@@ -253,98 +579,87 @@ fn process_frame(
     let damage = unsafe { crate::spa_meta::extract_damage(spa_buf) };
     let cursor = unsafe { crate::spa_meta::extract_cursor(spa_buf) };
 
-    // Access frame data through the raw spa_data array.
-    let (n_datas, datas_ptr) = unsafe { ((*spa_buf).n_datas, (*spa_buf).datas) };
-    if n_datas == 0 || datas_ptr.is_null() {
+    // Nothing changed on screen: skip the copy, but pass cursor movement on.
+    if damage.as_ref().is_some_and(Vec::is_empty) {
         unsafe { stream.queue_raw_buffer(raw_pw_buf) };
+        if let Some(cursor_info) = cursor {
+            if data.tx.try_send(CaptureEvent::Cursor(cursor_info)).is_err() {
+                tracing::trace!("Frame channel full, dropping cursor update");
+            }
+        }
         return;
     }
+    let pts_ns = crate::clock::presentation_time(unsafe { crate::spa_meta::extract_pts(spa_buf) });
 
-    // Safety: n_datas > 0 and datas_ptr is valid; Data is #[repr(transparent)].
-    let data: &mut pw::spa::buffer::Data =
-        unsafe { &mut *datas_ptr.cast::<pw::spa::buffer::Data>() };
-
-    // Read chunk metadata before taking the mutable data borrow.
-    let chunk = data.chunk();
-    #[allow(clippy::cast_sign_loss)] // negative stride is invalid, treated as zero below
-    let stride = chunk.stride() as u32;
-    let offset = chunk.offset() as usize;
-    let size = chunk.size() as usize;
-
-    let Some(slice) = data.data() else {
+    let Some(layout) = format.layout() else {
         unsafe { stream.queue_raw_buffer(raw_pw_buf) };
         return;
     };
 
-    if size == 0 || stride == 0 {
+    // Access frame data through the raw spa_data array.
+    let (n_datas, datas_ptr) = unsafe { ((*spa_buf).n_datas, (*spa_buf).datas) };
+    if n_datas == 0 || datas_ptr.is_null() {
         unsafe { stream.queue_raw_buffer(raw_pw_buf) };
         return;
     }
 
-    // Infer dimensions from stride and size.
-    // PipeWire BGRx/BGRA is 4 bytes per pixel.
-    let bpp = 4u32;
-    let width = stride / bpp;
-    #[allow(clippy::cast_possible_truncation)] // frame size always fits in u32
-    let height = if stride > 0 { (size as u32) / stride } else { 0 };
-
-    if width == 0 || height == 0 {
+    // Safety: n_datas > 0 and datas_ptr is valid; planes stay mapped until
+    // the buffer is queued back.
+    let Some(plane) = (unsafe { mapped_plane(datas_ptr, 0) }) else {
         unsafe { stream.queue_raw_buffer(raw_pw_buf) };
         return;
-    }
-
-    let end = offset + size;
-    if end > slice.len() {
-        tracing::warn!(
-            offset,
-            size,
-            slice_len = slice.len(),
-            "Buffer slice out of bounds"
-        );
-        unsafe { stream.queue_raw_buffer(raw_pw_buf) };
-        return;
-    }
-
-    // Copy pixel data before returning the buffer to PipeWire.
-    let mut frame_data = slice[offset..end].to_vec();
-    let sequence = seq.fetch_add(1, Ordering::Relaxed);
-
-    // Safety: we've finished reading from the buffer, return it to PipeWire.
-    unsafe { stream.queue_raw_buffer(raw_pw_buf) };
-
-    // Check if PipeWire negotiated an RGB-order format (RGBx or RGBA).
-    // The RDP server expects BGRA, so swap R and B channels if needed.
-    let fmt = negotiated_format.load(Ordering::Relaxed);
+    };
 
     // Log raw pixel bytes on the first frame to diagnose color channel order.
-    if sequence == 0 && frame_data.len() >= 12 {
+    if seq.load(Ordering::Relaxed) == 0 && plane.data.len() >= 8 {
+        let src = plane.data;
         tracing::info!(
-            spa_format_id = fmt,
-            bgrx_id = pw::spa::param::video::VideoFormat::BGRx.as_raw(),
-            rgbx_id = pw::spa::param::video::VideoFormat::RGBx.as_raw(),
+            format = ?format.format,
             raw_pixel_0 = format_args!("[{:#04x},{:#04x},{:#04x},{:#04x}]",
-                frame_data[0], frame_data[1], frame_data[2], frame_data[3]),
+                src[0], src[1], src[2], src[3]),
             raw_pixel_1 = format_args!("[{:#04x},{:#04x},{:#04x},{:#04x}]",
-                frame_data[4], frame_data[5], frame_data[6], frame_data[7]),
+                src[4], src[5], src[6], src[7]),
             "PipeWire first frame: raw pixels BEFORE any swap"
         );
     }
 
-    let is_rgb_order = fmt == pw::spa::param::video::VideoFormat::RGBx.as_raw()
-        || fmt == pw::spa::param::video::VideoFormat::RGBA.as_raw();
-    if is_rgb_order ^ swap_colors {
-        for pixel in frame_data.chunks_exact_mut(4) {
-            pixel.swap(0, 2); // R,G,B,A -> B,G,R,A  (or force swap when override set)
+    // Copy pixel data into a pooled buffer before returning the buffer to
+    // PipeWire. The copy is the only pass over the pixels.
+    let (width, height) = (format.width, format.height);
+    let copied = match layout {
+        SourceLayout::Rgb8 { .. } | SourceLayout::Rgb10 { .. } => {
+            copy_rgb(&plane, width, height, layout, swap_colors, &data.pool)
+                .map(|(buffer, stride)| (buffer, stride, PixelFormat::Bgra))
         }
-    }
+        SourceLayout::Nv12 => {
+            // Safety: as above, for the second plane if there is one.
+            let chroma = (n_datas > 1)
+                .then(|| unsafe { mapped_plane(datas_ptr, 1) })
+                .flatten();
+            copy_nv12(&plane, chroma, width, height, &data.pool)
+                .map(|(buffer, stride)| (buffer, stride, PixelFormat::Nv12))
+        }
+    };
+
+    // Safety: we've finished reading from the buffer, return it to PipeWire.
+    unsafe { stream.queue_raw_buffer(raw_pw_buf) };
+
+    let Some((buffer, stride, pixel_format)) = copied else {
+        return;
+    };
+    let Ok(stride) = u32::try_from(stride) else {
+        return;
+    };
+    let sequence = seq.fetch_add(1, Ordering::Relaxed);
 
     let frame = CapturedFrame {
-        data: frame_data,
+        data: buffer.freeze(),
         width,
         height,
-        format: PixelFormat::Bgra,
+        format: pixel_format,
         stride,
         sequence,
+        pts_ns,
         damage,
     };
 
@@ -354,11 +669,204 @@ fn process_frame(
     } else {
         CaptureEvent::Frame(frame)
     };
-    if tx.try_send(event).is_err() {
+    if data.tx.try_send(event).is_err() {
         tracing::trace!("Frame channel full, dropping frame {sequence}");
     }
 }
 
+/// One mapped plane of a `PipeWire` buffer.
+struct Plane<'a> {
+    /// The plane's bytes, from the chunk offset to the end of its data.
+    data: &'a [u8],
+    /// Bytes from the start of one row to the next.
+    stride: usize,
+}
+
+/// Map plane `index` of a buffer, or `None` if it is empty or unmapped.
+///
+/// # Safety
+///
+/// `datas` must point to more than `index` valid `spa_data` entries whose
+/// memory stays mapped for `'a`.
+unsafe fn mapped_plane<'a>(datas: *mut pw::spa::sys::spa_data, index: usize) -> Option<Plane<'a>> {
+    // Safety: guaranteed by the caller; Data is #[repr(transparent)].
+    let spa_data: &'a mut pw::spa::buffer::Data =
+        unsafe { &mut *datas.add(index).cast::<pw::spa::buffer::Data>() };
+
+    // Read chunk metadata before taking the mutable data borrow.
+    let chunk = spa_data.chunk();
+    // A negative stride is invalid and treated like a zero one.
+    let stride = usize::try_from(chunk.stride()).unwrap_or(0);
+    let offset = chunk.offset() as usize;
+    let size = chunk.size() as usize;
+    if size == 0 || stride == 0 {
+        return None;
+    }
+
+    let slice = spa_data.data()?;
+    let end = offset.saturating_add(size).min(slice.len());
+    if offset >= end {
+        tracing::warn!(
+            offset,
+            size,
+            slice_len = slice.len(),
+            "Buffer slice out of bounds"
+        );
+        return None;
+    }
+    Some(Plane {
+        data: &slice[offset..end],
+        stride,
+    })
+}
+
+/// Copy a packed RGB frame into a pooled BGRA buffer with the same stride.
+///
+/// R and B are swapped on the way if the source is in RGB order (or
+/// `swap_colors` says the compositor mislabels it), 10-bit channels are
+/// cut to 8 bits, and alpha is forced opaque as the 'x' byte of `BGRx` is
+/// undefined.
+fn copy_rgb(
+    plane: &Plane<'_>,
+    width: u32,
+    height: u32,
+    layout: SourceLayout,
+    swap_colors: bool,
+    pool: &BufferPool,
+) -> Option<(PooledBuffer, usize)> {
+    // Both depths are 4 bytes per pixel; rows may be padded beyond that,
+    // so only the stride says where each row starts.
+    let stride = plane.stride;
+    let row_len = width as usize * 4;
+    let needed = frame_len(stride, row_len, height as usize)?;
+    if plane.data.len() < needed {
+        tracing::warn!(
+            width,
+            height,
+            stride,
+            size = plane.data.len(),
+            "Buffer too small for the negotiated format, skipping"
+        );
+        return None;
+    }
+
+    let full_len = stride * height as usize;
+    let src = &plane.data[..plane.data.len().min(full_len)];
+    let mut buffer = pool.take(full_len);
+    match layout {
+        SourceLayout::Rgb8 { rgb_order } => {
+            copy_pixels(src, &mut buffer, stride, row_len, rgb_order ^ swap_colors);
+        }
+        SourceLayout::Rgb10 { rgb_order } => {
+            copy_pixels_10bit(src, &mut buffer, stride, row_len, rgb_order ^ swap_colors);
+        }
+        SourceLayout::Nv12 => unreachable!("NV12 is not packed RGB"),
+    }
+    Some((buffer, stride))
+}
+
+/// Copy an NV12 frame into a pooled buffer: the Y plane followed by the
+/// UV plane, both with the same stride.
+///
+/// Without a separate `chroma` plane, the UV plane is expected right after
+/// the Y plane in `luma`.
+fn copy_nv12(
+    luma: &Plane<'_>,
+    chroma: Option<Plane<'_>>,
+    width: u32,
+    height: u32,
+    pool: &BufferPool,
+) -> Option<(PooledBuffer, usize)> {
+    let (luma_rows, chroma_rows) = (height as usize, height.div_ceil(2) as usize);
+    let (luma_row_len, chroma_row_len) = (width as usize, width.next_multiple_of(2) as usize);
+    let chroma = chroma.unwrap_or_else(|| Plane {
+        data: luma.data.get(luma.stride * luma_rows..).unwrap_or_default(),
+        stride: luma.stride,
+    });
+
+    let luma_len = frame_len(luma.stride, luma_row_len, luma_rows)?;
+    let chroma_len = frame_len(chroma.stride, chroma_row_len, chroma_rows)?;
+    if luma.data.len() < luma_len || chroma.data.len() < chroma_len {
+        tracing::warn!(
+            width,
+            height,
+            luma_stride = luma.stride,
+            chroma_stride = chroma.stride,
+            "NV12 buffer too small for the negotiated format, skipping"
+        );
+        return None;
+    }
+
+    let stride = luma.stride.max(chroma_row_len);
+    let mut buffer = pool.take(stride * (luma_rows + chroma_rows));
+    let (y, uv) = buffer.split_at_mut(stride * luma_rows);
+    copy_rows(luma.data, luma.stride, y, stride, luma_row_len);
+    copy_rows(chroma.data, chroma.stride, uv, stride, chroma_row_len);
+    Some((buffer, stride))
+}
+
+/// Copy rows of `row_len` bytes from `src` to `dst`, spaced `src_stride`
+/// and `dst_stride` bytes apart.
+fn copy_rows(src: &[u8], src_stride: usize, dst: &mut [u8], dst_stride: usize, row_len: usize) {
+    for (src_row, dst_row) in src.chunks(src_stride).zip(dst.chunks_mut(dst_stride)) {
+        let len = row_len.min(src_row.len()).min(dst_row.len());
+        dst_row[..len].copy_from_slice(&src_row[..len]);
+    }
+}
+
+/// Copy whole rows of 32-bit pixels from `src` to `dst`, both `stride`
+/// bytes apart, swapping the first and third byte of each pixel if
+/// `swap_rb` and setting the fourth to 0xFF. Row padding is not copied.
+fn copy_pixels(src: &[u8], dst: &mut [u8], stride: usize, row_len: usize, swap_rb: bool) {
+    for (src_row, dst_row) in src.chunks(stride).zip(dst.chunks_mut(stride)) {
+        let len = row_len.min(src_row.len()).min(dst_row.len());
+        let pixels = src_row[..len]
+            .chunks_exact(4)
+            .zip(dst_row[..len].chunks_exact_mut(4));
+        if swap_rb {
+            // R,G,B,A -> B,G,R,A  (or force swap when override set)
+            for (s, d) in pixels {
+                d.copy_from_slice(&[s[2], s[1], s[0], 0xFF]);
+            }
+        } else {
+            for (s, d) in pixels {
+                d.copy_from_slice(&[s[0], s[1], s[2], 0xFF]);
+            }
+        }
+    }
+}
+
+/// Like [`copy_pixels`], for 10-bit channels packed into little-endian
+/// 32-bit words, lowest channel first. Each channel keeps its top 8 bits.
+fn copy_pixels_10bit(src: &[u8], dst: &mut [u8], stride: usize, row_len: usize, swap_rb: bool) {
+    for (src_row, dst_row) in src.chunks(stride).zip(dst.chunks_mut(stride)) {
+        let len = row_len.min(src_row.len()).min(dst_row.len());
+        let pixels = src_row[..len]
+            .chunks_exact(4)
+            .zip(dst_row[..len].chunks_exact_mut(4));
+        for (s, d) in pixels {
+            let word = u32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+            #[allow(clippy::cast_possible_truncation)] // masked to 8 bits
+            let [low, mid, high] = [word >> 2, word >> 12, word >> 22].map(|c| (c & 0xFF) as u8);
+            if swap_rb {
+                d.copy_from_slice(&[high, mid, low, 0xFF]);
+            } else {
+                d.copy_from_slice(&[low, mid, high, 0xFF]);
+            }
+        }
+    }
+}
+
+/// Bytes needed for `height` rows of `row_len` bytes spaced `stride` apart
+/// (the last row's padding may be missing), or `None` if the geometry is
+/// unusable.
+fn frame_len(stride: usize, row_len: usize, height: usize) -> Option<usize> {
+    if row_len == 0 || height == 0 || stride < row_len {
+        return None;
+    }
+    Some(stride * (height - 1) + row_len)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PwError {
     #[error("failed to create PipeWire MainLoop")]
@@ -382,3 +890,96 @@ pub enum PwError {
     #[error("failed to spawn PipeWire thread")]
     SpawnThread(#[source] std::io::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_len_allows_padded_rows() {
+        // 1366 pixels wide, rows padded to 5504 bytes.
+        assert_eq!(frame_len(5504, 1366 * 4, 768), Some(5504 * 767 + 1366 * 4));
+        assert_eq!(frame_len(7680, 1920 * 4, 1080), Some(7680 * 1080));
+    }
+
+    #[test]
+    fn copy_swaps_and_fills_alpha_but_skips_padding() {
+        // 1x2 frame with rows padded to 8 bytes; the last row's padding
+        // is missing from the source.
+        let src = [1, 2, 3, 0, 9, 9, 9, 9, 4, 5, 6, 0];
+        let mut dst = [0xEE; 16];
+        copy_pixels(&src, &mut dst, 8, 4, false);
+        assert_eq!(
+            dst,
+            [1, 2, 3, 0xFF, 0xEE, 0xEE, 0xEE, 0xEE, 4, 5, 6, 0xFF, 0xEE, 0xEE, 0xEE, 0xEE]
+        );
+        copy_pixels(&src, &mut dst, 8, 4, true);
+        assert_eq!(dst[..4], [3, 2, 1, 0xFF]);
+        assert_eq!(dst[8..12], [6, 5, 4, 0xFF]);
+    }
+
+    #[test]
+    fn copy_10bit_keeps_top_bits() {
+        // B = 0x3FF, G = 0x200, R = 0x004 in xRGB2101010.
+        let word: u32 = 0x3FF | (0x200 << 10) | (0x004 << 20) | (0b11 << 30);
+        let src = word.to_le_bytes();
+        let mut dst = [0; 4];
+        copy_pixels_10bit(&src, &mut dst, 4, 4, false);
+        assert_eq!(dst, [0xFF, 0x80, 0x01, 0xFF]);
+        copy_pixels_10bit(&src, &mut dst, 4, 4, true);
+        assert_eq!(dst, [0x01, 0x80, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn nv12_planes_are_packed_with_one_stride() {
+        let pool = BufferPool::new(0);
+        // 3x3 frame in one plane: luma rows padded to 4 bytes, followed
+        // by two chroma rows of 4 bytes.
+        let single = [
+            1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0, 10, 11, 12, 13, 14, 15, 16, 17,
+        ];
+        let luma = Plane {
+            data: &single,
+            stride: 4,
+        };
+        let (buffer, stride) = copy_nv12(&luma, None, 3, 3, &pool).unwrap();
+        assert_eq!(stride, 4);
+        assert_eq!(&buffer[..], &single[..]);
+
+        // The same chroma in its own plane with wider rows.
+        let chroma = Plane {
+            data: &[10, 11, 12, 13, 0, 0, 14, 15, 16, 17],
+            stride: 6,
+        };
+        let (buffer, stride) = copy_nv12(&luma, Some(chroma), 3, 3, &pool).unwrap();
+        assert_eq!(stride, 4);
+        assert_eq!(&buffer[12..], &single[12..]);
+
+        // Missing chroma rows.
+        let short = Plane {
+            data: &single[..16],
+            stride: 4,
+        };
+        assert!(copy_nv12(&short, None, 3, 3, &pool).is_none());
+    }
+
+    #[test]
+    fn frame_len_rejects_bad_geometry() {
+        assert_eq!(frame_len(4000, 1920 * 4, 1080), None);
+        assert_eq!(frame_len(7680, 0, 1080), None);
+        assert_eq!(frame_len(7680, 1920 * 4, 0), None);
+    }
+
+    #[test]
+    fn requested_sizes_round_trip() {
+        assert_eq!(unpack_size(pack_size(None)), None);
+        assert_eq!(
+            unpack_size(pack_size(Some((2560, 1440)))),
+            Some((2560, 1440))
+        );
+        assert_eq!(
+            unpack_size(pack_size(Some((1, u32::MAX)))),
+            Some((1, u32::MAX))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Recycled frame buffers.
+//!
+//! A 4K frame is 32 MiB and may arrive 60 times a second; allocating each
+//! one afresh costs a page fault per 4 KiB on every frame. Frames are
+//! instead copied into buffers from a [`BufferPool`] and frozen into
+//! [`Bytes`], which the bitmap path, the encoder and the repaint cache
+//! share without copying. The buffer goes back to the pool once the last
+//! of them lets go.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use bytes::Bytes;
+
+/// A pool of reusable byte buffers.
+///
+/// Cloning the pool shares it.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    idle: Mutex<Vec<Vec<u8>>>,
+    /// Buffers beyond this many idle ones are freed.
+    max_idle: usize,
+}
+
+impl BufferPool {
+    /// Create a pool that keeps up to `max_idle` unused buffers.
+    #[must_use]
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                idle: Mutex::new(Vec::with_capacity(max_idle)),
+                max_idle,
+            }),
+        }
+    }
+
+    /// A buffer of `len` bytes.
+    ///
+    /// Recycled buffers keep their previous contents, so the caller must
+    /// overwrite every byte it reads back.
+    #[must_use]
+    pub fn take(&self, len: usize) -> PooledBuffer {
+        let recycled = self
+            .shared
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let mut data = recycled.unwrap_or_default();
+        data.resize(len, 0);
+        PooledBuffer {
+            data,
+            pool: Arc::downgrade(&self.shared),
+        }
+    }
+
+    /// Number of buffers waiting to be reused.
+    #[must_use]
+    pub fn idle(&self) -> usize {
+        self.shared
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// A buffer on loan from a [`BufferPool`], returned to it on drop.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    data: Vec<u8>,
+    pool: Weak<Shared>,
+}
+
+impl PooledBuffer {
+    /// Share the buffer read-only.
+    ///
+    /// It goes back to the pool when the last clone of the `Bytes` is
+    /// dropped.
+    #[must_use]
+    pub fn freeze(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let Some(shared) = self.pool.upgrade() else {
+            return;
+        };
+        let mut idle = shared.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < shared.max_idle {
+            idle.push(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_return_when_last_clone_drops() {
+        let pool = BufferPool::new(2);
+        let mut buffer = pool.take(16);
+        buffer[0] = 7;
+        let frame = buffer.freeze();
+        let shared = frame.clone();
+        drop(frame);
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(shared[0], 7);
+        drop(shared);
+        assert_eq!(pool.idle(), 1);
+
+        // The recycled buffer is reused, resized to the new length.
+        let buffer = pool.take(8);
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(buffer[0], 7);
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn excess_buffers_are_freed() {
+        let pool = BufferPool::new(1);
+        let (a, b) = (pool.take(4), pool.take(4));
+        drop(a);
+        drop(b);
+        assert_eq!(pool.idle(), 1);
+
+        // Buffers outliving their pool are simply freed.
+        let orphan = pool.take(4);
+        drop(pool);
+        drop(orphan);
+    }
+}
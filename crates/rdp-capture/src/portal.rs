@@ -3,6 +3,26 @@ use std::os::fd::OwnedFd;
 use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType, Stream as ScreencastStream};
 use ashpd::desktop::PersistMode;
 
+/// What the portal dialog offers for capture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureSource {
+    /// Whole monitors.
+    #[default]
+    Monitor,
+    /// A new virtual monitor, extending the desktop, created at the given
+    /// size.
+    Virtual { width: u16, height: u16 },
+}
+
+impl CaptureSource {
+    fn source_type(self) -> SourceType {
+        match self {
+            Self::Monitor => SourceType::Monitor,
+            Self::Virtual { .. } => SourceType::Virtual,
+        }
+    }
+}
+
 /// Information about a captured screen stream.
 #[derive(Debug, Clone)]
 pub struct PortalStream {
@@ -12,6 +32,8 @@ pub struct PortalStream {
     pub width: Option<i32>,
     /// Stream height reported by the portal (compositor logical coordinates).
     pub height: Option<i32>,
+    /// Position of the monitor in the compositor's layout, if reported.
+    pub position: Option<(i32, i32)>,
 }
 
 impl From<&ScreencastStream> for PortalStream {
@@ -21,6 +43,7 @@ impl From<&ScreencastStream> for PortalStream {
             node_id: stream.pipe_wire_node_id(),
             width,
             height,
+            position: stream.position(),
         }
     }
 }
@@ -48,15 +71,18 @@ pub struct PortalSession {
 /// `CursorMode::Embedded` if metadata mode is not supported.
 ///
 /// When `multiple` is true, the portal allows the user to select multiple
-/// monitor sources for multi-monitor capture.
+/// sources for multi-monitor capture. `source` picks whether existing
+/// monitors are offered or a virtual one is created.
 ///
 /// # Errors
 ///
-/// Returns `PortalError` if the portal session cannot be created or started.
+/// Returns `PortalError` if the portal session cannot be created or started,
+/// [`PortalError::Cancelled`] if the user dismissed the dialog.
 pub async fn start_screencast(
     restore_token: Option<&str>,
     cursor_metadata: bool,
     multiple: bool,
+    source: CaptureSource,
 ) -> Result<PortalSession, PortalError> {
     let proxy = Screencast::new().await.map_err(PortalError::Create)?;
 
@@ -86,7 +112,7 @@ pub async fn start_screencast(
         .select_sources(
             &session,
             cursor_mode,
-            SourceType::Monitor.into(),
+            source.source_type().into(),
             multiple,
             restore_token,
             PersistMode::ExplicitlyRevoked,
@@ -99,7 +125,12 @@ pub async fn start_screencast(
         .await
         .map_err(PortalError::Start)?
         .response()
-        .map_err(PortalError::Response)?;
+        .map_err(|e| match e {
+            ashpd::Error::Response(ashpd::desktop::ResponseError::Cancelled) => {
+                PortalError::Cancelled
+            }
+            e => PortalError::Response(e),
+        })?;
 
     let streams: Vec<PortalStream> = response
         .streams()
@@ -123,6 +154,7 @@ pub async fn start_screencast(
         width = ?streams[0].width,
         height = ?streams[0].height,
         streams = streams.len(),
+        ?source,
         "ScreenCast portal session started"
     );
 
@@ -149,7 +181,10 @@ pub enum PortalError {
     #[error("failed to start session")]
     Start(#[source] ashpd::Error),
 
-    #[error("user cancelled or portal response failed")]
+    #[error("user cancelled the screen sharing dialog")]
+    Cancelled,
+
+    #[error("portal response failed")]
     Response(#[source] ashpd::Error),
 
     #[error("no streams returned by portal")]
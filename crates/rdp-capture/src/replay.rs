@@ -0,0 +1,664 @@
+//! Replay frame source.
+//!
+//! Plays back recorded frames without a compositor, looping at the end:
+//! either a directory of PNG files, shown in file name order, or a
+//! YUV4MPEG2 (`.y4m`) file with 8-bit 4:2:0 frames, as written by
+//! `ffmpeg -pix_fmt yuv420p` or `GStreamer`'s `y4menc`.
+//!
+//! PNG frames are sent as BGRA and Y4M frames as NV12, each with damage
+//! around the pixels that changed since the previous frame. Frames are
+//! decoded on a dedicated thread, one ahead of playback.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use crate::source::headless_desktop;
+use crate::{
+    monotonic_ns, BufferPool, CaptureEvent, CapturedFrame, DamageRect, DesktopInfo, FrameSource,
+    PixelFormat,
+};
+
+/// Plays back a PNG sequence or a Y4M file.
+#[derive(Debug)]
+pub struct ReplaySource {
+    desktop: DesktopInfo,
+    events: mpsc::Receiver<CaptureEvent>,
+}
+
+impl ReplaySource {
+    /// Open `path`, a directory of PNG files or a Y4M file, and start
+    /// playing it back. PNG sequences play at `fps` frames per second,
+    /// Y4M files at their own frame rate.
+    ///
+    /// Up to `channel_capacity` frames are decoded ahead; playback pauses
+    /// while nobody takes them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ReplayError` if `path` cannot be read, holds no frames or
+    /// its first frame cannot be decoded.
+    pub fn open(path: &Path, fps: u32, channel_capacity: usize) -> Result<Self, ReplayError> {
+        let mut reader = Reader::open(path, fps)?;
+        let pool = BufferPool::new(channel_capacity + 4);
+        // Decoded up front, so a broken recording fails here and the
+        // desktop size is known.
+        let first = reader.next_picture(&pool)?;
+        let desktop = headless_desktop(
+            u16::try_from(first.width).unwrap_or(u16::MAX),
+            u16::try_from(first.height).unwrap_or(u16::MAX),
+        );
+
+        let (tx, events) = mpsc::channel(channel_capacity.max(1));
+        std::thread::Builder::new()
+            .name("replay".into())
+            .spawn(move || play(reader, first, &pool, &tx))
+            .map_err(ReplayError::SpawnThread)?;
+
+        tracing::info!(
+            path = %path.display(),
+            width = desktop.width,
+            height = desktop.height,
+            "Replaying recorded frames"
+        );
+        Ok(Self { desktop, events })
+    }
+}
+
+#[async_trait::async_trait]
+impl FrameSource for ReplaySource {
+    fn desktop(&self) -> &DesktopInfo {
+        &self.desktop
+    }
+
+    async fn next_event(&mut self) -> Option<CaptureEvent> {
+        self.events.recv().await
+    }
+}
+
+/// Send frames to `tx` at the recording's frame rate until the receiver
+/// is dropped or a frame cannot be decoded.
+fn play(mut reader: Reader, first: Picture, pool: &BufferPool, tx: &mpsc::Sender<CaptureEvent>) {
+    let interval = reader.frame_interval();
+    let mut due = Instant::now();
+    let mut previous: Option<Picture> = None;
+    let mut picture = first;
+    for sequence in 0.. {
+        let damage = previous
+            .as_ref()
+            .and_then(|previous| damage(previous, &picture));
+        // After a pause (no consumer), carry on from now rather than
+        // catching up.
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        } else {
+            due = now;
+        }
+        due += interval;
+
+        let frame = CapturedFrame {
+            data: picture.data.clone(),
+            width: picture.width,
+            height: picture.height,
+            format: picture.format,
+            stride: picture.stride,
+            sequence,
+            pts_ns: monotonic_ns(),
+            damage,
+        };
+        if tx.blocking_send(CaptureEvent::Frame(frame)).is_err() {
+            tracing::debug!("Replay stopped, frames no longer wanted");
+            return;
+        }
+
+        previous = Some(picture);
+        picture = match reader.next_picture(pool) {
+            Ok(picture) => picture,
+            Err(e) => {
+                tracing::error!("Replay stopped: {e}");
+                return;
+            }
+        };
+    }
+}
+
+/// A decoded frame.
+#[derive(Debug, Clone)]
+struct Picture {
+    data: Bytes,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    stride: u32,
+}
+
+/// Reads the frames of a recording, starting over at the end.
+enum Reader {
+    Png {
+        files: Vec<PathBuf>,
+        next: usize,
+        interval: Duration,
+    },
+    Y4m(Y4mReader<BufReader<File>>),
+}
+
+impl Reader {
+    fn open(path: &Path, fps: u32) -> Result<Self, ReplayError> {
+        let interval = Duration::from_secs(1) / fps.max(1);
+        let read_error = |source| ReplayError::Read {
+            path: path.to_owned(),
+            source,
+        };
+
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(read_error)?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|file| {
+                    file.extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+                })
+                .collect();
+            if files.is_empty() {
+                return Err(ReplayError::NoFrames(path.to_owned()));
+            }
+            files.sort();
+            return Ok(Self::Png {
+                files,
+                next: 0,
+                interval,
+            });
+        }
+
+        let file = File::open(path).map_err(read_error)?;
+        Y4mReader::new(BufReader::new(file), path, interval).map(Self::Y4m)
+    }
+
+    fn frame_interval(&self) -> Duration {
+        match self {
+            Self::Png { interval, .. } => *interval,
+            Self::Y4m(reader) => reader.header.interval,
+        }
+    }
+
+    fn next_picture(&mut self, pool: &BufferPool) -> Result<Picture, ReplayError> {
+        match self {
+            Self::Png { files, next, .. } => {
+                let picture = decode_png(&files[*next], pool)?;
+                *next = (*next + 1) % files.len();
+                Ok(picture)
+            }
+            Self::Y4m(reader) => reader.next_picture(pool),
+        }
+    }
+}
+
+/// Decode a PNG file into a BGRA picture.
+fn decode_png(path: &Path, pool: &BufferPool) -> Result<Picture, ReplayError> {
+    let png_error = |source: png::DecodingError| ReplayError::Png {
+        path: path.to_owned(),
+        source,
+    };
+    let file = File::open(path).map_err(|source| ReplayError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // Palettes, low bit depths and 16-bit channels all become 8-bit.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(png_error)?;
+    check_size(info.width, info.height)?;
+
+    let stride = info.width * 4;
+    let mut buffer = pool.take(stride as usize * info.height as usize);
+    to_bgra(
+        &pixels[..info.buffer_size()],
+        info.line_size,
+        info.color_type.samples(),
+        &mut buffer,
+        stride as usize,
+    );
+    Ok(Picture {
+        data: buffer.freeze(),
+        width: info.width,
+        height: info.height,
+        format: PixelFormat::Bgra,
+        stride,
+    })
+}
+
+/// Convert rows of 8-bit grey, grey and alpha, RGB or RGBA pixels
+/// (`samples` channels) to opaque BGRA.
+fn to_bgra(src: &[u8], src_stride: usize, samples: usize, dst: &mut [u8], dst_stride: usize) {
+    for (src_row, dst_row) in src.chunks(src_stride).zip(dst.chunks_mut(dst_stride)) {
+        for (s, d) in src_row
+            .chunks_exact(samples)
+            .zip(dst_row.chunks_exact_mut(4))
+        {
+            let pixel = if samples < 3 {
+                [s[0], s[0], s[0], 0xFF]
+            } else {
+                [s[2], s[1], s[0], 0xFF]
+            };
+            d.copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Stream parameters of a Y4M file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Y4mHeader {
+    width: u32,
+    height: u32,
+    interval: Duration,
+}
+
+impl Y4mHeader {
+    /// Parse a `YUV4MPEG2` stream header line. Files without a frame rate
+    /// play at `default_interval`.
+    fn parse(line: &str, default_interval: Duration) -> Result<Self, ReplayError> {
+        let mut params = line.trim_end().split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(ReplayError::Y4mHeader("missing YUV4MPEG2 signature".into()));
+        }
+
+        let (mut width, mut height, mut interval) = (None, None, default_interval);
+        for param in params {
+            let Some(tag) = param.chars().next() else {
+                continue;
+            };
+            let value = &param[tag.len_utf8()..];
+            match tag {
+                'W' => width = value.parse::<u32>().ok(),
+                'H' => height = value.parse::<u32>().ok(),
+                'F' => {
+                    interval = frame_interval(value).ok_or_else(|| {
+                        ReplayError::Y4mHeader(format!("invalid frame rate {value}"))
+                    })?;
+                }
+                'C' if !matches!(value, "420" | "420jpeg" | "420paldv" | "420mpeg2") => {
+                    return Err(ReplayError::Y4mColorSpace(value.into()));
+                }
+                // Interlacing, aspect ratio and extensions don't matter
+                // for playback.
+                _ => {}
+            }
+        }
+
+        let (Some(width), Some(height)) = (width, height) else {
+            return Err(ReplayError::Y4mHeader("missing frame size".into()));
+        };
+        check_size(width, height)?;
+        Ok(Self {
+            width,
+            height,
+            interval,
+        })
+    }
+}
+
+/// Time between frames at a Y4M frame rate (`num:den` frames a second).
+fn frame_interval(rate: &str) -> Option<Duration> {
+    let (num, den) = rate.split_once(':')?;
+    let (num, den) = (num.parse::<u64>().ok()?, den.parse::<u64>().ok()?);
+    (num > 0 && den > 0).then(|| Duration::from_nanos(1_000_000_000 * den / num))
+}
+
+/// Reads 4:2:0 frames from a Y4M stream and converts them to NV12.
+struct Y4mReader<R> {
+    input: R,
+    path: PathBuf,
+    header: Y4mHeader,
+    /// Offset of the first frame, to loop back to.
+    data_start: u64,
+}
+
+impl<R: BufRead + Seek> Y4mReader<R> {
+    /// Read the stream header of `input`, read from `path`.
+    fn new(mut input: R, path: &Path, default_interval: Duration) -> Result<Self, ReplayError> {
+        let mut line = Vec::new();
+        input
+            .read_until(b'\n', &mut line)
+            .map_err(|source| ReplayError::Read {
+                path: path.to_owned(),
+                source,
+            })?;
+        let header = Y4mHeader::parse(&String::from_utf8_lossy(&line), default_interval)?;
+        Ok(Self {
+            input,
+            path: path.to_owned(),
+            header,
+            data_start: line.len() as u64,
+        })
+    }
+
+    /// Read the next frame, starting over after the last one.
+    fn next_picture(&mut self, pool: &BufferPool) -> Result<Picture, ReplayError> {
+        let mut line = Vec::new();
+        if self.read_line(&mut line)? == 0 {
+            self.input
+                .seek(SeekFrom::Start(self.data_start))
+                .map_err(|source| self.read_error(source))?;
+            if self.read_line(&mut line)? == 0 {
+                return Err(ReplayError::NoFrames(self.path.clone()));
+            }
+        }
+        if !line.starts_with(b"FRAME") {
+            return Err(ReplayError::Y4mFrame(self.path.clone()));
+        }
+
+        let Y4mHeader { width, height, .. } = self.header;
+        let (luma_len, luma_rows) = (width as usize, height as usize);
+        let (chroma_width, chroma_rows) = (width.div_ceil(2) as usize, height.div_ceil(2) as usize);
+        let stride = width.next_multiple_of(2) as usize;
+
+        let mut buffer = pool.take(stride * (luma_rows + chroma_rows));
+        let (y, uv) = buffer.split_at_mut(stride * luma_rows);
+        let mut chroma = vec![0; 2 * chroma_width * chroma_rows];
+        let truncated = |_| ReplayError::Y4mFrame(self.path.clone());
+        for row in y.chunks_exact_mut(stride) {
+            self.input
+                .read_exact(&mut row[..luma_len])
+                .map_err(truncated)?;
+        }
+        self.input.read_exact(&mut chroma).map_err(truncated)?;
+        let (u, v) = chroma.split_at(chroma_width * chroma_rows);
+        interleave_chroma(u, v, uv, chroma_width, stride);
+
+        Ok(Picture {
+            data: buffer.freeze(),
+            width,
+            height,
+            format: PixelFormat::Nv12,
+            stride: u32::try_from(stride).unwrap_or(u32::MAX),
+        })
+    }
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    /// Read a frame header line into `line`; returns 0 at the end.
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<usize, ReplayError> {
+        self.input
+            .read_until(b'\n', line)
+            .map_err(|source| self.read_error(source))
+    }
+
+    fn read_error(&self, source: std::io::Error) -> ReplayError {
+        ReplayError::Read {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+/// Interleave planar U and V rows of `width` samples into NV12 UV rows
+/// spaced `stride` bytes apart.
+fn interleave_chroma(u: &[u8], v: &[u8], uv: &mut [u8], width: usize, stride: usize) {
+    let rows = u.chunks_exact(width).zip(v.chunks_exact(width));
+    for ((u_row, v_row), uv_row) in rows.zip(uv.chunks_exact_mut(stride)) {
+        for ((&u, &v), pair) in u_row.iter().zip(v_row).zip(uv_row.chunks_exact_mut(2)) {
+            pair.copy_from_slice(&[u, v]);
+        }
+    }
+}
+
+/// Frames are served as a desktop, which RDP limits to 16-bit sizes.
+fn check_size(width: u32, height: u32) -> Result<(), ReplayError> {
+    if width == 0 || height == 0 || width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+        return Err(ReplayError::FrameSize { width, height });
+    }
+    Ok(())
+}
+
+/// Damage between two consecutive pictures: the box around the changed
+/// pixels, or `None` (full frame) if the size or format changed.
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)] // sizes fit in u16
+fn damage(previous: &Picture, next: &Picture) -> Option<Vec<DamageRect>> {
+    if (
+        previous.width,
+        previous.height,
+        previous.format,
+        previous.stride,
+    ) != (next.width, next.height, next.format, next.stride)
+    {
+        return None;
+    }
+    let (width, height) = (next.width as usize, next.height as usize);
+    let stride = next.stride as usize;
+    let bounds = match next.format {
+        PixelFormat::Bgra | PixelFormat::Rgba => {
+            changed_bounds(&previous.data, &next.data, stride, height, width * 4, 4)
+        }
+        PixelFormat::Nv12 => {
+            let luma = stride * height;
+            let luma_bounds = changed_bounds(&previous.data, &next.data, stride, height, width, 1);
+            // Each UV pair covers 2x2 luma pixels.
+            let chroma_bounds = changed_bounds(
+                &previous.data[luma..],
+                &next.data[luma..],
+                stride,
+                height.div_ceil(2),
+                width.next_multiple_of(2),
+                2,
+            )
+            .map(|[x0, y0, x1, y1]| [2 * x0, 2 * y0, (2 * x1).min(width), (2 * y1).min(height)]);
+            merge(luma_bounds, chroma_bounds)
+        }
+    };
+
+    Some(
+        bounds
+            .map(|[x0, y0, x1, y1]| {
+                DamageRect::new(x0 as i32, y0 as i32, (x1 - x0) as u32, (y1 - y0) as u32)
+            })
+            .into_iter()
+            .collect(),
+    )
+}
+
+/// Bounds (`[x0, y0, x1, y1]`, end exclusive, in pixels of `bpp` bytes) of
+/// what differs between two planes of `rows` rows of `row_len` bytes,
+/// spaced `stride` bytes apart.
+fn changed_bounds(
+    a: &[u8],
+    b: &[u8],
+    stride: usize,
+    rows: usize,
+    row_len: usize,
+    bpp: usize,
+) -> Option<[usize; 4]> {
+    let mut bounds = None;
+    for y in 0..rows {
+        let (row_a, row_b) = (
+            &a[y * stride..y * stride + row_len],
+            &b[y * stride..y * stride + row_len],
+        );
+        if row_a == row_b {
+            continue;
+        }
+        let differs = |(p, q): (&u8, &u8)| p != q;
+        let first = row_a.iter().zip(row_b).position(differs).unwrap_or(0) / bpp;
+        let last = row_a.iter().zip(row_b).rposition(differs).unwrap_or(0) / bpp + 1;
+        bounds = merge(bounds, Some([first, y, last, y + 1]));
+    }
+    bounds
+}
+
+/// Bounding box of two optional boxes.
+fn merge(a: Option<[usize; 4]>, b: Option<[usize; 4]>) -> Option<[usize; 4]> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some([
+            a[0].min(b[0]),
+            a[1].min(b[1]),
+            a[2].max(b[2]),
+            a[3].max(b[3]),
+        ]),
+        (a, b) => a.or(b),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("failed to read {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("failed to decode PNG {}", path.display())]
+    Png {
+        path: PathBuf,
+        #[source]
+        source: png::DecodingError,
+    },
+
+    #[error("no frames in {}", .0.display())]
+    NoFrames(PathBuf),
+
+    #[error("frame size {width}x{height} is not supported")]
+    FrameSize { width: u32, height: u32 },
+
+    #[error("invalid Y4M header: {0}")]
+    Y4mHeader(String),
+
+    #[error("unsupported Y4M colour space {0} (only 8-bit 4:2:0 is supported)")]
+    Y4mColorSpace(String),
+
+    #[error("truncated or invalid Y4M frame in {}", .0.display())]
+    Y4mFrame(PathBuf),
+
+    #[error("failed to spawn replay thread")]
+    SpawnThread(#[source] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn y4m_headers_are_parsed() {
+        let header = Y4mHeader::parse("YUV4MPEG2 W640 H360 F30000:1001 Ip A1:1 C420jpeg\n", SECOND)
+            .expect("valid header");
+        assert_eq!((header.width, header.height), (640, 360));
+        assert_eq!(header.interval, Duration::from_nanos(33_366_666));
+
+        let header = Y4mHeader::parse("YUV4MPEG2 W3 H2", SECOND).expect("valid header");
+        assert_eq!(header.interval, SECOND);
+
+        assert!(Y4mHeader::parse("YUV4MPEG W3 H2", SECOND).is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W3", SECOND).is_err());
+        assert!(Y4mHeader::parse("YUV4MPEG2 W3 H2 F30:0", SECOND).is_err());
+        assert!(matches!(
+            Y4mHeader::parse("YUV4MPEG2 W3 H2 C444", SECOND),
+            Err(ReplayError::Y4mColorSpace(_))
+        ));
+    }
+
+    #[test]
+    fn y4m_frames_become_nv12_and_loop() {
+        // 3x2 frames: 6 luma samples, then 2x1 U and V samples.
+        let mut stream = b"YUV4MPEG2 W3 H2 F25:1 C420\n".to_vec();
+        stream.extend_from_slice(b"FRAME\n");
+        stream.extend_from_slice(&[1, 2, 3, 4, 5, 6, 10, 11, 20, 21]);
+        stream.extend_from_slice(b"FRAME Ixyz\n");
+        stream.extend_from_slice(&[7, 8, 9, 10, 11, 12, 30, 31, 40, 41]);
+        let input = std::io::Cursor::new(stream);
+        let mut reader = Y4mReader::new(input, Path::new("test.y4m"), SECOND).expect("header");
+        let pool = BufferPool::new(0);
+
+        let first = reader.next_picture(&pool).expect("first frame");
+        assert_eq!((first.width, first.height, first.stride), (3, 2, 4));
+        assert_eq!(first.format, PixelFormat::Nv12);
+        assert_eq!(&first.data[..3], &[1, 2, 3]);
+        assert_eq!(&first.data[4..7], &[4, 5, 6]);
+        assert_eq!(&first.data[8..], &[10, 20, 11, 21]);
+
+        let second = reader.next_picture(&pool).expect("second frame");
+        assert_eq!(&second.data[8..], &[30, 40, 31, 41]);
+        let looped = reader.next_picture(&pool).expect("first frame again");
+        assert_eq!(looped.data, first.data);
+    }
+
+    #[test]
+    fn truncated_y4m_frames_are_rejected() {
+        let stream = b"YUV4MPEG2 W3 H2\nFRAME\n\x01\x02".to_vec();
+        let input = std::io::Cursor::new(stream);
+        let mut reader = Y4mReader::new(input, Path::new("test.y4m"), SECOND).expect("header");
+        assert!(matches!(
+            reader.next_picture(&BufferPool::new(0)),
+            Err(ReplayError::Y4mFrame(_))
+        ));
+
+        let input = std::io::Cursor::new(b"YUV4MPEG2 W3 H2\n".to_vec());
+        let mut reader = Y4mReader::new(input, Path::new("test.y4m"), SECOND).expect("header");
+        assert!(matches!(
+            reader.next_picture(&BufferPool::new(0)),
+            Err(ReplayError::NoFrames(_))
+        ));
+    }
+
+    #[test]
+    fn png_pixels_become_bgra() {
+        let mut dst = [0; 8];
+        to_bgra(&[1, 2, 3, 4, 5, 6], 6, 3, &mut dst, 8);
+        assert_eq!(dst, [3, 2, 1, 0xFF, 6, 5, 4, 0xFF]);
+        to_bgra(&[7, 0x80, 9, 0x80], 4, 2, &mut dst, 8);
+        assert_eq!(dst, [7, 7, 7, 0xFF, 9, 9, 9, 0xFF]);
+    }
+
+    fn picture(data: Vec<u8>, width: u32, height: u32, format: PixelFormat) -> Picture {
+        let stride = match format {
+            PixelFormat::Nv12 => width.next_multiple_of(2),
+            PixelFormat::Bgra | PixelFormat::Rgba => width * 4,
+        };
+        Picture {
+            data: Bytes::from(data),
+            width,
+            height,
+            format,
+            stride,
+        }
+    }
+
+    #[test]
+    fn damage_covers_changed_pixels() {
+        let blank = picture(vec![0; 4 * 4 * 4], 4, 4, PixelFormat::Bgra);
+        assert_eq!(damage(&blank, &blank), Some(vec![]));
+
+        let mut data = vec![0; 4 * 4 * 4];
+        data[(4 + 1) * 4] = 1; // (1, 1)
+        data[(2 * 4 + 2) * 4 + 3] = 1; // (2, 2)
+        let changed = picture(data, 4, 4, PixelFormat::Bgra);
+        assert_eq!(
+            damage(&blank, &changed),
+            Some(vec![DamageRect::new(1, 1, 2, 2)])
+        );
+
+        let smaller = picture(vec![0; 2 * 2 * 4], 2, 2, PixelFormat::Bgra);
+        assert_eq!(damage(&blank, &smaller), None);
+    }
+
+    #[test]
+    fn nv12_chroma_damage_covers_its_luma_block() {
+        // 4x4: 16 luma bytes, then 2 rows of 2 UV pairs.
+        let blank = picture(vec![0; 24], 4, 4, PixelFormat::Nv12);
+        let mut data = vec![0; 24];
+        data[16 + 4 + 3] = 1; // V of the bottom-right block
+        let changed = picture(data, 4, 4, PixelFormat::Nv12);
+        assert_eq!(
+            damage(&blank, &changed),
+            Some(vec![DamageRect::new(2, 2, 2, 2)])
+        );
+    }
+}
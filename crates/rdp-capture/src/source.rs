@@ -0,0 +1,78 @@
+//! Frame sources.
+//!
+//! The server takes its frames from a [`FrameSource`]: normally the screen
+//! capture started by [`start_capture`](crate::start_capture), wrapped in a
+//! [`PipeWireSource`]. Without a compositor, e.g. for demos, benchmarks or
+//! CI, a [`SyntheticSource`](crate::SyntheticSource) generates a moving
+//! test pattern and a [`ReplaySource`](crate::ReplaySource) plays back
+//! recorded frames.
+
+use tokio::sync::mpsc;
+
+use crate::{CaptureEvent, DesktopInfo, MonitorInfo};
+
+/// A source of captured frames and cursor updates.
+#[async_trait::async_trait]
+pub trait FrameSource: Send {
+    /// The desktop the frames cover when the source starts. Frames may
+    /// change size later on; consumers follow the frames.
+    fn desktop(&self) -> &DesktopInfo;
+
+    /// Wait for the next event; `None` once the source has ended.
+    ///
+    /// Must be cancel safe: consumers wait for it alongside other events
+    /// and drop the future if one of those comes first.
+    async fn next_event(&mut self) -> Option<CaptureEvent>;
+}
+
+/// Frames of a screen capture started with
+/// [`start_capture`](crate::start_capture).
+///
+/// The [`CaptureHandle`](crate::CaptureHandle) must be kept alive for as
+/// long as frames are wanted.
+#[derive(Debug)]
+pub struct PipeWireSource {
+    events: mpsc::Receiver<CaptureEvent>,
+    desktop: DesktopInfo,
+}
+
+impl PipeWireSource {
+    /// Wrap the event receiver and desktop info returned by
+    /// [`start_capture`](crate::start_capture).
+    #[must_use]
+    pub fn new(events: mpsc::Receiver<CaptureEvent>, desktop: DesktopInfo) -> Self {
+        Self { events, desktop }
+    }
+}
+
+#[async_trait::async_trait]
+impl FrameSource for PipeWireSource {
+    fn desktop(&self) -> &DesktopInfo {
+        &self.desktop
+    }
+
+    async fn next_event(&mut self) -> Option<CaptureEvent> {
+        self.events.recv().await
+    }
+}
+
+/// Desktop of a source without a compositor: one monitor of the given
+/// size at the origin.
+pub(crate) fn headless_desktop(width: u16, height: u16) -> DesktopInfo {
+    let monitor = MonitorInfo {
+        node_id: 0,
+        width,
+        height,
+        x: 0,
+        y: 0,
+    };
+    DesktopInfo {
+        width,
+        height,
+        node_id: 0,
+        monitors: vec![monitor.clone()],
+        sources: vec![monitor],
+        origin: Some((0, 0)),
+        restore_token: None,
+    }
+}
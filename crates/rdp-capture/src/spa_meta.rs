@@ -5,7 +5,8 @@
 //! metadata, so we access it through raw pointers to the underlying
 //! `spa_buffer` structure.
 //!
-//! Two metadata types are extracted:
+//! Three metadata types are extracted:
+//! - `SPA_META_Header` (type 1): presentation timestamp
 //! - `SPA_META_VideoDamage` (type 3): array of damage rectangles
 //! - `SPA_META_Cursor` (type 5): cursor position and optional bitmap
 
@@ -13,6 +14,43 @@ use pipewire::spa::sys as spa_sys;
 
 use crate::frame::{CursorBitmap, CursorInfo, DamageRect};
 
+/// Extract the presentation timestamp from a `PipeWire` buffer's
+/// `SPA_META_Header` metadata.
+///
+/// Returns the `pts` in `CLOCK_MONOTONIC` nanoseconds, or `None` if no
+/// header is present or the timestamp is unset (non-positive).
+///
+/// # Safety
+///
+/// The `spa_buffer` pointer must be valid for the duration of this call.
+/// This is guaranteed when called from within the `PipeWire` process callback
+/// while the buffer is dequeued.
+#[must_use]
+pub unsafe fn extract_pts(spa_buffer: *const spa_sys::spa_buffer) -> Option<u64> {
+    if spa_buffer.is_null() {
+        return None;
+    }
+
+    let buffer = &*spa_buffer;
+    if buffer.n_metas == 0 || buffer.metas.is_null() {
+        return None;
+    }
+
+    let metas = std::slice::from_raw_parts(buffer.metas, buffer.n_metas as usize);
+    let meta = metas
+        .iter()
+        .find(|meta| meta.type_ == spa_sys::SPA_META_Header)?;
+
+    if meta.data.is_null()
+        || (meta.size as usize) < std::mem::size_of::<spa_sys::spa_meta_header>()
+    {
+        return None;
+    }
+
+    let header = &*(meta.data.cast::<spa_sys::spa_meta_header>());
+    u64::try_from(header.pts).ok().filter(|&pts| pts > 0)
+}
+
 /// Extract damage rectangles from a `PipeWire` buffer's `SPA_META_VideoDamage` metadata.
 ///
 /// Returns `None` if no damage metadata is present (treat as full-frame damage).
@@ -135,7 +173,7 @@ pub unsafe fn extract_cursor(
             });
         }
 
-        let bitmap = extract_cursor_bitmap(meta.data, cursor);
+        let bitmap = extract_cursor_bitmap(meta.data, meta.size as usize, cursor);
 
         return Some(CursorInfo {
             x: cursor.position.x,
@@ -148,15 +186,27 @@ pub unsafe fn extract_cursor(
     None
 }
 
+/// Largest cursor bitmap accepted from the compositor, in pixels per side:
+/// the most an RDP large pointer can carry.
+///
+/// Cursor metadata is sized for this when buffers are negotiated; larger
+/// bitmaps (or ones that do not fit the metadata) are dropped.
+pub const MAX_CURSOR_SIZE: u32 = 384;
+
 /// Extract the cursor bitmap from a `spa_meta_cursor`, if present.
 ///
+/// The bitmap header and pixels must lie within the `meta_size` bytes of
+/// cursor metadata, and the bitmap must be at most [`MAX_CURSOR_SIZE`] on
+/// either side; otherwise `None` is returned and only the position is used.
+///
 /// # Safety
 ///
-/// `meta_data` must point to valid memory of at least
-/// `cursor.bitmap_offset + sizeof(spa_meta_bitmap)` bytes.
+/// `meta_data` must point to valid memory of at least `meta_size` bytes
+/// starting with `cursor`.
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 unsafe fn extract_cursor_bitmap(
     meta_data: *mut std::os::raw::c_void,
+    meta_size: usize,
     cursor: &spa_sys::spa_meta_cursor,
 ) -> Option<CursorBitmap> {
     // bitmap_offset == 0 means no bitmap data.
@@ -164,15 +214,18 @@ unsafe fn extract_cursor_bitmap(
         return None;
     }
 
+    let bitmap_offset = cursor.bitmap_offset as usize;
     let bitmap_size = std::mem::size_of::<spa_sys::spa_meta_bitmap>();
-    if (cursor.bitmap_offset as usize) < bitmap_size {
+    if bitmap_offset < std::mem::size_of::<spa_sys::spa_meta_cursor>()
+        || bitmap_offset + bitmap_size > meta_size
+    {
         return None;
     }
 
     #[allow(clippy::cast_ptr_alignment)] // SPA guarantees 4-byte aligned metadata
     let bitmap_ptr = meta_data
         .cast::<u8>()
-        .add(cursor.bitmap_offset as usize)
+        .add(bitmap_offset)
         .cast::<spa_sys::spa_meta_bitmap>();
     let bitmap = &*bitmap_ptr;
 
@@ -187,52 +240,94 @@ unsafe fn extract_cursor_bitmap(
     if width == 0 || height == 0 {
         return None;
     }
+    if width > MAX_CURSOR_SIZE || height > MAX_CURSOR_SIZE {
+        tracing::debug!(width, height, "Cursor bitmap too large, ignoring");
+        return None;
+    }
+
+    let Some(layout) = CursorLayout::from_spa(bitmap.format) else {
+        tracing::debug!(format = bitmap.format, "Unsupported cursor bitmap format");
+        return None;
+    };
 
-    // Validate format: we only handle ARGB8888.
-    if bitmap.format != spa_sys::SPA_VIDEO_FORMAT_ARGB {
+    let stride = bitmap.stride.unsigned_abs() as usize;
+    let pixels_offset = bitmap_offset + bitmap.offset as usize;
+    let len = stride * (height as usize - 1) + width as usize * 4;
+    if stride < width as usize * 4 || pixels_offset + len > meta_size {
         tracing::debug!(
-            format = bitmap.format,
-            "Unsupported cursor bitmap format (expected ARGB8888)"
+            width,
+            height,
+            stride,
+            meta_size,
+            "Cursor bitmap does not fit its metadata, ignoring"
         );
         return None;
     }
 
-    let stride = bitmap.stride.unsigned_abs() as usize;
-    let expected_size = stride * height as usize;
-
-    // Read pixel data from the bitmap's offset field.
-    let pixel_ptr = bitmap_ptr
-        .cast::<u8>()
-        .add(bitmap.offset as usize);
-    let pixel_data = std::slice::from_raw_parts(pixel_ptr, expected_size);
-
-    // Convert ARGB8888 (A,R,G,B on big-endian / B,G,R,A on little-endian)
-    // to RGBA. On little-endian (x86), the memory layout for ARGB is
-    // [B, G, R, A] and we need [R, G, B, A].
-    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
-    for row in 0..height as usize {
-        let row_start = row * stride;
-        for col in 0..width as usize {
-            let px = row_start + col * 4;
-            if px + 3 < pixel_data.len() {
-                // ARGB on LE: [B, G, R, A] -> RGBA: [R, G, B, A]
-                rgba.push(pixel_data[px + 2]); // R
-                rgba.push(pixel_data[px + 1]); // G
-                rgba.push(pixel_data[px]);     // B
-                rgba.push(pixel_data[px + 3]); // A
-            }
-        }
-    }
+    let pixel_data =
+        std::slice::from_raw_parts(bitmap_ptr.cast::<u8>().add(bitmap.offset as usize), len);
 
     Some(CursorBitmap {
         width,
         height,
-        hot_x: cursor.hotspot.x.max(0) as u32,
-        hot_y: cursor.hotspot.y.max(0) as u32,
-        data: rgba,
+        hot_x: (cursor.hotspot.x.max(0) as u32).min(width - 1),
+        hot_y: (cursor.hotspot.y.max(0) as u32).min(height - 1),
+        data: layout.to_rgba(pixel_data, stride, width as usize, height as usize),
     })
 }
 
+/// Byte positions of the channels of a 32-bit cursor bitmap format.
+///
+/// SPA video formats are named in memory byte order (`ARGB` is stored as
+/// A, R, G, B), like `GStreamer`'s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CursorLayout {
+    red: usize,
+    green: usize,
+    blue: usize,
+    /// `None` for formats with a padding byte, which are opaque.
+    alpha: Option<usize>,
+}
+
+impl CursorLayout {
+    fn from_spa(format: u32) -> Option<Self> {
+        let (red, green, blue, alpha) = match format {
+            spa_sys::SPA_VIDEO_FORMAT_ARGB => (1, 2, 3, Some(0)),
+            spa_sys::SPA_VIDEO_FORMAT_BGRA => (2, 1, 0, Some(3)),
+            spa_sys::SPA_VIDEO_FORMAT_RGBA => (0, 1, 2, Some(3)),
+            spa_sys::SPA_VIDEO_FORMAT_ABGR => (3, 2, 1, Some(0)),
+            spa_sys::SPA_VIDEO_FORMAT_xRGB => (1, 2, 3, None),
+            spa_sys::SPA_VIDEO_FORMAT_BGRx => (2, 1, 0, None),
+            spa_sys::SPA_VIDEO_FORMAT_RGBx => (0, 1, 2, None),
+            spa_sys::SPA_VIDEO_FORMAT_xBGR => (3, 2, 1, None),
+            _ => return None,
+        };
+        Some(Self {
+            red,
+            green,
+            blue,
+            alpha,
+        })
+    }
+
+    /// Convert `height` rows of `width` pixels, `stride` bytes apart, to
+    /// tightly packed RGBA.
+    fn to_rgba(self, src: &[u8], stride: usize, width: usize, height: usize) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for row in src.chunks(stride).take(height) {
+            for px in row[..width * 4].chunks_exact(4) {
+                rgba.extend_from_slice(&[
+                    px[self.red],
+                    px[self.green],
+                    px[self.blue],
+                    self.alpha.map_or(0xFF, |a| px[a]),
+                ]);
+            }
+        }
+        rgba
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -349,6 +444,60 @@ mod tests {
         assert!(result.is_none());
     }
 
+    fn header_buffer(header: &spa_sys::spa_meta_header) -> (spa_sys::spa_meta, spa_sys::spa_buffer) {
+        let meta = spa_sys::spa_meta {
+            type_: spa_sys::SPA_META_Header,
+            #[allow(clippy::cast_possible_truncation)]
+            size: std::mem::size_of::<spa_sys::spa_meta_header>() as u32,
+            data: std::ptr::from_ref(header).cast_mut().cast::<std::os::raw::c_void>(),
+        };
+        let buffer = spa_sys::spa_buffer {
+            n_metas: 1,
+            n_datas: 0,
+            metas: std::ptr::null_mut(),
+            datas: std::ptr::null_mut(),
+        };
+        (meta, buffer)
+    }
+
+    #[test]
+    fn test_extract_pts_from_header() {
+        let header = spa_sys::spa_meta_header {
+            flags: 0,
+            offset: 0,
+            pts: 1_234_567_890,
+            dts_offset: 0,
+            seq: 7,
+        };
+        let (mut meta, mut buffer) = header_buffer(&header);
+        buffer.metas = &raw mut meta;
+
+        let result = unsafe { extract_pts(&raw const buffer) };
+        assert_eq!(result, Some(1_234_567_890));
+    }
+
+    #[test]
+    fn test_extract_pts_unset() {
+        let header = spa_sys::spa_meta_header {
+            flags: 0,
+            offset: 0,
+            pts: -1,
+            dts_offset: 0,
+            seq: 0,
+        };
+        let (mut meta, mut buffer) = header_buffer(&header);
+        buffer.metas = &raw mut meta;
+
+        let result = unsafe { extract_pts(&raw const buffer) };
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_extract_pts_null_buffer() {
+        let result = unsafe { extract_pts(std::ptr::null()) };
+        assert!(result.is_none());
+    }
+
     #[test]
     fn test_extract_cursor_null_buffer() {
         let result = unsafe { extract_cursor(std::ptr::null()) };
@@ -434,4 +583,109 @@ mod tests {
         assert_eq!(info.y, 75);
         assert!(info.bitmap.is_none());
     }
+
+    /// Cursor metadata laid out as a compositor would: the cursor, then
+    /// the bitmap header, then a 2x2 bitmap with a 12-byte stride.
+    #[repr(C)]
+    struct CursorMeta {
+        cursor: spa_sys::spa_meta_cursor,
+        bitmap: spa_sys::spa_meta_bitmap,
+        pixels: [u8; 24],
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn cursor_meta(format: u32, width: u32) -> CursorMeta {
+        CursorMeta {
+            cursor: spa_sys::spa_meta_cursor {
+                id: 1,
+                flags: 0,
+                position: spa_sys::spa_point { x: 10, y: 20 },
+                hotspot: spa_sys::spa_point { x: 1, y: 5 },
+                bitmap_offset: std::mem::offset_of!(CursorMeta, bitmap) as u32,
+            },
+            bitmap: spa_sys::spa_meta_bitmap {
+                format,
+                size: spa_sys::spa_rectangle { width, height: 2 },
+                stride: 12,
+                offset: (std::mem::offset_of!(CursorMeta, pixels)
+                    - std::mem::offset_of!(CursorMeta, bitmap)) as u32,
+            },
+            // Two rows of two pixels, each followed by 4 padding bytes.
+            pixels: [
+                1, 2, 3, 4, 5, 6, 7, 8, 0xEE, 0xEE, 0xEE, 0xEE, //
+                9, 10, 11, 12, 13, 14, 15, 16, 0xEE, 0xEE, 0xEE, 0xEE,
+            ],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn extract_from(cursor: &CursorMeta, size: usize) -> Option<CursorInfo> {
+        let mut meta = spa_sys::spa_meta {
+            type_: spa_sys::SPA_META_Cursor,
+            size: size as u32,
+            data: std::ptr::from_ref(cursor).cast_mut().cast::<std::os::raw::c_void>(),
+        };
+        let buffer = spa_sys::spa_buffer {
+            n_metas: 1,
+            n_datas: 0,
+            metas: &raw mut meta,
+            datas: std::ptr::null_mut(),
+        };
+        unsafe { extract_cursor(&raw const buffer) }
+    }
+
+    #[test]
+    fn test_extract_cursor_bitmap_bgra() {
+        let meta = cursor_meta(spa_sys::SPA_VIDEO_FORMAT_BGRA, 2);
+        let info = extract_from(&meta, std::mem::size_of::<CursorMeta>()).unwrap();
+        let bitmap = info.bitmap.unwrap();
+        assert!(bitmap.is_valid());
+        assert_eq!((bitmap.width, bitmap.height), (2, 2));
+        // Hotspot clamped into the bitmap.
+        assert_eq!((bitmap.hot_x, bitmap.hot_y), (1, 1));
+        assert_eq!(
+            bitmap.data,
+            [3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]
+        );
+    }
+
+    #[test]
+    fn test_extract_cursor_bitmap_byte_order() {
+        let first_pixel = |format| {
+            let meta = cursor_meta(format, 2);
+            let info = extract_from(&meta, std::mem::size_of::<CursorMeta>()).unwrap();
+            info.bitmap.unwrap().data[..4].to_vec()
+        };
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_ARGB), [2, 3, 4, 1]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_RGBA), [1, 2, 3, 4]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_ABGR), [4, 3, 2, 1]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_xRGB), [2, 3, 4, 0xFF]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_BGRx), [3, 2, 1, 0xFF]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_RGBx), [1, 2, 3, 0xFF]);
+        assert_eq!(first_pixel(spa_sys::SPA_VIDEO_FORMAT_xBGR), [4, 3, 2, 0xFF]);
+    }
+
+    #[test]
+    fn test_extract_cursor_bitmap_rejected() {
+        let size = std::mem::size_of::<CursorMeta>();
+
+        // Unsupported format: position only.
+        let meta = cursor_meta(spa_sys::SPA_VIDEO_FORMAT_RGB, 2);
+        let info = extract_from(&meta, size).unwrap();
+        assert!(info.visible);
+        assert!(info.bitmap.is_none());
+
+        // Stride too small for the width.
+        let meta = cursor_meta(spa_sys::SPA_VIDEO_FORMAT_BGRA, 4);
+        assert!(extract_from(&meta, size).unwrap().bitmap.is_none());
+
+        // Pixels past the end of the metadata.
+        let meta = cursor_meta(spa_sys::SPA_VIDEO_FORMAT_BGRA, 2);
+        assert!(extract_from(&meta, size - 8).unwrap().bitmap.is_none());
+
+        // Larger than the cursor size limit.
+        let mut meta = cursor_meta(spa_sys::SPA_VIDEO_FORMAT_BGRA, 2);
+        meta.bitmap.size.height = MAX_CURSOR_SIZE + 1;
+        assert!(extract_from(&meta, size).unwrap().bitmap.is_none());
+    }
 }
@@ -0,0 +1,282 @@
+//! Synthetic frame source.
+//!
+//! Generates a moving test pattern without a compositor: RGBW colour
+//! quadrants with a square bouncing across them and a bar sweeping along
+//! the bottom edge. Only the areas the two moved through are reported as
+//! damage, so the server sees partial updates as it would from a real
+//! desktop.
+
+use std::time::Duration;
+
+use tokio::time::{Interval, MissedTickBehavior};
+
+use crate::source::headless_desktop;
+use crate::{
+    monotonic_ns, BufferPool, CaptureEvent, CapturedFrame, DamageRect, DesktopInfo, FrameSource,
+    PixelFormat,
+};
+
+/// Colour of the bouncing square (BGRA).
+const SQUARE_BGRA: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// Colour of the sweeping bar (BGRA).
+const BAR_BGRA: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+
+/// A rectangle moving in a straight line, bouncing off the frame edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sprite {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    dx: i64,
+    dy: i64,
+}
+
+impl Sprite {
+    /// Move one step within a `width` x `height` frame.
+    fn step(&mut self, width: u32, height: u32) {
+        (self.x, self.dx) = bounce(self.x, self.dx, width.saturating_sub(self.width));
+        (self.y, self.dy) = bounce(self.y, self.dy, height.saturating_sub(self.height));
+    }
+
+    /// Bounding box of this sprite and `other`.
+    #[allow(clippy::cast_possible_wrap)] // frame sizes fit in u16
+    fn union(&self, other: &Self) -> DamageRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        DamageRect::new(x as i32, y as i32, right - x, bottom - y)
+    }
+}
+
+/// Advance `pos` by `delta` within `0..=max`, reversing at either end.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // clamped to 0..=max
+fn bounce(pos: u32, delta: i64, max: u32) -> (u32, i64) {
+    let next = i64::from(pos) + delta;
+    if next < 0 {
+        (0, delta.abs())
+    } else if next > i64::from(max) {
+        (max, -delta.abs())
+    } else {
+        (next as u32, delta)
+    }
+}
+
+/// Generates a moving BGRA test pattern at a fixed frame rate.
+///
+/// The first frame is sent whole (without damage), later frames report
+/// the areas the square and the bar moved through.
+pub struct SyntheticSource {
+    desktop: DesktopInfo,
+    width: u32,
+    height: u32,
+    /// The pattern as last sent, updated in place.
+    canvas: Vec<u8>,
+    pool: BufferPool,
+    frame_interval: Duration,
+    /// Created on first use, as timers need the runtime.
+    interval: Option<Interval>,
+    sequence: u64,
+    square: Sprite,
+    bar: Sprite,
+}
+
+impl SyntheticSource {
+    /// Generate `width` x `height` frames, `fps` times a second.
+    #[must_use]
+    pub fn new(width: u16, height: u16, fps: u32) -> Self {
+        let (w, h) = (u32::from(width.max(1)), u32::from(height.max(1)));
+        let side = (h / 6).max(1);
+        let square = Sprite {
+            x: 0,
+            y: 0,
+            width: side,
+            height: side,
+            dx: i64::from((w / 120).max(1)),
+            dy: i64::from((h / 120).max(1)),
+        };
+        let bar_height = (h / 24).max(1);
+        let bar = Sprite {
+            x: 0,
+            y: h - bar_height,
+            width: (w / 8).max(1),
+            height: bar_height,
+            dx: i64::from((w / 90).max(1)),
+            dy: 0,
+        };
+
+        let mut canvas = vec![0; w as usize * h as usize * 4];
+        for y in 0..h {
+            for x in 0..w {
+                put_pixel(&mut canvas, w, x, y, background(w, h, x, y));
+            }
+        }
+        let mut source = Self {
+            desktop: headless_desktop(width.max(1), height.max(1)),
+            width: w,
+            height: h,
+            canvas,
+            pool: BufferPool::new(4),
+            frame_interval: Duration::from_secs(1) / fps.max(1),
+            interval: None,
+            sequence: 0,
+            square,
+            bar,
+        };
+        source.draw(square, SQUARE_BGRA);
+        source.draw(bar, BAR_BGRA);
+        source
+    }
+
+    /// Move the pattern on and return the next frame.
+    fn next_frame(&mut self) -> CapturedFrame {
+        let damage = if self.sequence == 0 {
+            None
+        } else {
+            let (old_square, old_bar) = (self.square, self.bar);
+            self.square.step(self.width, self.height);
+            self.bar.step(self.width, self.height);
+            self.erase(old_square);
+            self.erase(old_bar);
+            self.draw(self.bar, BAR_BGRA);
+            self.draw(self.square, SQUARE_BGRA);
+            Some(vec![
+                old_square.union(&self.square),
+                old_bar.union(&self.bar),
+            ])
+        };
+
+        let mut buffer = self.pool.take(self.canvas.len());
+        buffer.copy_from_slice(&self.canvas);
+        let frame = CapturedFrame {
+            data: buffer.freeze(),
+            width: self.width,
+            height: self.height,
+            format: PixelFormat::Bgra,
+            stride: self.width * 4,
+            sequence: self.sequence,
+            pts_ns: monotonic_ns(),
+            damage,
+        };
+        self.sequence += 1;
+        frame
+    }
+
+    /// Fill `sprite`'s area with `color`.
+    fn draw(&mut self, sprite: Sprite, color: [u8; 4]) {
+        for y in sprite.y..sprite.y + sprite.height {
+            for x in sprite.x..sprite.x + sprite.width {
+                put_pixel(&mut self.canvas, self.width, x, y, color);
+            }
+        }
+    }
+
+    /// Restore the background under `sprite`.
+    fn erase(&mut self, sprite: Sprite) {
+        for y in sprite.y..sprite.y + sprite.height {
+            for x in sprite.x..sprite.x + sprite.width {
+                let color = background(self.width, self.height, x, y);
+                put_pixel(&mut self.canvas, self.width, x, y, color);
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl FrameSource for SyntheticSource {
+    fn desktop(&self) -> &DesktopInfo {
+        &self.desktop
+    }
+
+    async fn next_event(&mut self) -> Option<CaptureEvent> {
+        let frame_interval = self.frame_interval;
+        let interval = self.interval.get_or_insert_with(|| {
+            let mut interval = tokio::time::interval(frame_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            interval
+        });
+        interval.tick().await;
+        Some(CaptureEvent::Frame(self.next_frame()))
+    }
+}
+
+impl std::fmt::Debug for SyntheticSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyntheticSource")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("frame_interval", &self.frame_interval)
+            .field("sequence", &self.sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Background colour at (`x`, `y`): red, green, blue and white quadrants.
+fn background(width: u32, height: u32, x: u32, y: u32) -> [u8; 4] {
+    match (x < width / 2, y < height / 2) {
+        (true, true) => [0x00, 0x00, 0xFF, 0xFF],
+        (false, true) => [0x00, 0xFF, 0x00, 0xFF],
+        (true, false) => [0xFF, 0x00, 0x00, 0xFF],
+        (false, false) => [0xFF, 0xFF, 0xFF, 0xFF],
+    }
+}
+
+fn put_pixel(canvas: &mut [u8], width: u32, x: u32, y: u32, color: [u8; 4]) {
+    let offset = (y as usize * width as usize + x as usize) * 4;
+    canvas[offset..offset + 4].copy_from_slice(&color);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_bounce_off_the_edges() {
+        assert_eq!(bounce(5, 3, 10), (8, 3));
+        assert_eq!(bounce(9, 3, 10), (10, -3));
+        assert_eq!(bounce(1, -3, 10), (0, 3));
+        assert_eq!(bounce(0, 3, 0), (0, -3));
+    }
+
+    #[test]
+    fn only_damaged_pixels_change() {
+        let mut source = SyntheticSource::new(64, 48, 30);
+        let mut previous = source.next_frame();
+        assert!(previous.damage.is_none());
+
+        for _ in 0..100 {
+            let frame = source.next_frame();
+            let damage = frame.damage.clone().expect("damage after the first frame");
+            assert_eq!(damage.len(), 2);
+            for y in 0..frame.height {
+                for x in 0..frame.width {
+                    #[allow(clippy::cast_possible_wrap)]
+                    let damaged = damage.iter().any(|rect| {
+                        (rect.x..rect.x + rect.width as i32).contains(&(x as i32))
+                            && (rect.y..rect.y + rect.height as i32).contains(&(y as i32))
+                    });
+                    let offset = (y * frame.stride + x * 4) as usize;
+                    if !damaged {
+                        assert_eq!(
+                            frame.data[offset..offset + 4],
+                            previous.data[offset..offset + 4],
+                            "undamaged pixel ({x}, {y}) changed"
+                        );
+                    }
+                }
+            }
+            previous = frame;
+        }
+    }
+
+    #[test]
+    fn tiny_frames_are_supported() {
+        let mut source = SyntheticSource::new(1, 1, 0);
+        for _ in 0..3 {
+            let frame = source.next_frame();
+            assert_eq!(frame.data.len(), 4);
+        }
+    }
+}
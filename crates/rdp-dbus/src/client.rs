@@ -16,6 +16,10 @@ pub trait RdpServer {
     /// Tell the daemon to shut down gracefully.
     fn stop(&self) -> zbus::Result<bool>;
 
+    /// Serve the monitor on the given connector (e.g. "DP-1"); fails if
+    /// it cannot be served.
+    fn select_monitor(&self, connector: &str) -> zbus::Result<bool>;
+
     /// Whether the server is currently running.
     #[zbus(property)]
     fn running(&self) -> zbus::Result<bool>;
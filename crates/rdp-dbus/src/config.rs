@@ -158,7 +158,8 @@ impl std::fmt::Debug for AuthConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct CaptureConfig {
-    /// Target frames per second.
+    /// Maximum frames per second. Frames are only sent when the screen
+    /// changes.
     pub fps: u32,
 
     /// `PipeWire` channel capacity (number of buffered frames).
@@ -172,6 +173,30 @@ pub struct CaptureConfig {
     /// COSMIC's xdg-desktop-portal delivers `RGBx` byte-order data while
     /// reporting `BGRx` format.  Set to `false` if colors look inverted.
     pub swap_colors: bool,
+
+    /// Change the captured output's mode to fit the client's window
+    /// instead of only resizing the encoded surface.
+    ///
+    /// Uses `cosmic-randr`; the original mode is restored when the client
+    /// disconnects or the server exits.
+    pub resize_output: bool,
+
+    /// How the desktop is shown on a smaller client: "fit" (scaled down
+    /// to the client's size) or "viewport" (a client-sized window onto
+    /// the desktop that follows the pointer).
+    pub display_mode: String,
+
+    /// Connector name of the monitor to serve (e.g. "DP-1"). When set,
+    /// every monitor selected in the portal dialog is offered and the
+    /// served one can be switched at runtime; empty serves the monitor
+    /// chosen in the dialog. Ignored with `multi_monitor`.
+    pub monitor: String,
+
+    /// What the portal dialog offers: "monitor" (whole monitors) or
+    /// "virtual" (a new monitor sized to the client).
+    /// "synthetic" (a moving test pattern) and "replay:<path>" (PNG files
+    /// or a Y4M file) need no compositor.
+    pub source: String,
 }
 
 /// Audio forwarding settings.
@@ -186,6 +211,41 @@ pub struct AudioConfig {
 
     /// Number of audio channels.
     pub channels: u16,
+
+    /// Preferred audio codec: "auto", "opus", "aac", or "pcm".
+    ///
+    /// "auto" advertises every codec with an installed encoder and lets
+    /// the client's format list decide (Opus > AAC > PCM). Uncompressed
+    /// PCM is always offered as a fallback.
+    pub codec: String,
+
+    /// Target bitrate for compressed codecs in bits per second.
+    pub bitrate: u32,
+
+    /// What to capture: "" or "default" (default sink monitor),
+    /// "sink:<node.name>" (a specific sink's monitor) or
+    /// "node:<node.name>" (e.g. a single application's output).
+    ///
+    /// Ignored when `virtual_sink` is enabled.
+    pub source: String,
+
+    /// Create a dedicated null sink for each RDP session, make it the
+    /// default output while a client is connected, and capture only that.
+    ///
+    /// The previous default output is restored on disconnect.
+    pub virtual_sink: bool,
+
+    /// Playback mode: "mirror" (local speakers keep playing) or
+    /// "redirect" (local outputs are muted while a client is connected).
+    ///
+    /// Outputs muted in redirect mode are unmuted on disconnect, and on
+    /// the next start if the server exited without cleaning up.
+    pub mode: String,
+
+    /// Accept the client's microphone over the `AUDIO_INPUT` channel and
+    /// expose it as an "RDP Microphone" `PipeWire` source. Off unless the
+    /// user opts in, since local applications can then record the client.
+    pub microphone: bool,
 }
 
 impl Default for AudioConfig {
@@ -194,6 +254,12 @@ impl Default for AudioConfig {
             enable: true,
             sample_rate: 44100,
             channels: 2,
+            codec: "auto".to_string(),
+            bitrate: 128_000,
+            source: String::new(),
+            virtual_sink: false,
+            mode: "mirror".to_string(),
+            microphone: false,
         }
     }
 }
@@ -204,11 +270,24 @@ impl Default for AudioConfig {
 pub struct ClipboardConfig {
     /// Enable clipboard sharing between local and remote sessions.
     pub enable: bool,
+
+    /// Also write text copied on the remote side into the local PRIMARY
+    /// selection, so it can be pasted with a middle click.
+    pub mirror_to_primary: bool,
+
+    /// Offer the local PRIMARY selection (currently selected text) to the
+    /// remote client as clipboard content. When non-empty, it takes
+    /// precedence over the regular clipboard.
+    pub offer_primary: bool,
 }
 
 impl Default for ClipboardConfig {
     fn default() -> Self {
-        Self { enable: true }
+        Self {
+            enable: true,
+            mirror_to_primary: false,
+            offer_primary: false,
+        }
     }
 }
 
@@ -252,6 +331,10 @@ impl Default for CaptureConfig {
             // data while reporting BGRx format ID.  Default to swapping
             // R↔B so colors are correct out of the box.
             swap_colors: true,
+            resize_output: false,
+            display_mode: "fit".into(),
+            monitor: String::new(),
+            source: "monitor".into(),
         }
     }
 }
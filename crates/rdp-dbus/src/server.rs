@@ -58,6 +58,35 @@ pub enum DaemonCommand {
     Reload,
     /// Gracefully shut down the server.
     Stop,
+    /// Serve the monitor on the given connector (e.g. "DP-1") and send
+    /// the outcome on `reply`.
+    SelectMonitor {
+        connector: String,
+        reply: tokio::sync::oneshot::Sender<Result<(), SelectMonitorError>>,
+    },
+}
+
+/// Why the daemon could not serve the requested monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectMonitorError {
+    /// No captured monitor is on the connector.
+    UnknownConnector(String),
+    /// The daemon was started without a `monitor` configured.
+    Unavailable,
+    /// Switching the capture failed.
+    Failed(String),
+}
+
+impl From<SelectMonitorError> for zbus::fdo::Error {
+    fn from(error: SelectMonitorError) -> Self {
+        match error {
+            SelectMonitorError::UnknownConnector(message) => Self::InvalidArgs(message),
+            SelectMonitorError::Unavailable => Self::NotSupported(
+                "switching monitors requires `monitor` in the capture config".into(),
+            ),
+            SelectMonitorError::Failed(message) => Self::Failed(message),
+        }
+    }
 }
 
 impl RdpServerInterface {
@@ -99,6 +128,30 @@ impl RdpServerInterface {
         Ok(self.cmd_tx.send(DaemonCommand::Stop).await.is_ok())
     }
 
+    /// Serve the monitor on the given connector (e.g. "DP-1").
+    ///
+    /// Returns `true` once the monitor is served. Fails with `InvalidArgs`
+    /// if no captured monitor is on the connector and with `NotSupported`
+    /// unless the daemon was started with a `monitor` configured. Only
+    /// callers running as the same Unix user may invoke this method.
+    async fn select_monitor(
+        &self,
+        connector: String,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<bool> {
+        verify_same_uid(&header, connection).await?;
+        let (reply, outcome) = tokio::sync::oneshot::channel();
+        self.cmd_tx
+            .send(DaemonCommand::SelectMonitor { connector, reply })
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("daemon is shutting down".into()))?;
+        outcome
+            .await
+            .map_err(|_| zbus::fdo::Error::Failed("daemon dropped the request".into()))??;
+        Ok(true)
+    }
+
     /// Whether the server is currently running.
     #[zbus(property)]
     async fn running(&self) -> bool {
@@ -0,0 +1,109 @@
+//! Throughput of the RGB to YUV conversion, next to `GStreamer`'s
+//! `videoconvert` doing the same job.
+//!
+//! Run with `cargo bench -p rdp-encode --bench convert`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use rdp_encode::{rgb_to_yuv, ChannelOrder, YuvFormat, YuvLayout};
+
+const SIZES: [(u32, u32); 2] = [(1920, 1080), (3840, 2160)];
+const ITERATIONS: u32 = 60;
+
+fn main() {
+    for (width, height) in SIZES {
+        let stride = width as usize * 4;
+        let frame: Arc<[u8]> = test_frame(width, height).into();
+        for format in [YuvFormat::I420, YuvFormat::Nv12] {
+            let layout = YuvLayout::packed(format, width, height);
+            let mut out = vec![0; layout.size];
+            let rust = time(|| {
+                rgb_to_yuv(&frame, stride, ChannelOrder::Bgrx, &layout, &mut out);
+            });
+            report(width, height, format, "rgb_to_yuv", rust);
+            match videoconvert(width, height, format, &frame) {
+                Ok(elapsed) => report(width, height, format, "videoconvert", elapsed),
+                Err(e) => println!("{width}x{height} {format:?}: videoconvert unavailable: {e}"),
+            }
+        }
+    }
+}
+
+/// A BGRx frame with gradients, so no shortcut applies.
+fn test_frame(width: u32, height: u32) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        for x in 0..width {
+            #[allow(clippy::cast_possible_truncation)]
+            frame.extend_from_slice(&[x as u8, y as u8, (x + y) as u8, 0]);
+        }
+    }
+    frame
+}
+
+/// Average time of one call to `run`, after a warm-up call.
+fn time(mut run: impl FnMut()) -> Duration {
+    run();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        run();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn report(width: u32, height: u32, format: YuvFormat, what: &str, per_frame: Duration) {
+    let megapixels = f64::from(width) * f64::from(height) / 1e6;
+    println!(
+        "{width}x{height} {format:?} {what:>12}: {:7.2} ms/frame, {:7.1} MP/s",
+        per_frame.as_secs_f64() * 1e3,
+        megapixels / per_frame.as_secs_f64()
+    );
+}
+
+/// Average time for `videoconvert` to convert `frame`, including the
+/// round trip through an `appsrc ! videoconvert ! appsink` pipeline.
+fn videoconvert(
+    width: u32,
+    height: u32,
+    format: YuvFormat,
+    frame: &Arc<[u8]>,
+) -> Result<Duration, Box<dyn std::error::Error>> {
+    gst::init()?;
+    let description = format!(
+        "appsrc name=src caps=video/x-raw,format=BGRx,width={width},height={height},\
+         framerate=0/1,colorimetry=1:3:5:1 \
+         ! videoconvert ! video/x-raw,format={},colorimetry=1:3:5:1 \
+         ! appsink name=sink sync=false",
+        format.caps_name()
+    );
+    let pipeline = gst::parse::launch(&description)?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| "not a pipeline")?;
+    let src = pipeline
+        .by_name("src")
+        .and_then(|e| e.downcast::<gst_app::AppSrc>().ok())
+        .ok_or("no appsrc")?;
+    let sink = pipeline
+        .by_name("sink")
+        .and_then(|e| e.downcast::<gst_app::AppSink>().ok())
+        .ok_or("no appsink")?;
+    pipeline.set_state(gst::State::Playing)?;
+
+    let mut result = Ok(());
+    let elapsed = time(|| {
+        if result.is_err() {
+            return;
+        }
+        result = src
+            .push_buffer(gst::Buffer::from_slice(Arc::clone(frame)))
+            .map_err(|e| e.to_string())
+            .and_then(|_| sink.pull_sample().map(drop).map_err(|e| e.to_string()));
+    });
+    let _ = pipeline.set_state(gst::State::Null);
+    result?;
+    Ok(elapsed)
+}
@@ -0,0 +1,979 @@
+//! RGB to YUV conversion for the encoder.
+//!
+//! Frames are converted from 32-bit RGB to I420 or NV12 (BT.709 matrix,
+//! full range, matching the `1:3:5:1` colorimetry signalled to clients)
+//! in a single pass that reads each source pixel once. The R/B swizzle is
+//! folded into the coefficients and the alpha byte is never read, so
+//! frames in either channel order with an undefined `x` byte can be
+//! converted as they are.
+//!
+//! On `x86_64` the conversion uses SSE2 (always available there); other
+//! targets use the scalar path, which produces identical output.
+//!
+//! NV12 frames captured as they are only need their planes copied into
+//! the encoder's layout ([`nv12_to_yuv`]); [`nv12_to_bgra`] converts them
+//! back for paths that work on RGB.
+
+/// Channel order of 32-bit source pixels, in memory order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    /// `B, G, R, x` (`BGRx`/`BGRA`).
+    Bgrx,
+    /// `R, G, B, x` (`RGBx`/`RGBA`).
+    Rgbx,
+}
+
+/// Planar YUV 4:2:0 output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvFormat {
+    /// Separate Y, U and V planes.
+    I420,
+    /// A Y plane followed by interleaved U/V.
+    Nv12,
+}
+
+impl YuvFormat {
+    /// `GStreamer` caps name of the format.
+    #[must_use]
+    pub fn caps_name(self) -> &'static str {
+        match self {
+            Self::I420 => "I420",
+            Self::Nv12 => "NV12",
+        }
+    }
+}
+
+/// Where the planes of a YUV frame live in its buffer.
+///
+/// [`packed`](Self::packed) lays the planes out as `GStreamer` does by
+/// default, rows padded to 4 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YuvLayout {
+    /// Pixel format.
+    pub format: YuvFormat,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Byte offset of each plane (the third is unused for NV12).
+    pub offsets: [usize; 3],
+    /// Row stride of each plane in bytes.
+    pub strides: [usize; 3],
+    /// Total buffer size in bytes.
+    pub size: usize,
+}
+
+impl YuvLayout {
+    /// Default layout for a `width` x `height` frame.
+    #[must_use]
+    pub fn packed(format: YuvFormat, width: u32, height: u32) -> Self {
+        let round_up_4 = |n: usize| (n + 3) & !3;
+        let luma_rows = (height as usize + 1) & !1;
+        let chroma_rows = luma_rows / 2;
+        let chroma_width = (width as usize).div_ceil(2);
+        let luma_stride = round_up_4(width as usize);
+        let luma_size = luma_stride * luma_rows;
+        let (offsets, strides, size) = match format {
+            YuvFormat::I420 => {
+                let chroma_stride = round_up_4(chroma_width);
+                let chroma_size = chroma_stride * chroma_rows;
+                (
+                    [0, luma_size, luma_size + chroma_size],
+                    [luma_stride, chroma_stride, chroma_stride],
+                    luma_size + 2 * chroma_size,
+                )
+            }
+            YuvFormat::Nv12 => {
+                let chroma_stride = round_up_4(chroma_width * 2);
+                (
+                    [0, luma_size, 0],
+                    [luma_stride, chroma_stride, 0],
+                    luma_size + chroma_stride * chroma_rows,
+                )
+            }
+        };
+        Self {
+            format,
+            width,
+            height,
+            offsets,
+            strides,
+            size,
+        }
+    }
+
+    /// Number of planes.
+    #[must_use]
+    pub fn planes(&self) -> usize {
+        match self.format {
+            YuvFormat::I420 => 3,
+            YuvFormat::Nv12 => 2,
+        }
+    }
+}
+
+/// Fixed-point BT.709 full-range coefficients, scaled by 2^14, in
+/// `B, G, R` order.
+const Y_COEFFS: [i16; 3] = [1183, 11718, 3483];
+const U_COEFFS: [i16; 3] = [8192, -6315, -1877];
+const V_COEFFS: [i16; 3] = [-751, -7441, 8192];
+
+/// Coefficients laid out for pixels in `order` (`x` weighted zero).
+fn pixel_coeffs(coeffs: [i16; 3], order: ChannelOrder) -> [i16; 4] {
+    let [b, g, r] = coeffs;
+    match order {
+        ChannelOrder::Bgrx => [b, g, r, 0],
+        ChannelOrder::Rgbx => [r, g, b, 0],
+    }
+}
+
+/// Convert a frame of 32-bit pixels to YUV 4:2:0.
+///
+/// `src` holds `layout.height` rows of `layout.width` pixels, each row
+/// starting `src_stride` bytes after the previous one; `dst` receives the
+/// planes at the positions given by `layout`. Each chroma sample averages
+/// a 2x2 block, with the last column or row repeated for odd sizes.
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` is too small for the frame or `src_stride`
+/// is shorter than a row.
+pub fn rgb_to_yuv(
+    src: &[u8],
+    src_stride: usize,
+    order: ChannelOrder,
+    layout: &YuvLayout,
+    dst: &mut [u8],
+) {
+    let width = layout.width as usize;
+    let height = layout.height as usize;
+    if width == 0 || height == 0 {
+        return;
+    }
+    assert!(src_stride >= width * 4, "source stride shorter than a row");
+    assert!(
+        src.len() >= src_stride * (height - 1) + width * 4,
+        "source too small for {width}x{height}"
+    );
+    assert!(dst.len() >= layout.size, "destination too small for layout");
+
+    let (luma, chroma) = dst.split_at_mut(layout.offsets[1]);
+    let mut chroma_planes = match layout.format {
+        YuvFormat::I420 => {
+            let (u, v) = chroma.split_at_mut(layout.offsets[2] - layout.offsets[1]);
+            ChromaPlanes::Planar {
+                u,
+                v,
+                stride: layout.strides[1],
+            }
+        }
+        YuvFormat::Nv12 => ChromaPlanes::Interleaved {
+            uv: chroma,
+            stride: layout.strides[1],
+        },
+    };
+    let luma_stride = layout.strides[0];
+    let coeffs = Coeffs::new(order);
+
+    for pair in 0..height.div_ceil(2) {
+        let top = 2 * pair;
+        let bottom = (top + 1).min(height - 1);
+        let rows = RowPair {
+            top: &src[top * src_stride..top * src_stride + width * 4],
+            bottom: &src[bottom * src_stride..bottom * src_stride + width * 4],
+        };
+        let (luma_top, luma_rest) = luma[top * luma_stride..].split_at_mut(luma_stride);
+        let luma_bottom = (bottom != top).then(|| &mut luma_rest[..luma_stride]);
+        let chroma_row = chroma_planes.row(pair);
+        convert_rows(
+            &rows,
+            &coeffs,
+            &mut luma_top[..width],
+            luma_bottom,
+            chroma_row,
+            width,
+        );
+    }
+}
+
+/// Copy an NV12 frame into the planes of `layout`.
+///
+/// `src` holds `layout.height` luma rows followed by `height.div_ceil(2)`
+/// rows of interleaved U/V samples, all `src_stride` bytes apart. For
+/// I420 the samples are split into their own planes.
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` is too small for the frame or `src_stride`
+/// is shorter than a row.
+pub fn nv12_to_yuv(src: &[u8], src_stride: usize, layout: &YuvLayout, dst: &mut [u8]) {
+    let width = layout.width as usize;
+    let height = layout.height as usize;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (luma_src, chroma_src) = split_nv12(src, src_stride, width, height);
+    assert!(dst.len() >= layout.size, "destination too small for layout");
+
+    let chroma_len = 2 * width.div_ceil(2);
+    for (y, row) in luma_src.chunks(src_stride).enumerate() {
+        let start = layout.offsets[0] + y * layout.strides[0];
+        dst[start..start + width].copy_from_slice(&row[..width]);
+    }
+    for (y, row) in chroma_src.chunks(src_stride).enumerate() {
+        let row = &row[..chroma_len];
+        let u_start = layout.offsets[1] + y * layout.strides[1];
+        match layout.format {
+            YuvFormat::Nv12 => dst[u_start..u_start + chroma_len].copy_from_slice(row),
+            YuvFormat::I420 => {
+                let v_start = layout.offsets[2] + y * layout.strides[2];
+                for (x, uv) in row.chunks_exact(2).enumerate() {
+                    dst[u_start + x] = uv[0];
+                    dst[v_start + x] = uv[1];
+                }
+            }
+        }
+    }
+}
+
+/// Convert an NV12 frame, laid out as for [`nv12_to_yuv`], to opaque BGRA
+/// with rows `dst_stride` bytes apart.
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` is too small for the frame or a stride is
+/// shorter than a row.
+pub fn nv12_to_bgra(
+    src: &[u8],
+    src_stride: usize,
+    width: u32,
+    height: u32,
+    dst: &mut [u8],
+    dst_stride: usize,
+) {
+    let width = width as usize;
+    let height = height as usize;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (luma_src, chroma_src) = split_nv12(src, src_stride, width, height);
+    assert!(
+        dst_stride >= width * 4,
+        "destination stride shorter than a row"
+    );
+    assert!(
+        dst.len() >= dst_stride * (height - 1) + width * 4,
+        "destination too small for {width}x{height}"
+    );
+
+    for y in 0..height {
+        let luma_row = &luma_src[y * src_stride..];
+        let chroma_row = &chroma_src[y / 2 * src_stride..];
+        let dst_row = &mut dst[y * dst_stride..y * dst_stride + width * 4];
+        for (x, pixel) in dst_row.chunks_exact_mut(4).enumerate() {
+            let c = x / 2 * 2;
+            pixel.copy_from_slice(&yuv_to_bgra(luma_row[x], chroma_row[c], chroma_row[c + 1]));
+        }
+    }
+}
+
+/// Split an NV12 frame into its luma and chroma planes, checking that
+/// both are complete.
+fn split_nv12(src: &[u8], stride: usize, width: usize, height: usize) -> (&[u8], &[u8]) {
+    let chroma_rows = height.div_ceil(2);
+    let chroma_len = 2 * width.div_ceil(2);
+    assert!(stride >= chroma_len, "source stride shorter than a row");
+    assert!(
+        src.len() >= stride * (height + chroma_rows - 1) + chroma_len,
+        "source too small for {width}x{height}"
+    );
+    let (luma, chroma) = src.split_at(stride * height);
+    (luma, chroma)
+}
+
+/// Fixed-point inverse BT.709 full-range coefficients, scaled by 2^14.
+const R_FROM_V: i32 = 25802;
+const G_FROM_U: i32 = 3069;
+const G_FROM_V: i32 = 7670;
+const B_FROM_U: i32 = 30402;
+
+/// One opaque BGRA pixel from its YUV samples.
+fn yuv_to_bgra(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let y = i32::from(y) << 14;
+    let (u, v) = (i32::from(u) - 128, i32::from(v) - 128);
+    let channel = |sum: i32| {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let c = ((sum + (1 << 13)) >> 14).clamp(0, 255) as u8;
+        c
+    };
+    [
+        channel(y + B_FROM_U * u),
+        channel(y - G_FROM_U * u - G_FROM_V * v),
+        channel(y + R_FROM_V * v),
+        0xFF,
+    ]
+}
+
+/// Destination chroma plane(s).
+enum ChromaPlanes<'a> {
+    Planar {
+        u: &'a mut [u8],
+        v: &'a mut [u8],
+        stride: usize,
+    },
+    Interleaved {
+        uv: &'a mut [u8],
+        stride: usize,
+    },
+}
+
+/// One row of chroma samples.
+enum ChromaRow<'a> {
+    Planar { u: &'a mut [u8], v: &'a mut [u8] },
+    Interleaved(&'a mut [u8]),
+}
+
+impl ChromaPlanes<'_> {
+    fn row(&mut self, row: usize) -> ChromaRow<'_> {
+        match self {
+            Self::Planar { u, v, stride } => {
+                let start = row * *stride;
+                ChromaRow::Planar {
+                    u: &mut u[start..start + *stride],
+                    v: &mut v[start..start + *stride],
+                }
+            }
+            Self::Interleaved { uv, stride } => {
+                let start = row * *stride;
+                ChromaRow::Interleaved(&mut uv[start..start + *stride])
+            }
+        }
+    }
+}
+
+impl ChromaRow<'_> {
+    /// Store chroma sample `index` of the row.
+    fn set(&mut self, index: usize, u: u8, v: u8) {
+        match self {
+            Self::Planar { u: u_row, v: v_row } => {
+                u_row[index] = u;
+                v_row[index] = v;
+            }
+            Self::Interleaved(uv_row) => {
+                uv_row[2 * index] = u;
+                uv_row[2 * index + 1] = v;
+            }
+        }
+    }
+}
+
+struct RowPair<'a> {
+    top: &'a [u8],
+    bottom: &'a [u8],
+}
+
+struct Coeffs {
+    y: [i16; 4],
+    u: [i16; 4],
+    v: [i16; 4],
+}
+
+impl Coeffs {
+    fn new(order: ChannelOrder) -> Self {
+        Self {
+            y: pixel_coeffs(Y_COEFFS, order),
+            u: pixel_coeffs(U_COEFFS, order),
+            v: pixel_coeffs(V_COEFFS, order),
+        }
+    }
+}
+
+/// Convert two rows into luma and one row of chroma. `luma_bottom` is
+/// `None` when the bottom row repeats the top one (odd heights).
+fn convert_rows(
+    rows: &RowPair<'_>,
+    coeffs: &Coeffs,
+    luma_top: &mut [u8],
+    mut luma_bottom: Option<&mut [u8]>,
+    mut chroma: ChromaRow<'_>,
+    width: usize,
+) {
+    #[cfg(target_arch = "x86_64")]
+    let done = {
+        // Safety: SSE2 is part of the x86_64 baseline; the kernel stays
+        // within `width` pixels of each row.
+        unsafe {
+            sse2::convert_rows(
+                rows,
+                coeffs,
+                luma_top,
+                luma_bottom.as_deref_mut(),
+                &mut chroma,
+                width,
+            )
+        }
+    };
+    #[cfg(not(target_arch = "x86_64"))]
+    let done = 0;
+
+    for x in (done..width).step_by(2) {
+        let right = (x + 1).min(width - 1);
+        let pixels = [
+            &rows.top[x * 4..x * 4 + 4],
+            &rows.top[right * 4..right * 4 + 4],
+            &rows.bottom[x * 4..x * 4 + 4],
+            &rows.bottom[right * 4..right * 4 + 4],
+        ];
+        luma_top[x] = luma(pixels[0], coeffs.y);
+        if right != x {
+            luma_top[right] = luma(pixels[1], coeffs.y);
+        }
+        if let Some(luma_bottom) = luma_bottom.as_deref_mut() {
+            luma_bottom[x] = luma(pixels[2], coeffs.y);
+            if right != x {
+                luma_bottom[right] = luma(pixels[3], coeffs.y);
+            }
+        }
+        let mut sums = [0i32; 4];
+        for pixel in pixels {
+            for (sum, &value) in sums.iter_mut().zip(pixel) {
+                *sum += i32::from(value);
+            }
+        }
+        chroma.set(
+            x / 2,
+            chroma_sample(sums, coeffs.u),
+            chroma_sample(sums, coeffs.v),
+        );
+    }
+}
+
+fn dot(values: impl IntoIterator<Item = i32>, coeffs: [i16; 4]) -> i32 {
+    values
+        .into_iter()
+        .zip(coeffs)
+        .map(|(value, coeff)| value * i32::from(coeff))
+        .sum()
+}
+
+fn luma(pixel: &[u8], coeffs: [i16; 4]) -> u8 {
+    let sum = dot(pixel.iter().map(|&v| i32::from(v)), coeffs);
+    // The coefficients sum to 2^14, so this is within 0..=255.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let y = ((sum + (1 << 13)) >> 14) as u8;
+    y
+}
+
+/// A chroma sample from the channel sums of four pixels.
+fn chroma_sample(sums: [i32; 4], coeffs: [i16; 4]) -> u8 {
+    let sum = dot(sums, coeffs);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let c = (((sum + (1 << 15)) >> 16) + 128).clamp(0, 255) as u8;
+    c
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::{
+        __m128i, _mm_add_epi16, _mm_add_epi32, _mm_castps_si128, _mm_castsi128_ps,
+        _mm_cvtsi128_si32, _mm_loadu_si128, _mm_madd_epi16, _mm_packs_epi32, _mm_packus_epi16,
+        _mm_set1_epi32, _mm_set_epi16, _mm_setzero_si128, _mm_shuffle_ps, _mm_srai_epi32,
+        _mm_srli_si128, _mm_unpackhi_epi8, _mm_unpacklo_epi64, _mm_unpacklo_epi8,
+    };
+
+    use super::{ChromaRow, Coeffs, RowPair};
+
+    /// Pixels converted per step: four per row, two chroma samples.
+    const STEP: usize = 4;
+
+    /// Even and odd 32-bit lanes of `a` then `b`.
+    const EVEN: i32 = 0b10_00_10_00;
+    const ODD: i32 = 0b11_01_11_01;
+
+    /// Convert the leading multiple of four pixels of both rows, returning
+    /// how many were converted.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support SSE2.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn convert_rows(
+        rows: &RowPair<'_>,
+        coeffs: &Coeffs,
+        luma_top: &mut [u8],
+        mut luma_bottom: Option<&mut [u8]>,
+        chroma: &mut ChromaRow<'_>,
+        width: usize,
+    ) -> usize {
+        let y_coeffs = splat(coeffs.y);
+        let u_coeffs = splat(coeffs.u);
+        let v_coeffs = splat(coeffs.v);
+        let zero = _mm_setzero_si128();
+        let luma_round = _mm_set1_epi32(1 << 13);
+        let chroma_round = _mm_set1_epi32(1 << 15);
+        let chroma_offset = _mm_set1_epi32(128);
+
+        let end = width - width % STEP;
+        for x in (0..end).step_by(STEP) {
+            let top = _mm_loadu_si128(rows.top[x * 4..x * 4 + 16].as_ptr().cast());
+            let bottom = _mm_loadu_si128(rows.bottom[x * 4..x * 4 + 16].as_ptr().cast());
+            // Widen to 16 bits: two pixels per register.
+            let top_lo = _mm_unpacklo_epi8(top, zero);
+            let top_hi = _mm_unpackhi_epi8(top, zero);
+            let bottom_lo = _mm_unpacklo_epi8(bottom, zero);
+            let bottom_hi = _mm_unpackhi_epi8(bottom, zero);
+
+            store4(
+                &mut luma_top[x..x + 4],
+                luma(top_lo, top_hi, y_coeffs, luma_round),
+            );
+            if let Some(luma_bottom) = luma_bottom.as_deref_mut() {
+                store4(
+                    &mut luma_bottom[x..x + 4],
+                    luma(bottom_lo, bottom_hi, y_coeffs, luma_round),
+                );
+            }
+
+            // Sum each 2x2 block: rows first, then neighbouring pixels.
+            let left = _mm_add_epi16(top_lo, bottom_lo);
+            let right = _mm_add_epi16(top_hi, bottom_hi);
+            let left = _mm_add_epi16(left, _mm_srli_si128::<8>(left));
+            let right = _mm_add_epi16(right, _mm_srli_si128::<8>(right));
+            let blocks = _mm_unpacklo_epi64(left, right);
+            // [u0, u1, v0, v1]
+            let sum = pairwise_sum(
+                _mm_madd_epi16(blocks, u_coeffs),
+                _mm_madd_epi16(blocks, v_coeffs),
+            );
+            let uv = _mm_add_epi32(
+                _mm_srai_epi32::<16>(_mm_add_epi32(sum, chroma_round)),
+                chroma_offset,
+            );
+            let [u0, u1, v0, v1] = to_bytes(uv).to_le_bytes();
+            let index = x / 2;
+            match chroma {
+                ChromaRow::Planar { u, v } => {
+                    u[index..index + 2].copy_from_slice(&[u0, u1]);
+                    v[index..index + 2].copy_from_slice(&[v0, v1]);
+                }
+                ChromaRow::Interleaved(uv_row) => {
+                    uv_row[2 * index..2 * index + 4].copy_from_slice(&[u0, v0, u1, v1]);
+                }
+            }
+        }
+        end
+    }
+
+    /// Luma of the four pixels in `lo` and `hi`.
+    #[target_feature(enable = "sse2")]
+    unsafe fn luma(lo: __m128i, hi: __m128i, coeffs: __m128i, round: __m128i) -> u32 {
+        let sum = pairwise_sum(_mm_madd_epi16(lo, coeffs), _mm_madd_epi16(hi, coeffs));
+        to_bytes(_mm_srai_epi32::<14>(_mm_add_epi32(sum, round)))
+    }
+
+    /// Coefficients for two pixels.
+    #[target_feature(enable = "sse2")]
+    unsafe fn splat([c0, c1, c2, c3]: [i16; 4]) -> __m128i {
+        _mm_set_epi16(c3, c2, c1, c0, c3, c2, c1, c0)
+    }
+
+    /// Per-pixel sums from `madd` results holding two partial sums per
+    /// pixel: `[a0 + a1, a2 + a3, b0 + b1, b2 + b3]`.
+    #[target_feature(enable = "sse2")]
+    unsafe fn pairwise_sum(a: __m128i, b: __m128i) -> __m128i {
+        let (a, b) = (_mm_castsi128_ps(a), _mm_castsi128_ps(b));
+        _mm_add_epi32(
+            _mm_castps_si128(_mm_shuffle_ps::<EVEN>(a, b)),
+            _mm_castps_si128(_mm_shuffle_ps::<ODD>(a, b)),
+        )
+    }
+
+    /// Saturate four 32-bit lanes to bytes.
+    #[target_feature(enable = "sse2")]
+    unsafe fn to_bytes(lanes: __m128i) -> u32 {
+        let words = _mm_packs_epi32(lanes, lanes);
+        #[allow(clippy::cast_sign_loss)]
+        let bytes = _mm_cvtsi128_si32(_mm_packus_epi16(words, words)) as u32;
+        bytes
+    }
+
+    fn store4(dst: &mut [u8], bytes: u32) {
+        dst.copy_from_slice(&bytes.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `BGRx` test image with smooth gradients and hard edges.
+    fn test_image(width: usize, height: usize, stride: usize) -> Vec<u8> {
+        let mut image = vec![0xAA; stride * height];
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * stride + x * 4;
+                #[allow(clippy::cast_possible_truncation)]
+                image[i..i + 4].copy_from_slice(&[
+                    (x * 255 / width.max(2).saturating_sub(1)) as u8,
+                    ((x * 7 + y * 13) % 256) as u8,
+                    if (x / 3 + y / 2) % 2 == 0 { 255 } else { 0 },
+                    (x ^ y) as u8,
+                ]);
+            }
+        }
+        image
+    }
+
+    /// Floating-point BT.709 full-range reference.
+    fn reference(b: f64, g: f64, r: f64) -> [f64; 3] {
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        [y, (b - y) / 1.8556 + 128.0, (r - y) / 1.5748 + 128.0]
+    }
+
+    fn convert(image: &[u8], stride: usize, order: ChannelOrder, layout: &YuvLayout) -> Vec<u8> {
+        let mut out = vec![0; layout.size];
+        rgb_to_yuv(image, stride, order, layout, &mut out);
+        out
+    }
+
+    /// The scalar path alone, for comparison with the SIMD one.
+    fn convert_scalar(image: &[u8], stride: usize, layout: &YuvLayout) -> Vec<u8> {
+        let width = layout.width as usize;
+        let height = layout.height as usize;
+        let mut out = vec![0; layout.size];
+        let coeffs = Coeffs::new(ChannelOrder::Bgrx);
+        for y in 0..height {
+            for x in 0..width {
+                let i = y * stride + x * 4;
+                out[y * layout.strides[0] + x] = luma(&image[i..i + 4], coeffs.y);
+            }
+        }
+        for cy in 0..height.div_ceil(2) {
+            for cx in 0..width.div_ceil(2) {
+                let mut sums = [0i32; 4];
+                for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    let y = (2 * cy + dy).min(height - 1);
+                    let x = (2 * cx + dx).min(width - 1);
+                    for (c, sum) in sums.iter_mut().enumerate() {
+                        *sum += i32::from(image[y * stride + x * 4 + c]);
+                    }
+                }
+                let (u, v) = (chroma_sample(sums, coeffs.u), chroma_sample(sums, coeffs.v));
+                let row = layout.offsets[1] + cy * layout.strides[1];
+                match layout.format {
+                    YuvFormat::I420 => {
+                        out[row + cx] = u;
+                        out[layout.offsets[2] + cy * layout.strides[2] + cx] = v;
+                    }
+                    YuvFormat::Nv12 => {
+                        out[row + 2 * cx] = u;
+                        out[row + 2 * cx + 1] = v;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn packed_layout_matches_gstreamer() {
+        let i420 = YuvLayout::packed(YuvFormat::I420, 7, 5);
+        assert_eq!(i420.strides, [8, 4, 4]);
+        assert_eq!(i420.offsets, [0, 48, 60]);
+        assert_eq!(i420.size, 72);
+
+        let nv12 = YuvLayout::packed(YuvFormat::Nv12, 1920, 1080);
+        assert_eq!(nv12.strides[..2], [1920, 1920]);
+        assert_eq!(nv12.offsets[1], 1920 * 1080);
+        assert_eq!(nv12.size, 1920 * 1080 * 3 / 2);
+    }
+
+    #[test]
+    fn primaries_match_bt709_full_range() {
+        let layout = YuvLayout::packed(YuvFormat::I420, 2, 2);
+        for bgr in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [0, 0, 255],
+            [0, 255, 0],
+            [255, 0, 0],
+            [40, 128, 200],
+        ] {
+            let pixel = [bgr[0], bgr[1], bgr[2], 0];
+            let out = convert(&pixel.repeat(4), 8, ChannelOrder::Bgrx, &layout);
+            let [y, u, v] = reference(bgr[0].into(), bgr[1].into(), bgr[2].into());
+            for (got, want) in [(out[0], y), (out[8], u), (out[12], v)] {
+                assert!(
+                    (f64::from(got) - want.clamp(0.0, 255.0)).abs() <= 1.0,
+                    "{bgr:?}: got {got}, want {want}"
+                );
+            }
+        }
+    }
+
+    /// `test_image(9, 5, _)` in I420, rows without padding. Checked
+    /// against `reference`: every sample is the nearest integer.
+    const GOLDEN_Y: [u8; 45] = [
+        54, 61, 69, 22, 29, 37, 98, 105, 113, //
+        64, 71, 78, 31, 38, 46, 107, 115, 122, //
+        19, 26, 33, 95, 102, 109, 62, 70, 77, //
+        28, 35, 42, 104, 111, 119, 72, 79, 86, //
+        91, 99, 106, 59, 66, 74, 135, 143, 150,
+    ];
+    const GOLDEN_U: [u8; 15] = [
+        103, 144, 185, 182, 202, //
+        122, 134, 146, 201, 221, //
+        85, 126, 167, 165, 185,
+    ];
+    const GOLDEN_V: [u8; 15] = [
+        250, 177, 104, 222, 215, //
+        111, 165, 220, 83, 76, //
+        230, 157, 84, 202, 195,
+    ];
+
+    /// Rows of plane `plane` in `out`, without their padding.
+    fn plane_rows(
+        out: &[u8],
+        layout: &YuvLayout,
+        plane: usize,
+        row_len: usize,
+        rows: usize,
+    ) -> Vec<u8> {
+        (0..rows)
+            .flat_map(|row| {
+                let start = layout.offsets[plane] + row * layout.strides[plane];
+                out[start..start + row_len].iter().copied()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn both_paths_match_golden_planes() {
+        let (width, height) = (9, 5);
+        let stride = width * 4 + 12;
+        let image = test_image(width, height, stride);
+        let chroma: Vec<u8> = GOLDEN_U
+            .iter()
+            .zip(&GOLDEN_V)
+            .flat_map(|(&u, &v)| [u, v])
+            .collect();
+
+        let i420 = YuvLayout::packed(YuvFormat::I420, 9, 5);
+        let nv12 = YuvLayout::packed(YuvFormat::Nv12, 9, 5);
+        for (path, i420_out, nv12_out) in [
+            (
+                "simd",
+                convert(&image, stride, ChannelOrder::Bgrx, &i420),
+                convert(&image, stride, ChannelOrder::Bgrx, &nv12),
+            ),
+            (
+                "scalar",
+                convert_scalar(&image, stride, &i420),
+                convert_scalar(&image, stride, &nv12),
+            ),
+        ] {
+            assert_eq!(
+                plane_rows(&i420_out, &i420, 0, 9, 5),
+                GOLDEN_Y,
+                "{path} I420 Y"
+            );
+            assert_eq!(
+                plane_rows(&i420_out, &i420, 1, 5, 3),
+                GOLDEN_U,
+                "{path} I420 U"
+            );
+            assert_eq!(
+                plane_rows(&i420_out, &i420, 2, 5, 3),
+                GOLDEN_V,
+                "{path} I420 V"
+            );
+            assert_eq!(
+                plane_rows(&nv12_out, &nv12, 0, 9, 5),
+                GOLDEN_Y,
+                "{path} NV12 Y"
+            );
+            assert_eq!(
+                plane_rows(&nv12_out, &nv12, 1, 10, 3),
+                chroma,
+                "{path} NV12 UV"
+            );
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar_golden() {
+        // Widths around the SIMD step, odd sizes and padded strides.
+        for (width, height) in [(1, 1), (3, 3), (4, 2), (9, 5), (16, 7), (33, 4)] {
+            let stride = width * 4 + 12;
+            let image = test_image(width, height, stride);
+            for format in [YuvFormat::I420, YuvFormat::Nv12] {
+                let layout = YuvLayout::packed(format, width as u32, height as u32);
+                let got = convert(&image, stride, ChannelOrder::Bgrx, &layout);
+                let want = convert_scalar(&image, stride, &layout);
+                assert_eq!(got, want, "{format:?} {width}x{height}");
+            }
+        }
+    }
+
+    /// `BGRx` test image of smooth gradients, which any chroma siting
+    /// subsamples alike.
+    fn gradient_image(width: usize, height: usize) -> Vec<u8> {
+        let ramp =
+            |v: usize, len: usize| u8::try_from(v * 255 / len.max(2).saturating_sub(1)).unwrap();
+        let mut image = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                image.extend_from_slice(&[
+                    ramp(x, width),
+                    ramp(y, height),
+                    ramp(x + y, width + height - 1),
+                    0,
+                ]);
+            }
+        }
+        image
+    }
+
+    /// Convert `image` (`BGRx`, rows `width * 4` bytes apart) to `layout`
+    /// with `GStreamer`'s `videoconvert`.
+    fn videoconvert(image: &[u8], layout: &YuvLayout) -> Vec<u8> {
+        use gstreamer as gst;
+        use gstreamer::prelude::*;
+        use gstreamer_app as gst_app;
+
+        gst::init().expect("GStreamer initializes");
+        assert!(
+            gst::ElementFactory::find("videoconvert").is_some(),
+            "videoconvert is installed"
+        );
+        let (width, height) = (layout.width, layout.height);
+        let format = layout.format.caps_name();
+        let description = format!(
+            "appsrc name=src caps=\"video/x-raw,format=BGRx,width={width},height={height},framerate=0/1\" \
+             ! videoconvert dither=none \
+             ! video/x-raw,format={format},colorimetry=1:3:5:1 \
+             ! appsink name=sink sync=false"
+        );
+        let pipeline = gst::parse::launch(&description)
+            .expect("videoconvert pipeline")
+            .downcast::<gst::Pipeline>()
+            .expect("pipeline");
+        let src = pipeline
+            .by_name("src")
+            .and_then(|e| e.downcast::<gst_app::AppSrc>().ok());
+        let sink = pipeline
+            .by_name("sink")
+            .and_then(|e| e.downcast::<gst_app::AppSink>().ok());
+        let (src, sink) = (src.expect("appsrc"), sink.expect("appsink"));
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .expect("pipeline starts");
+        src.push_buffer(gst::Buffer::from_slice(image.to_vec()))
+            .expect("push frame");
+        let sample = sink.pull_sample().expect("converted frame");
+        let _ = pipeline.set_state(gst::State::Null);
+        let map = sample
+            .buffer()
+            .expect("buffer")
+            .map_readable()
+            .expect("readable buffer");
+        map.as_slice().to_vec()
+    }
+
+    /// Largest difference between the samples of plane `plane` in `a` and
+    /// `b`, both laid out as `layout` (row padding is ignored).
+    fn plane_difference(a: &[u8], b: &[u8], layout: &YuvLayout, plane: usize) -> u8 {
+        let (width, height) = (layout.width as usize, layout.height as usize);
+        let (row_len, rows) = match (plane, layout.format) {
+            (0, _) => (width, height),
+            (_, YuvFormat::I420) => (width.div_ceil(2), height.div_ceil(2)),
+            (_, YuvFormat::Nv12) => (width.div_ceil(2) * 2, height.div_ceil(2)),
+        };
+        (0..rows)
+            .flat_map(|row| {
+                let start = layout.offsets[plane] + row * layout.strides[plane];
+                a[start..start + row_len]
+                    .iter()
+                    .zip(&b[start..start + row_len])
+            })
+            .map(|(x, y)| x.abs_diff(*y))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    #[ignore = "needs GStreamer with videoconvert; run with --ignored"]
+    #[allow(clippy::cast_possible_truncation)]
+    fn matches_gstreamer_videoconvert() {
+        for (width, height) in [(64, 48), (37, 21)] {
+            let image = gradient_image(width, height);
+            for format in [YuvFormat::I420, YuvFormat::Nv12] {
+                let layout = YuvLayout::packed(format, width as u32, height as u32);
+                let want = videoconvert(&image, &layout);
+                assert_eq!(
+                    want.len(),
+                    layout.size,
+                    "{format:?} {width}x{height} layout"
+                );
+                let got = convert(&image, width * 4, ChannelOrder::Bgrx, &layout);
+                // Rounding differs by a step; chroma siting and filtering
+                // by a few more on the gradients.
+                for (plane, tolerance) in [(0, 2), (1, 4), (2, 4)].into_iter().take(layout.planes())
+                {
+                    let difference = plane_difference(&got, &want, &layout, plane);
+                    assert!(
+                        difference <= tolerance,
+                        "{format:?} {width}x{height} plane {plane}: off by {difference}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nv12_copies_into_either_layout() {
+        let (width, height) = (10, 4);
+        let image = test_image(width, height, width * 4);
+        let nv12 = YuvLayout::packed(YuvFormat::Nv12, 10, 4);
+        let i420 = YuvLayout::packed(YuvFormat::I420, 10, 4);
+        // Both planes of the packed NV12 layout are 12 bytes per row.
+        let captured = convert(&image, width * 4, ChannelOrder::Bgrx, &nv12);
+
+        for layout in [nv12, i420] {
+            let mut out = vec![0; layout.size];
+            nv12_to_yuv(&captured, 12, &layout, &mut out);
+            assert_eq!(out, convert(&image, width * 4, ChannelOrder::Bgrx, &layout));
+        }
+    }
+
+    #[test]
+    fn nv12_to_bgra_inverts_the_conversion() {
+        let layout = YuvLayout::packed(YuvFormat::Nv12, 2, 2);
+        for bgr in [[0, 0, 0], [255, 255, 255], [40, 128, 200], [200, 30, 90]] {
+            let pixel = [bgr[0], bgr[1], bgr[2], 0];
+            let nv12 = convert(&pixel.repeat(4), 8, ChannelOrder::Bgrx, &layout);
+            let mut out = [0; 16];
+            nv12_to_bgra(&nv12, 4, 2, 2, &mut out, 8);
+            for (got, want) in out[..4].iter().zip([bgr[0], bgr[1], bgr[2], 0xFF]) {
+                assert!(got.abs_diff(want) <= 2, "{bgr:?}: got {out:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn rgb_order_swaps_red_and_blue() {
+        let (width, height) = (10, 4);
+        let image = test_image(width, height, width * 4);
+        let swapped: Vec<u8> = image
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0], p[3]])
+            .collect();
+        #[allow(clippy::cast_possible_truncation)]
+        let layout = YuvLayout::packed(YuvFormat::Nv12, width as u32, height as u32);
+        assert_eq!(
+            convert(&swapped, width * 4, ChannelOrder::Rgbx, &layout),
+            convert(&image, width * 4, ChannelOrder::Bgrx, &layout)
+        );
+    }
+}
@@ -1,6 +1,12 @@
 //! `GStreamer` H.264 encoding pipeline.
 //!
-//! Pipeline: `appsrc ! videoconvert ! capsfilter(I420,BT.709-full) ! encoder ! h264parse ! appsink`
+//! Pipeline: `appsrc ! [videoscale !] capsfilter(I420|NV12,BT.709-full) ! encoder ! h264parse ! appsink`
+//!
+//! Frames are converted to YUV on the CPU by [`crate::convert`] before
+//! they are pushed, in the format the encoder takes natively (see
+//! [`EncoderType::input_format`]), so no `videoconvert` is needed.
+//! `videoscale` is only inserted when [`EncoderConfig::scale_to`] asks for
+//! an output size different from the captured one.
 //!
 //! Supports hardware-accelerated encoding via VAAPI (Intel/AMD) and
 //! NVENC (NVIDIA), with automatic fallback to x264 software encoding.
@@ -10,6 +16,7 @@ use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
 use gstreamer_video as gst_video;
 
+use crate::convert::{self, ChannelOrder, YuvFormat, YuvLayout};
 use crate::{EncodeError, EncodedFrame, EncoderConfig};
 
 /// Hardware encoder backend.
@@ -33,6 +40,16 @@ impl EncoderType {
             Self::Software => "x264enc",
         }
     }
+
+    /// YUV format fed to this encoder: NV12 for the hardware encoders,
+    /// which use it internally, and I420 for x264.
+    #[must_use]
+    pub fn input_format(self) -> YuvFormat {
+        match self {
+            Self::Vaapi | Self::Nvenc => YuvFormat::Nv12,
+            Self::Software => YuvFormat::I420,
+        }
+    }
 }
 
 impl std::fmt::Display for EncoderType {
@@ -73,7 +90,7 @@ pub fn detect_best_encoder() -> EncoderType {
 /// H.264 encoder using a `GStreamer` pipeline.
 ///
 /// Creates and manages the pipeline:
-/// `appsrc ! videoconvert ! capsfilter(I420,BT.601 limited) ! encoder ! h264parse ! appsink`
+/// `appsrc ! [videoscale !] capsfilter(I420|NV12,BT.709-full) ! encoder ! h264parse ! appsink`
 ///
 /// Push raw BGRx/BGRA frames via [`encode_frame`](GstEncoder::encode_frame)
 /// and receive H.264 NAL units in byte-stream format.
@@ -82,6 +99,8 @@ pub struct GstEncoder {
     appsrc: gst_app::AppSrc,
     appsink: gst_app::AppSink,
     encoder_type: EncoderType,
+    /// Input frame size and the layout of its converted planes.
+    layout: YuvLayout,
     running: bool,
     /// Log negotiated caps once after first successful buffer push.
     caps_logged: bool,
@@ -110,6 +129,7 @@ impl GstEncoder {
             appsrc,
             appsink,
             encoder_type,
+            layout: YuvLayout::packed(encoder_type.input_format(), config.width, config.height),
             running: false,
             caps_logged: false,
         })
@@ -150,32 +170,91 @@ impl GstEncoder {
         self.running
     }
 
-    /// Encode a raw BGRA frame.
+    /// Encode a raw BGRA frame whose rows start every `stride` bytes.
     ///
-    /// Pushes the frame into the `GStreamer` pipeline and attempts to
-    /// pull an encoded H.264 frame. The pipeline starts automatically
-    /// on the first call.
+    /// Converts the frame to YUV (see [`crate::convert`]), pushes it into
+    /// the `GStreamer` pipeline and attempts to pull an encoded H.264
+    /// frame. The pipeline starts automatically on the first call.
     ///
     /// Returns `Ok(None)` if no encoded frame is available yet (the
     /// encoder may buffer a few frames before producing output).
     ///
     /// # Errors
     ///
-    /// Returns [`EncodeError`] if pushing the frame or pulling the
-    /// result fails.
-    pub fn encode_frame(&mut self, frame_data: &[u8]) -> Result<Option<EncodedFrame>, EncodeError> {
+    /// Returns [`EncodeError`] if the frame does not match the configured
+    /// size, or pushing the frame or pulling the result fails.
+    pub fn encode_frame(
+        &mut self,
+        frame_data: &[u8],
+        stride: usize,
+    ) -> Result<Option<EncodedFrame>, EncodeError> {
+        let (width, height) = (self.layout.width, self.layout.height);
+        let row_len = width as usize * 4;
+        let needed = stride * (height as usize).saturating_sub(1) + row_len;
+        if stride < row_len || frame_data.len() < needed {
+            return Err(EncodeError::FrameSize {
+                width,
+                height,
+                stride,
+                len: frame_data.len(),
+            });
+        }
+
+        self.encode_with(|layout, dst| {
+            convert::rgb_to_yuv(frame_data, stride, ChannelOrder::Bgrx, layout, dst);
+        })
+    }
+
+    /// Encode an NV12 frame: `height` luma rows followed by the
+    /// interleaved chroma rows, all `stride` bytes apart.
+    ///
+    /// The planes are copied into the pipeline's input format as they are,
+    /// without going through RGB. Otherwise as [`encode_frame`](Self::encode_frame).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncodeError`] if the frame does not match the configured
+    /// size, or pushing the frame or pulling the result fails.
+    pub fn encode_nv12_frame(
+        &mut self,
+        frame_data: &[u8],
+        stride: usize,
+    ) -> Result<Option<EncodedFrame>, EncodeError> {
+        let (width, height) = (self.layout.width, self.layout.height);
+        let row_len = 2 * width.div_ceil(2) as usize;
+        let rows = height as usize + height.div_ceil(2) as usize;
+        let needed = stride * rows.saturating_sub(1) + row_len;
+        if stride < row_len || frame_data.len() < needed {
+            return Err(EncodeError::FrameSize {
+                width,
+                height,
+                stride,
+                len: frame_data.len(),
+            });
+        }
+
+        self.encode_with(|layout, dst| convert::nv12_to_yuv(frame_data, stride, layout, dst))
+    }
+
+    /// Have `fill` write a frame into a new buffer laid out as the
+    /// pipeline expects, push it and try to pull an encoded frame.
+    fn encode_with(
+        &mut self,
+        fill: impl FnOnce(&YuvLayout, &mut [u8]),
+    ) -> Result<Option<EncodedFrame>, EncodeError> {
         if !self.running {
             self.start()?;
         }
 
-        // Create a GStreamer buffer from the frame data
-        let mut buffer = gst::Buffer::with_size(frame_data.len())
+        // Convert straight into the buffer handed to the pipeline.
+        let mut buffer = gst::Buffer::with_size(self.layout.size)
             .map_err(|e| EncodeError::PushBuffer(e.to_string()))?;
-
         {
             let buffer_ref = buffer.get_mut().ok_or(EncodeError::BufferMap)?;
-            let mut map = buffer_ref.map_writable().map_err(|_| EncodeError::BufferMap)?;
-            map.copy_from_slice(frame_data);
+            let mut map = buffer_ref
+                .map_writable()
+                .map_err(|_| EncodeError::BufferMap)?;
+            fill(&self.layout, &mut map);
         }
 
         // Push into the pipeline
@@ -188,22 +267,10 @@ impl GstEncoder {
         // encoded frame is pulled, so only mark as logged when we
         // actually see caps (retry on subsequent frames otherwise).
         if !self.caps_logged {
-            if let Some(convert) = self.pipeline.by_name("convert") {
-                let sink_caps = convert.static_pad("sink").and_then(|p| p.current_caps());
-                let src_caps = convert.static_pad("src").and_then(|p| p.current_caps());
-                if sink_caps.is_some() || src_caps.is_some() {
+            if let Some(enc) = self.pipeline.by_name("encoder") {
+                if let Some(caps) = enc.static_pad("sink").and_then(|p| p.current_caps()) {
                     self.caps_logged = true;
-                    if let Some(caps) = sink_caps {
-                        tracing::info!(%caps, "videoconvert input caps (negotiated)");
-                    }
-                    if let Some(caps) = src_caps {
-                        tracing::info!(%caps, "videoconvert output caps (negotiated)");
-                    }
-                    if let Some(enc) = self.pipeline.by_name("encoder") {
-                        if let Some(caps) = enc.static_pad("sink").and_then(|p| p.current_caps()) {
-                            tracing::info!(%caps, "encoder input caps (negotiated)");
-                        }
-                    }
+                    tracing::info!(%caps, "encoder input caps (negotiated)");
                 }
             }
         }
@@ -275,7 +342,7 @@ impl Drop for GstEncoder {
 
 /// Build the `GStreamer` encoding pipeline.
 ///
-/// `appsrc ! videoconvert ! capsfilter(I420,BT.709-full) ! encoder ! h264parse ! appsink`
+/// `appsrc ! [videoscale !] capsfilter(I420|NV12,BT.709-full) ! encoder ! h264parse ! appsink`
 fn build_pipeline(
     config: &EncoderConfig,
     encoder_type: EncoderType,
@@ -286,22 +353,22 @@ fn build_pipeline(
     let height = config.height as i32;
     #[allow(clippy::cast_possible_wrap)]
     let framerate = config.framerate as i32;
+    let format = encoder_type.input_format().caps_name();
 
     let pipeline = gst::Pipeline::new();
 
-    // AppSrc: raw video input from PipeWire (BGRx = memory [B, G, R, x]).
+    // AppSrc: YUV frames converted by `convert::rgb_to_yuv`.
     //
-    // CRITICAL: Explicitly set colorimetry to full-range BT.709.
-    // Without this, GStreamer defaults BGRx at HD resolution to LIMITED
-    // range (bt709 = 2:3:5:1), but PipeWire desktop content is full-range
-    // RGB (0-255).  The mismatch causes videoconvert to apply an incorrect
-    // range conversion, producing wrong colors in the H.264 output.
+    // CRITICAL: Explicitly set colorimetry to full-range BT.709, which is
+    // what the conversion produces. Without this, GStreamer defaults YUV
+    // at HD resolution to LIMITED range (bt709 = 2:3:5:1) and the encoder
+    // would signal the wrong range to the client.
     //   1:3:5:1 = full-range : BT709 matrix : BT709 transfer : BT709 primaries
     let appsrc = gst_app::AppSrc::builder()
         .name("source")
         .caps(
             &gst::Caps::builder("video/x-raw")
-                .field("format", "BGRx")
+                .field("format", format)
                 .field("width", width)
                 .field("height", height)
                 .field("framerate", gst::Fraction::new(framerate, 1))
@@ -313,24 +380,43 @@ fn build_pipeline(
         .do_timestamp(true)
         .build();
 
-    // videoconvert: RGB→YUV color space conversion.
-    let videoconvert = make_element("videoconvert", "convert")?;
-
-    // Capsfilter: force I420 with full-range BT.709 colorimetry.
+    // Capsfilter: keep the input format with full-range BT.709 colorimetry.
     //
     // FreeRDP's prim_YUV.c uses BT.709 full-range coefficients for
     // AVC420 H.264 decode (256*Y with no Y-16 offset, V→R=403/256=1.574,
     // U→B=475/256=1.856 — matching BT.709 exactly).
-    // Both AppSrc and capsfilter use 1:3:5:1 so videoconvert performs a
-    // pure format change (BGRx→I420) with no range scaling.
     let capsfilter = make_element("capsfilter", "filter")?;
-    capsfilter.set_property(
-        "caps",
-        gst::Caps::builder("video/x-raw")
-            .field("format", "I420")
-            .field("colorimetry", "1:3:5:1")
-            .build(),
-    );
+    let mut filter_caps = gst::Caps::builder("video/x-raw")
+        .field("format", format)
+        .field("colorimetry", "1:3:5:1");
+
+    // videoscale: fit the frame into the client's desktop size. With
+    // `add-borders` the aspect ratio is kept and the remainder is filled
+    // with black, matching `scale::Letterbox` used for input mapping.
+    let scale_to = config
+        .scale_to
+        .filter(|&size| size != (config.width, config.height));
+    let videoscale = if let Some((out_width, out_height)) = scale_to {
+        let videoscale = make_element("videoscale", "scale")?;
+        videoscale.set_property("add-borders", true);
+        #[allow(clippy::cast_possible_wrap)]
+        let (caps_width, caps_height) = (out_width as i32, out_height as i32);
+        filter_caps = filter_caps
+            .field("width", caps_width)
+            .field("height", caps_height)
+            .field("pixel-aspect-ratio", gst::Fraction::new(1, 1));
+        tracing::info!(
+            from_width = config.width,
+            from_height = config.height,
+            to_width = out_width,
+            to_height = out_height,
+            "Scaling encoder output to client desktop size"
+        );
+        Some(videoscale)
+    } else {
+        None
+    };
+    capsfilter.set_property("caps", filter_caps.build());
 
     // H.264 encoder: hardware or software
     let encoder = make_element(encoder_type.element_name(), "encoder")?;
@@ -350,30 +436,21 @@ fn build_pipeline(
         )
         .build();
 
-    // Pipeline: appsrc(BGRx) ! videoconvert ! capsfilter(I420 BT.709-full) ! encoder ! h264parse ! appsink
+    // Pipeline: appsrc(YUV) ! [videoscale !] capsfilter(YUV BT.709-full) ! encoder ! h264parse ! appsink
+    let mut elements: Vec<&gst::Element> = vec![appsrc.upcast_ref()];
+    elements.extend(videoscale.as_ref());
+    elements.extend([&capsfilter, &encoder, &h264parse, appsink.upcast_ref()]);
+
     pipeline
-        .add_many([
-            appsrc.upcast_ref(),
-            &videoconvert,
-            &capsfilter,
-            &encoder,
-            &h264parse,
-            appsink.upcast_ref(),
-        ])
+        .add_many(elements.iter().copied())
         .map_err(|e| EncodeError::PipelineLink(e.to_string()))?;
 
-    gst::Element::link_many([
-        appsrc.upcast_ref(),
-        &videoconvert,
-        &capsfilter,
-        &encoder,
-        &h264parse,
-        appsink.upcast_ref(),
-    ])
-    .map_err(|e| EncodeError::PipelineLink(e.to_string()))?;
+    gst::Element::link_many(elements.iter().copied())
+        .map_err(|e| EncodeError::PipelineLink(e.to_string()))?;
 
     tracing::info!(
         %encoder_type,
+        format,
         width = config.width,
         height = config.height,
         bitrate = config.bitrate,
@@ -8,13 +8,24 @@
 //! or used for server-side frame processing.
 //!
 //! - [`gstreamer_enc`]: H.264 encoding via `GStreamer` pipeline
+//! - [`audio`]: AAC/Opus audio encoding for RDPSND
 //! - [`bitmap`]: Raw bitmap pass-through (no encoding)
+//! - [`scale`]: Letterboxed scaling to the client's desktop size
+//! - [`convert`]: Single-pass RGB to YUV conversion for the encoder
 
+pub mod audio;
 pub mod bitmap;
+pub mod convert;
 pub mod gstreamer_enc;
+pub mod scale;
 
+pub use audio::{
+    aac_audio_specific_config, AudioCodec, AudioEncoderConfig, EncodedAudio, GstAudioEncoder,
+};
 pub use bitmap::BitmapEncoder;
+pub use convert::{nv12_to_bgra, nv12_to_yuv, rgb_to_yuv, ChannelOrder, YuvFormat, YuvLayout};
 pub use gstreamer_enc::{EncoderType, GstEncoder};
+pub use scale::{compose_bgra, scale_bgra, Letterbox, Region};
 
 /// Configuration for the video encoder.
 #[derive(Debug, Clone)]
@@ -33,6 +44,11 @@ pub struct EncoderConfig {
     pub low_latency: bool,
     /// Keyframe interval in frames (GOP size).
     pub keyframe_interval: u32,
+    /// Output size, when it differs from the input (`width` x `height`).
+    ///
+    /// Frames are scaled to fit with their aspect ratio preserved and
+    /// black borders filling the rest. `None` encodes at the input size.
+    pub scale_to: Option<(u32, u32)>,
 }
 
 impl Default for EncoderConfig {
@@ -45,6 +61,7 @@ impl Default for EncoderConfig {
             encoder_type: None, // auto-detect
             low_latency: true,
             keyframe_interval: 30,
+            scale_to: None,
         }
     }
 }
@@ -93,4 +110,17 @@ pub enum EncodeError {
     /// Failed to map a `GStreamer` buffer.
     #[error("failed to map GStreamer buffer")]
     BufferMap,
+
+    /// A frame does not hold the configured size at its stride.
+    #[error("frame of {len} bytes with stride {stride} does not fit {width}x{height}")]
+    FrameSize {
+        /// Configured width in pixels.
+        width: u32,
+        /// Configured height in pixels.
+        height: u32,
+        /// Row stride in bytes.
+        stride: usize,
+        /// Frame length in bytes.
+        len: usize,
+    },
 }
@@ -0,0 +1,365 @@
+//! Aspect-preserving scaling of captured frames to the client's desktop.
+//!
+//! The capture resolution follows the compositor, the desktop size
+//! follows the client, and the two drift apart on resize. Frames are
+//! scaled to fit the desktop and centred, with black bars filling the
+//! rest ("letterboxing"). [`Letterbox`] holds the geometry so pointer
+//! coordinates can be mapped back to the capture.
+//!
+//! The EGFX path scales inside the `GStreamer` pipeline (see
+//! [`EncoderConfig::scale_to`](crate::EncoderConfig::scale_to));
+//! [`scale_bgra`] is the CPU equivalent for bitmap delivery.
+//!
+//! With several monitors on either side, each captured monitor is
+//! letterboxed into its own client monitor: a [`Region`] places one part
+//! of the capture on the desktop, and [`compose_bgra`] draws a set of them.
+
+/// Placement of a scaled source image inside a destination surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Letterbox {
+    /// Source (capture) width.
+    pub src_width: u32,
+    /// Source (capture) height.
+    pub src_height: u32,
+    /// Destination (client desktop) width.
+    pub dst_width: u32,
+    /// Destination (client desktop) height.
+    pub dst_height: u32,
+    /// Left edge of the image inside the destination.
+    pub x: u32,
+    /// Top edge of the image inside the destination.
+    pub y: u32,
+    /// Width of the scaled image.
+    pub width: u32,
+    /// Height of the scaled image.
+    pub height: u32,
+}
+
+impl Letterbox {
+    /// Fit a `src_width` x `src_height` image into the destination,
+    /// preserving its aspect ratio and centring it.
+    #[must_use]
+    pub fn fit(src_width: u32, src_height: u32, dst_width: u32, dst_height: u32) -> Self {
+        let (width, height) = if src_width == 0 || src_height == 0 {
+            (dst_width, dst_height)
+        } else if u64::from(dst_width) * u64::from(src_height)
+            <= u64::from(dst_height) * u64::from(src_width)
+        {
+            // Width-limited: bars above and below.
+            let height = u64::from(dst_width) * u64::from(src_height) / u64::from(src_width);
+            (dst_width, u32::try_from(height).unwrap_or(dst_height).max(1))
+        } else {
+            // Height-limited: bars left and right.
+            let width = u64::from(dst_height) * u64::from(src_width) / u64::from(src_height);
+            (u32::try_from(width).unwrap_or(dst_width).max(1), dst_height)
+        };
+
+        Self {
+            src_width,
+            src_height,
+            dst_width,
+            dst_height,
+            x: (dst_width - width.min(dst_width)) / 2,
+            y: (dst_height - height.min(dst_height)) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Stretch a `src_width` x `src_height` image over the whole
+    /// destination, ignoring its aspect ratio.
+    #[must_use]
+    pub fn stretch(src_width: u32, src_height: u32, dst_width: u32, dst_height: u32) -> Self {
+        Self {
+            src_width,
+            src_height,
+            dst_width,
+            dst_height,
+            x: 0,
+            y: 0,
+            width: dst_width,
+            height: dst_height,
+        }
+    }
+
+    /// Whether source and destination are the same size (no scaling).
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        self.src_width == self.dst_width && self.src_height == self.dst_height
+    }
+
+    /// Map a destination point (e.g. a client pointer position) to the
+    /// source image, clamping points on the bars to the nearest edge.
+    #[must_use]
+    pub fn to_source(&self, x: u32, y: u32) -> (u32, u32) {
+        (
+            map_axis(x, self.x, self.width, self.src_width),
+            map_axis(y, self.y, self.height, self.src_height),
+        )
+    }
+
+    /// Map a source point (e.g. the captured cursor position) to the
+    /// destination surface.
+    #[must_use]
+    pub fn to_dest(&self, x: u32, y: u32) -> (u32, u32) {
+        (
+            self.x + map_axis(x, 0, self.src_width, self.width),
+            self.y + map_axis(y, 0, self.src_height, self.height),
+        )
+    }
+}
+
+/// A part of the source image letterboxed into a part of the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Left edge of the source rectangle.
+    pub src_x: u32,
+    /// Top edge of the source rectangle.
+    pub src_y: u32,
+    /// Left edge of the destination rectangle.
+    pub dst_x: u32,
+    /// Top edge of the destination rectangle.
+    pub dst_y: u32,
+    /// Placement of the source rectangle inside the destination rectangle,
+    /// relative to their origins.
+    pub letterbox: Letterbox,
+}
+
+impl Region {
+    /// Fit the source rectangle at (`src_x`, `src_y`) into the destination
+    /// rectangle at (`dst_x`, `dst_y`); sizes are taken from `letterbox`.
+    #[must_use]
+    pub fn new(src_x: u32, src_y: u32, dst_x: u32, dst_y: u32, letterbox: Letterbox) -> Self {
+        Self {
+            src_x,
+            src_y,
+            dst_x,
+            dst_y,
+            letterbox,
+        }
+    }
+
+    /// Whether the destination point lies in this region (bars included).
+    #[must_use]
+    pub fn contains_dest(&self, x: u32, y: u32) -> bool {
+        contains(x, y, self.dst_x, self.dst_y, self.letterbox.dst_width, self.letterbox.dst_height)
+    }
+
+    /// Whether the source point lies in this region's source rectangle.
+    #[must_use]
+    pub fn contains_source(&self, x: u32, y: u32) -> bool {
+        contains(x, y, self.src_x, self.src_y, self.letterbox.src_width, self.letterbox.src_height)
+    }
+
+    /// Map a destination point to the source image (see
+    /// [`Letterbox::to_source`]).
+    #[must_use]
+    pub fn to_source(&self, x: u32, y: u32) -> (u32, u32) {
+        let (x, y) = self
+            .letterbox
+            .to_source(x.saturating_sub(self.dst_x), y.saturating_sub(self.dst_y));
+        (self.src_x + x, self.src_y + y)
+    }
+
+    /// Map a source point to the destination (see [`Letterbox::to_dest`]).
+    #[must_use]
+    pub fn to_dest(&self, x: u32, y: u32) -> (u32, u32) {
+        let (x, y) = self
+            .letterbox
+            .to_dest(x.saturating_sub(self.src_x), y.saturating_sub(self.src_y));
+        (self.dst_x + x, self.dst_y + y)
+    }
+}
+
+fn contains(x: u32, y: u32, left: u32, top: u32, width: u32, height: u32) -> bool {
+    (left..left.saturating_add(width)).contains(&x) && (top..top.saturating_add(height)).contains(&y)
+}
+
+/// Map `v` from the span `[offset, offset + from_len)` onto `[0, to_len)`.
+fn map_axis(v: u32, offset: u32, from_len: u32, to_len: u32) -> u32 {
+    if from_len == 0 || to_len == 0 {
+        return 0;
+    }
+    let v = v.saturating_sub(offset).min(from_len - 1);
+    let mapped = u64::from(v) * u64::from(to_len) / u64::from(from_len);
+    u32::try_from(mapped).unwrap_or(to_len - 1)
+}
+
+/// Scale a BGRA frame into a letterboxed `dst_width` x `dst_height` frame.
+///
+/// Uses nearest-neighbour sampling; bars are opaque black. Returns a
+/// tightly packed buffer (stride = `dst_width * 4`).
+#[must_use]
+pub fn scale_bgra(src: &[u8], src_stride: usize, letterbox: &Letterbox) -> Vec<u8> {
+    compose_bgra(
+        src,
+        src_stride,
+        &[Region::new(0, 0, 0, 0, *letterbox)],
+        letterbox.dst_width,
+        letterbox.dst_height,
+    )
+}
+
+/// Draw `regions` of a BGRA frame into a `dst_width` x `dst_height` frame.
+///
+/// Each region is scaled as in [`scale_bgra`]; anything outside the
+/// scaled images is opaque black. Returns a tightly packed buffer.
+#[must_use]
+pub fn compose_bgra(
+    src: &[u8],
+    src_stride: usize,
+    regions: &[Region],
+    dst_width: u32,
+    dst_height: u32,
+) -> Vec<u8> {
+    const BLACK: [u8; 4] = [0, 0, 0, 0xFF];
+
+    let mut dst = BLACK.repeat(dst_width as usize * dst_height as usize);
+
+    for region in regions {
+        blit_scaled(src, src_stride, region, &mut dst, dst_width, dst_height);
+    }
+
+    dst
+}
+
+/// Scale one region into `dst`, clipping it to the destination.
+fn blit_scaled(
+    src: &[u8],
+    src_stride: usize,
+    region: &Region,
+    dst: &mut [u8],
+    dst_width: u32,
+    dst_height: u32,
+) {
+    let letterbox = &region.letterbox;
+    let left = region.dst_x + letterbox.x;
+    let top = region.dst_y + letterbox.y;
+    let width = letterbox.width.min(dst_width.saturating_sub(left));
+    let height = letterbox.height.min(dst_height.saturating_sub(top));
+    if width == 0 || height == 0 {
+        return;
+    }
+    let dst_stride = dst_width as usize * 4;
+
+    // Precompute the source byte offset for each output column.
+    let columns: Vec<usize> = (0..width)
+        .map(|x| {
+            (region.src_x + map_axis(x, 0, letterbox.width, letterbox.src_width)) as usize * 4
+        })
+        .collect();
+
+    for y in 0..height {
+        let src_y = (region.src_y + map_axis(y, 0, letterbox.height, letterbox.src_height)) as usize;
+        let Some(src_row) = src.get(src_y * src_stride..) else {
+            break;
+        };
+        let dst_start = (top + y) as usize * dst_stride + left as usize * 4;
+        let dst_row = &mut dst[dst_start..dst_start + width as usize * 4];
+
+        for (dst_px, &src_off) in dst_row.chunks_exact_mut(4).zip(&columns) {
+            if let Some(px) = src_row.get(src_off..src_off + 4) {
+                dst_px.copy_from_slice(px);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_aspect_fills_destination() {
+        let lb = Letterbox::fit(3840, 2160, 1920, 1080);
+        assert_eq!((lb.x, lb.y, lb.width, lb.height), (0, 0, 1920, 1080));
+        assert!(!lb.is_identity());
+        assert!(Letterbox::fit(800, 600, 800, 600).is_identity());
+    }
+
+    #[test]
+    fn wider_destination_gets_pillarbox() {
+        // 4:3 into 16:9: bars on the left and right.
+        let lb = Letterbox::fit(1024, 768, 1920, 1080);
+        assert_eq!((lb.x, lb.y, lb.width, lb.height), (240, 0, 1440, 1080));
+    }
+
+    #[test]
+    fn taller_destination_gets_letterbox() {
+        // 16:9 into 4:3: bars above and below.
+        let lb = Letterbox::fit(1920, 1080, 1024, 768);
+        assert_eq!((lb.x, lb.y, lb.width, lb.height), (0, 96, 1024, 576));
+    }
+
+    #[test]
+    fn coordinates_round_trip() {
+        let lb = Letterbox::fit(1024, 768, 1920, 1080);
+        assert_eq!(lb.to_source(240, 0), (0, 0));
+        assert_eq!(lb.to_source(1679, 1079), (1023, 767));
+        assert_eq!(lb.to_source(960, 540), (512, 384));
+        // Points on the bars clamp to the image edge.
+        assert_eq!(lb.to_source(0, 500).0, 0);
+        assert_eq!(lb.to_source(1919, 500).0, 1023);
+        assert_eq!(lb.to_dest(512, 384), (960, 540));
+    }
+
+    #[test]
+    fn scale_bgra_letterboxes_with_black_bars() {
+        // 2x1 source (red, green) into a 2x2 destination.
+        let src = [0, 0, 0xFF, 0xFF, 0, 0xFF, 0, 0xFF];
+        let lb = Letterbox::fit(2, 1, 2, 2);
+        assert_eq!((lb.x, lb.y, lb.width, lb.height), (0, 0, 2, 1));
+        let dst = scale_bgra(&src, 8, &lb);
+        assert_eq!(dst.len(), 2 * 2 * 4);
+        assert_eq!(&dst[..8], &src);
+        assert_eq!(&dst[8..], &[0, 0, 0, 0xFF, 0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn scale_bgra_stretches_without_bars() {
+        // 1x2 source (blue, transparent) stretched to 2x2.
+        let src = [0xFF, 0, 0, 0xFF, 0, 0, 0, 0];
+        let lb = Letterbox::stretch(1, 2, 2, 2);
+        let dst = scale_bgra(&src, 4, &lb);
+        assert_eq!(&dst[..8], &[0xFF, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF]);
+        assert_eq!(&dst[8..], &[0; 8]);
+    }
+
+    #[test]
+    fn scale_bgra_downscales() {
+        // 4x4 source with a distinct value per pixel, into 2x2.
+        let src: Vec<u8> = (0..16u8).flat_map(|i| [i, i, i, 0xFF]).collect();
+        let lb = Letterbox::fit(4, 4, 2, 2);
+        let dst = scale_bgra(&src, 16, &lb);
+        let values: Vec<u8> = dst.chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(values, vec![0, 2, 8, 10]);
+    }
+
+    #[test]
+    fn regions_map_between_monitors() {
+        // Two 1920x1080 captured monitors side by side, shown on a 1280x1024
+        // client monitor stacked above a 1920x1080 one.
+        let left = Region::new(0, 0, 0, 0, Letterbox::fit(1920, 1080, 1280, 1024));
+        let right = Region::new(1920, 0, 0, 1024, Letterbox::fit(1920, 1080, 1920, 1080));
+        assert!(left.contains_dest(640, 1023) && !left.contains_dest(640, 1024));
+        assert!(right.contains_source(1920, 0) && !right.contains_source(1919, 0));
+        assert_eq!(right.to_source(0, 1024), (1920, 0));
+        assert_eq!(right.to_dest(3839, 1079), (1919, 2103));
+        // 16:9 into 5:4 leaves bars above and below.
+        assert_eq!(left.to_dest(0, 0), (0, 152));
+        assert_eq!(left.to_source(0, 0), (0, 0));
+    }
+
+    #[test]
+    fn compose_bgra_places_regions() {
+        // 2x1 source (red, green); each pixel goes to its own row.
+        let src = [0, 0, 0xFF, 0xFF, 0, 0xFF, 0, 0xFF];
+        let regions = [
+            Region::new(1, 0, 0, 0, Letterbox::fit(1, 1, 2, 1)),
+            Region::new(0, 0, 0, 1, Letterbox::fit(1, 1, 1, 1)),
+        ];
+        let dst = compose_bgra(&src, 8, &regions, 2, 2);
+        assert_eq!(&dst[..8], &[0, 0xFF, 0, 0xFF, 0, 0, 0, 0xFF]);
+        assert_eq!(&dst[8..], &[0, 0, 0xFF, 0xFF, 0, 0, 0, 0xFF]);
+    }
+}